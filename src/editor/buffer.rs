@@ -0,0 +1,329 @@
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::fmt;
+use std::ops::Range;
+
+use ropey::Rope;
+
+/// A replacement applied to a `TextBuffer`, as byte offsets and `(line, byte column)` points, so
+/// that derived state such as syntax trees can be updated without rescanning the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferEdit {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+    pub start_point: (usize, usize),
+    pub old_end_point: (usize, usize),
+    pub new_end_point: (usize, usize),
+}
+
+/// Rope-backed text storage addressed by UTF-8 byte offsets.
+///
+/// Edits are O(log n). `text()` materializes a contiguous `String` lazily and caches it until the
+/// next edit, so callers that still need a `&str` only pay for it once per change. Anything run on
+/// every edit or frame reads lines and slices instead, which borrow from the rope.
+#[derive(Default)]
+pub struct TextBuffer {
+    rope: Rope,
+    flattened: OnceCell<String>,
+}
+
+impl TextBuffer {
+    pub fn len(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn text(&self) -> &str {
+        self.flattened.get_or_init(|| self.rope.to_string())
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.slice_cow(range).into_owned()
+    }
+
+    /// The text in `range`, borrowed when it lies within one of the rope's chunks.
+    pub fn slice_cow(&self, range: Range<usize>) -> Cow<'_, str> {
+        let range = self.clip_range(range);
+        self.rope.byte_slice(range).into()
+    }
+
+    /// The bytes from `offset` to the end of the rope chunk containing it, for readers that take
+    /// text in pieces; empty at the end of the buffer.
+    pub fn chunk_bytes_at(&self, offset: usize) -> &[u8] {
+        if offset >= self.len() {
+            return &[];
+        }
+        let (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(offset);
+        &chunk.as_bytes()[offset - chunk_start..]
+    }
+
+    /// The text of `line`, excluding its trailing newline.
+    pub fn line(&self, line: usize) -> Cow<'_, str> {
+        self.slice_cow(self.line_range(line))
+    }
+
+    /// Replaces `range` with `new_text`, widening the range outwards to char boundaries.
+    /// Returns the edit that was actually applied.
+    pub fn replace(&mut self, range: Range<usize>, new_text: &str) -> BufferEdit {
+        let range = self.clip_range(range);
+        let start_point = self.offset_to_point(range.start);
+        let old_end_point = self.offset_to_point(range.end);
+        let start_char = self.rope.byte_to_char(range.start);
+        let end_char = self.rope.byte_to_char(range.end);
+        if start_char < end_char {
+            self.rope.remove(start_char..end_char);
+        }
+        if !new_text.is_empty() {
+            self.rope.insert(start_char, new_text);
+        }
+        self.flattened = OnceCell::new();
+
+        let new_end = range.start + new_text.len();
+        BufferEdit {
+            start: range.start,
+            old_end: range.end,
+            new_end,
+            start_point,
+            old_end_point,
+            new_end_point: self.offset_to_point(new_end),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn line_for_offset(&self, offset: usize) -> usize {
+        self.rope.byte_to_line(offset.min(self.len()))
+    }
+
+    pub fn line_start(&self, line: usize) -> usize {
+        if line >= self.line_count() {
+            return self.len();
+        }
+        self.rope.line_to_byte(line)
+    }
+
+    /// Byte offset of the end of `line`, excluding its trailing newline.
+    pub fn line_end(&self, line: usize) -> usize {
+        if line + 1 >= self.line_count() {
+            return self.len();
+        }
+        self.rope.line_to_byte(line + 1) - 1
+    }
+
+    pub fn line_range(&self, line: usize) -> Range<usize> {
+        self.line_start(line)..self.line_end(line)
+    }
+
+    /// Converts a byte offset into a `(line, byte column)` pair.
+    pub fn offset_to_point(&self, offset: usize) -> (usize, usize) {
+        let offset = self.floor_char_boundary(offset);
+        let line = self.line_for_offset(offset);
+        (line, offset - self.line_start(line))
+    }
+
+    /// Converts a `(line, byte column)` pair into a byte offset, clamping to the line's end.
+    pub fn point_to_offset(&self, line: usize, column: usize) -> usize {
+        if line >= self.line_count() {
+            return self.len();
+        }
+        let range = self.line_range(line);
+        self.floor_char_boundary((range.start + column).min(range.end))
+    }
+
+    /// Offset of the first occurrence of `needle` lying entirely within `range`. Searches the
+    /// rope chunk by chunk, carrying over enough of each to find matches spanning two.
+    pub fn find(&self, needle: &str, range: Range<usize>) -> Option<usize> {
+        let range = self.clip_range(range);
+        if needle.is_empty() {
+            return Some(range.start);
+        }
+        let mut window = String::new();
+        let mut window_start = range.start;
+        for chunk in self.rope.byte_slice(range).chunks() {
+            window.push_str(chunk);
+            if let Some(index) = window.find(needle) {
+                return Some(window_start + index);
+            }
+            let mut keep_from = window.len().saturating_sub(needle.len() - 1);
+            while !window.is_char_boundary(keep_from) {
+                keep_from -= 1;
+            }
+            window.drain(..keep_from);
+            window_start += keep_from;
+        }
+        None
+    }
+
+    /// The number of UTF-16 code units before the byte `offset`.
+    pub fn offset_to_utf16(&self, offset: usize) -> usize {
+        let char_idx = self.rope.byte_to_char(self.floor_char_boundary(offset));
+        self.rope.char_to_utf16_cu(char_idx)
+    }
+
+    /// The byte offset of the char at `utf16_offset` UTF-16 code units, rounding up when it falls
+    /// inside a surrogate pair.
+    pub fn offset_from_utf16(&self, utf16_offset: usize) -> usize {
+        let utf16_offset = utf16_offset.min(self.rope.len_utf16_cu());
+        let offset = self
+            .rope
+            .char_to_byte(self.rope.utf16_cu_to_char(utf16_offset));
+        if self.offset_to_utf16(offset) < utf16_offset {
+            self.next_char_boundary(offset)
+        } else {
+            offset
+        }
+    }
+
+    pub fn floor_char_boundary(&self, offset: usize) -> usize {
+        if offset >= self.len() {
+            return self.len();
+        }
+        self.rope.char_to_byte(self.rope.byte_to_char(offset))
+    }
+
+    pub fn ceil_char_boundary(&self, offset: usize) -> usize {
+        let floor = self.floor_char_boundary(offset);
+        if floor == offset.min(self.len()) {
+            return floor;
+        }
+        self.next_char_boundary(floor)
+    }
+
+    pub fn prev_char_boundary(&self, offset: usize) -> usize {
+        let offset = self.floor_char_boundary(offset);
+        if offset == 0 {
+            return 0;
+        }
+        self.rope.char_to_byte(self.rope.byte_to_char(offset) - 1)
+    }
+
+    pub fn next_char_boundary(&self, offset: usize) -> usize {
+        let offset = self.floor_char_boundary(offset);
+        if offset >= self.len() {
+            return self.len();
+        }
+        self.rope.char_to_byte(self.rope.byte_to_char(offset) + 1)
+    }
+
+    /// Clamps `range` to the buffer and widens it outwards to char boundaries.
+    pub fn clip_range(&self, range: Range<usize>) -> Range<usize> {
+        let mut start = range.start.min(self.len());
+        let mut end = range.end.min(self.len());
+        if start > end {
+            (start, end) = (end, start);
+        }
+        self.floor_char_boundary(start)..self.ceil_char_boundary(end)
+    }
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            flattened: OnceCell::new(),
+        }
+    }
+}
+
+/// Clones share the rope's nodes, so cloning is O(1). The flattened text is not copied along.
+impl Clone for TextBuffer {
+    fn clone(&self) -> Self {
+        Self {
+            rope: self.rope.clone(),
+            flattened: OnceCell::new(),
+        }
+    }
+}
+
+impl PartialEq for TextBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.rope == other.rope
+    }
+}
+
+impl Eq for TextBuffer {}
+
+impl fmt::Debug for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TextBuffer").field(&self.rope).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextBuffer;
+
+    #[test]
+    fn replace_updates_text_and_cache() {
+        let mut buffer = TextBuffer::from("hello world");
+        assert_eq!(buffer.text(), "hello world");
+        buffer.replace(6..11, "rope");
+        assert_eq!(buffer.text(), "hello rope");
+        let edit = buffer.replace(5..6, "\n");
+        assert_eq!((edit.start, edit.old_end, edit.new_end), (5, 6, 6));
+        assert_eq!(edit.new_end_point, (1, 0));
+        buffer.replace(5..6, " ");
+        buffer.replace(0..0, "> ");
+        assert_eq!(buffer.text(), "> hello rope");
+        assert_eq!(buffer.len(), "> hello rope".len());
+    }
+
+    #[test]
+    fn converts_between_offsets_and_lines() {
+        let buffer = TextBuffer::from("ab\ncde\n\nf");
+        assert_eq!(buffer.line_count(), 4);
+        let line_starts = (0..4)
+            .map(|line| buffer.line_start(line))
+            .collect::<Vec<_>>();
+        assert_eq!(line_starts, [0, 3, 7, 8]);
+        assert_eq!(buffer.line_range(1), 3..6);
+        assert_eq!(buffer.line_range(2), 7..7);
+        assert_eq!(buffer.slice(buffer.line_range(3)), "f");
+        assert_eq!(buffer.line(1), "cde");
+        assert_eq!(buffer.offset_to_point(5), (1, 2));
+        assert_eq!(buffer.point_to_offset(1, 10), 6);
+        assert_eq!(buffer.point_to_offset(9, 0), buffer.len());
+    }
+
+    #[test]
+    fn char_boundary_helpers_respect_multibyte_chars() {
+        let buffer = TextBuffer::from("aé漢");
+        assert_eq!(buffer.next_char_boundary(0), 1);
+        assert_eq!(buffer.next_char_boundary(1), 3);
+        assert_eq!(buffer.next_char_boundary(3), 6);
+        assert_eq!(buffer.prev_char_boundary(6), 3);
+        assert_eq!(buffer.floor_char_boundary(2), 1);
+        assert_eq!(buffer.ceil_char_boundary(2), 3);
+        assert_eq!(buffer.clip_range(2..4), 1..6);
+    }
+
+    #[test]
+    fn finds_text_spanning_rope_chunks() {
+        let text = format!("{}needle{}needle", "a".repeat(5000), "b".repeat(5000));
+        let buffer = TextBuffer::from(text.as_str());
+        assert!(buffer.rope.chunks().count() > 2);
+        assert_eq!(buffer.find("needle", 0..buffer.len()), Some(5000));
+        assert_eq!(buffer.find("needle", 5001..buffer.len()), Some(10006));
+        assert_eq!(buffer.find("needle", 5001..10011), None);
+        assert_eq!(buffer.find("ab", 0..buffer.len()), None);
+        assert_eq!(buffer.find("ene", 0..buffer.len()), None);
+    }
+
+    #[test]
+    fn converts_between_byte_and_utf16_offsets() {
+        let buffer = TextBuffer::from("aé😀\nb");
+        assert_eq!(buffer.offset_to_utf16(3), 2);
+        assert_eq!(buffer.offset_to_utf16(7), 4);
+        assert_eq!(buffer.offset_to_utf16(buffer.len()), 6);
+        assert_eq!(buffer.offset_from_utf16(2), 3);
+        assert_eq!(buffer.offset_from_utf16(3), 7);
+        assert_eq!(buffer.offset_from_utf16(4), 7);
+        assert_eq!(buffer.offset_from_utf16(99), buffer.len());
+    }
+}
@@ -0,0 +1,205 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Line separator used by a file on disk. Buffers always hold `\n`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+/// How a file's text is laid out on disk, so that saving writes it back the way it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    /// Whether the last line is terminated. The terminator is not part of the buffer, so the
+    /// editor does not show an empty line after it.
+    pub trailing_newline: bool,
+}
+
+impl Default for FileFormat {
+    /// The format of new files.
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Splits text read from disk into buffer text and the format it was stored in. A file with
+    /// mixed line endings is taken to use whichever it has more of; a lone `\n` in a mostly CRLF
+    /// file becomes CRLF on save, and a `\r` before a `\n` in a mostly LF one stays in the text.
+    pub fn decode(raw: &str) -> (String, Self) {
+        let newlines = raw.matches('\n').count();
+        let crlfs = raw.matches("\r\n").count();
+        let line_ending = if crlfs > newlines - crlfs {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        };
+        let mut text = match line_ending {
+            LineEnding::Lf => raw.to_string(),
+            LineEnding::CrLf => raw.replace("\r\n", "\n"),
+        };
+        let trailing_newline = text.ends_with('\n');
+        if trailing_newline {
+            text.pop();
+        }
+        let format = Self {
+            line_ending,
+            trailing_newline,
+        };
+        (text, format)
+    }
+
+    /// The inverse of `decode`.
+    pub fn encode(&self, text: &str) -> String {
+        let mut raw = text.to_string();
+        if self.trailing_newline {
+            raw.push('\n');
+        }
+        match self.line_ending {
+            LineEnding::Lf => raw,
+            LineEnding::CrLf => raw.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// Reads a UTF-8 text file, returning its buffer text and format.
+pub fn read_file(path: &Path) -> io::Result<(String, FileFormat)> {
+    let raw = fs::read_to_string(path)?;
+    Ok(FileFormat::decode(&raw))
+}
+
+/// Replaces the contents of `path` so that a crash leaves either the old or the new file, never a
+/// truncated one: the data is written and synced to a temporary file beside it, which is then
+/// renamed over it. Symlinks are followed and an existing file's permissions are kept.
+pub fn write_file_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    stage_file(path, contents)?.commit()
+}
+
+/// New contents for a file, written and synced beside it but not yet in its place. Staging every
+/// file of a batch before committing any lets a failed write leave them all untouched. Dropped
+/// uncommitted, the file stays as it was.
+pub struct StagedFile {
+    path: PathBuf,
+    /// `None` once committed.
+    temp: Option<PathBuf>,
+}
+
+impl StagedFile {
+    /// Renames the new contents over the file.
+    pub fn commit(mut self) -> io::Result<()> {
+        let Some(temp) = self.temp.take() else {
+            return Ok(());
+        };
+        let result = fs::rename(&temp, &self.path);
+        if result.is_err() {
+            fs::remove_file(&temp).ok();
+        }
+        result
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if let Some(temp) = self.temp.take() {
+            fs::remove_file(temp).ok();
+        }
+    }
+}
+
+/// The first half of `write_file_atomic`: writes `contents` to a temporary file beside `path`.
+pub fn stage_file(path: &Path, contents: &[u8]) -> io::Result<StagedFile> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let temp = temp_path(&path)?;
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()
+    })();
+    match result {
+        Ok(()) => Ok(StagedFile {
+            path,
+            temp: Some(temp),
+        }),
+        Err(error) => {
+            fs::remove_file(&temp).ok();
+            Err(error)
+        }
+    }
+}
+
+/// A hidden sibling of `path`, on the same file system so the final rename is atomic.
+fn temp_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        )
+    })?;
+    let temp_name = format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id());
+    Ok(path.with_file_name(temp_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{FileFormat, LineEnding, read_file, stage_file, write_file_atomic};
+
+    #[test]
+    fn round_trips_line_endings_and_trailing_newline() {
+        for raw in ["", "one", "one\n", "one\r\ntwo\r\n", "one\r\ntwo", "a\n\n"] {
+            let (text, format) = FileFormat::decode(raw);
+            assert!(!text.contains('\r'), "{raw:?}");
+            assert_eq!(format.encode(&text), raw);
+        }
+
+        let (text, format) = FileFormat::decode("one\r\ntwo\r\n");
+        assert_eq!(text, "one\ntwo");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.trailing_newline);
+        assert_eq!(format.encode("one\ntwo\nthree"), "one\r\ntwo\r\nthree\r\n");
+
+        // Mixed endings follow the majority, wherever the first line break is.
+        let (_, format) = FileFormat::decode("one\ntwo\r\nthree\r\n");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        let (text, format) = FileFormat::decode("one\r\ntwo\nthree\n");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert_eq!(format.encode(&text), "one\r\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn atomic_write_replaces_contents() {
+        let dir = std::env::temp_dir().join(format!("zeno-file-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("note.md");
+
+        write_file_atomic(&path, b"first\r\n").unwrap();
+        write_file_atomic(&path, b"second\r\n").unwrap();
+        let (text, format) = read_file(&path).unwrap();
+        assert_eq!(text, "second");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+        assert_eq!(leftovers, 1);
+
+        // Staged contents only replace the file once committed.
+        drop(stage_file(&path, b"dropped").unwrap());
+        let staged = stage_file(&path, b"third").unwrap();
+        assert_eq!(read_file(&path).unwrap().0, "second");
+        staged.commit().unwrap();
+        assert_eq!(read_file(&path).unwrap().0, "third");
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+        assert_eq!(leftovers, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
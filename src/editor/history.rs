@@ -0,0 +1,186 @@
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use super::Selection;
+
+/// Consecutive edits of the same kind closer together than this are undone as one step.
+const GROUP_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// A single replacement, recorded so it can be applied in either direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub old_text: String,
+    pub new_text: String,
+}
+
+impl Edit {
+    /// Range covered by `old_text` before the edit was applied.
+    pub fn old_range(&self) -> Range<usize> {
+        self.start..self.start + self.old_text.len()
+    }
+
+    fn kind(&self) -> EditKind {
+        match (self.old_text.is_empty(), self.new_text.is_empty()) {
+            (true, false) if !self.new_text.contains('\n') => EditKind::Insert,
+            (false, true) => EditKind::Delete,
+            _ => EditKind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+/// All selections plus the index of the primary one.
+pub type SelectionSnapshot = (Vec<Selection>, usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    /// Edits sorted by position, non-overlapping, with offsets relative to the buffer as it was
+    /// before the transaction. Applying them back to front keeps every offset valid.
+    pub edits: Vec<Edit>,
+    pub selection_before: SelectionSnapshot,
+    pub selection_after: SelectionSnapshot,
+    last_edit_at: Instant,
+}
+
+impl Transaction {
+    /// Start offset of each edit in the buffer as it is after the transaction.
+    pub fn post_edit_starts(&self) -> Vec<usize> {
+        post_edit_starts(&self.edits)
+    }
+}
+
+pub fn post_edit_starts(edits: &[Edit]) -> Vec<usize> {
+    let mut delta = 0isize;
+    edits
+        .iter()
+        .map(|edit| {
+            let start = edit.start.saturating_add_signed(delta);
+            delta += edit.new_text.len() as isize - edit.old_text.len() as isize;
+            start
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    /// Whether the next edit may be merged into the top of `undo_stack`.
+    group_open: bool,
+}
+
+impl History {
+    /// Stops the next edit from being grouped with the previous one.
+    pub fn break_group(&mut self) {
+        self.group_open = false;
+    }
+
+    pub fn record(
+        &mut self,
+        edits: Vec<Edit>,
+        selection_before: SelectionSnapshot,
+        selection_after: SelectionSnapshot,
+    ) {
+        if edits.is_empty() {
+            return;
+        }
+        self.redo_stack.clear();
+        let now = Instant::now();
+
+        if self.group_open
+            && let Some(transaction) = self.undo_stack.last_mut()
+            && now.duration_since(transaction.last_edit_at) < GROUP_INTERVAL
+            && merge_transaction(transaction, &edits)
+        {
+            transaction.selection_after = selection_after;
+            transaction.last_edit_at = now;
+            return;
+        }
+
+        self.group_open = edits.iter().all(|edit| edit.kind() != EditKind::Other);
+        self.undo_stack.push(Transaction {
+            edits,
+            selection_before,
+            selection_after,
+            last_edit_at: now,
+        });
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Transaction> {
+        let transaction = self.undo_stack.pop()?;
+        self.group_open = false;
+        self.redo_stack.push(transaction.clone());
+        Some(transaction)
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Transaction> {
+        let transaction = self.redo_stack.pop()?;
+        self.group_open = false;
+        self.undo_stack.push(transaction.clone());
+        Some(transaction)
+    }
+}
+
+/// Folds `edits` into `transaction` when each one continues the run of typing or deletion of the
+/// corresponding edit, as happens when typing with one or more cursors.
+fn merge_transaction(transaction: &mut Transaction, edits: &[Edit]) -> bool {
+    if transaction.edits.len() != edits.len() {
+        return false;
+    }
+
+    let post_starts = transaction.post_edit_starts();
+    let merges = transaction
+        .edits
+        .iter()
+        .zip(&post_starts)
+        .zip(edits)
+        .map(|((last, &post_start), next)| merge_kind(last, post_start, next))
+        .collect::<Option<Vec<_>>>();
+    let Some(merges) = merges else {
+        return false;
+    };
+
+    for ((last, next), merge) in transaction.edits.iter_mut().zip(edits).zip(merges) {
+        match merge {
+            Merge::Append => last.new_text.push_str(&next.new_text),
+            Merge::DeleteBackward => {
+                last.old_text.insert_str(0, &next.old_text);
+                last.start -= next.old_text.len();
+            }
+            Merge::DeleteForward => last.old_text.push_str(&next.old_text),
+        }
+    }
+    true
+}
+
+enum Merge {
+    Append,
+    DeleteBackward,
+    DeleteForward,
+}
+
+/// `last` is in pre-transaction offsets; `post_start` is where it begins once applied, which is the
+/// coordinate space `next` was recorded in.
+fn merge_kind(last: &Edit, post_start: usize, next: &Edit) -> Option<Merge> {
+    if last.kind() != next.kind() {
+        return None;
+    }
+
+    match next.kind() {
+        EditKind::Insert if next.start == post_start + last.new_text.len() => Some(Merge::Append),
+        EditKind::Delete
+            if next.old_range().end == post_start && last.start >= next.old_text.len() =>
+        {
+            Some(Merge::DeleteBackward)
+        }
+        EditKind::Delete if next.start == post_start => Some(Merge::DeleteForward),
+        _ => None,
+    }
+}
@@ -0,0 +1,56 @@
+/// The result of combining two sets of changes made to the same text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merged {
+    pub text: String,
+    /// Whether some changes overlapped. Each overlap is left in `text` between Git-style
+    /// `<<<<<<<`/`=======`/`>>>>>>>` markers for the user to resolve.
+    pub has_conflicts: bool,
+}
+
+/// Three-way merge, line by line, of `ours` and `theirs`, both edited from `base`.
+pub fn three_way_merge(base: &str, ours: &str, theirs: &str) -> Merged {
+    match diffy::merge(base, ours, theirs) {
+        Ok(text) => Merged {
+            text,
+            has_conflicts: false,
+        },
+        Err(text) => Merged {
+            text,
+            has_conflicts: true,
+        },
+    }
+}
+
+/// Whether `text` still holds a conflict `three_way_merge` marked: a line starting with
+/// `<<<<<<<` or `>>>>>>>`.
+pub fn has_conflict_markers(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_conflict_markers, three_way_merge};
+
+    #[test]
+    fn merges_separate_changes_and_marks_overlapping_ones() {
+        let base = "# Title\n\nfirst\nsecond\nthird\n";
+        let ours = "# Title\n\nfirst, edited here\nsecond\nthird\n";
+        let theirs = "# Title\n\nfirst\nsecond\nthird, edited by an agent\n";
+        let merged = three_way_merge(base, ours, theirs);
+        assert!(!merged.has_conflicts);
+        assert_eq!(
+            merged.text,
+            "# Title\n\nfirst, edited here\nsecond\nthird, edited by an agent\n"
+        );
+
+        let theirs = "# Title\n\nfirst, edited elsewhere\nsecond\nthird\n";
+        let merged = three_way_merge(base, ours, theirs);
+        assert!(merged.has_conflicts);
+        assert!(merged.text.contains("<<<<<<<"));
+        assert!(merged.text.contains("first, edited here"));
+        assert!(merged.text.contains("first, edited elsewhere"));
+        assert!(has_conflict_markers(&merged.text));
+        assert!(!has_conflict_markers(ours));
+    }
+}
@@ -1,3 +1,18 @@
+mod buffer;
+mod file;
+mod history;
+mod merge;
+mod movement;
+mod recovery;
+mod selection;
 mod state;
+mod watch;
 
+pub use buffer::{BufferEdit, TextBuffer};
+pub use file::{FileFormat, read_file, stage_file, write_file_atomic};
+pub use merge::{has_conflict_markers, three_way_merge};
+pub use movement::{LineLayout, MonospaceLayout, SelectionGranularity, granular_range_at};
+pub use recovery::{Journal, RecoveryEntry};
+pub use selection::{Selection, SelectionSet};
 pub use state::EditorState;
+pub use watch::{FileWatcher, TreeWatcher};
@@ -0,0 +1,306 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::TextBuffer;
+
+/// Geometry of laid-out lines, used to keep a goal column during vertical motion.
+///
+/// `column` values are byte offsets relative to the start of `line`; `x` is a display
+/// coordinate in whatever unit the layout uses (pixels in the editor view). A line may be soft
+/// wrapped into several visual rows, each with its own x origin.
+pub trait LineLayout {
+    /// `x` of `column` within the visual row that contains it.
+    fn x_for_column(&self, line: usize, column: usize) -> f32;
+    /// Column in visual `row` of `line` closest to `x`.
+    fn column_for_x(&self, line: usize, row: usize, x: f32) -> usize;
+
+    fn row_count(&self, _line: usize) -> usize {
+        1
+    }
+
+    fn row_for_column(&self, _line: usize, _column: usize) -> usize {
+        0
+    }
+}
+
+/// Treats every char as `char_width` wide. Used for lines the view has not shaped, and in tests.
+pub struct MonospaceLayout<'a> {
+    buffer: &'a TextBuffer,
+    /// Width of every char in the unit `x` is measured in, e.g. the font's advance in pixels.
+    char_width: f32,
+    /// Chars per visual row, if lines are soft wrapped.
+    wrap_column: Option<usize>,
+}
+
+impl<'a> MonospaceLayout<'a> {
+    /// Lays out `buffer` one unit per char, without wrapping.
+    pub fn new(buffer: &'a TextBuffer) -> Self {
+        Self {
+            buffer,
+            char_width: 1.,
+            wrap_column: None,
+        }
+    }
+
+    pub fn with_char_width(self, char_width: f32) -> Self {
+        Self { char_width, ..self }
+    }
+
+    /// Wraps lines every `wrap_column` chars.
+    pub fn wrapped(self, wrap_column: usize) -> Self {
+        Self {
+            wrap_column: Some(wrap_column.max(1)),
+            ..self
+        }
+    }
+
+    fn line(&self, line: usize) -> Option<Cow<'a, str>> {
+        (line < self.buffer.line_count()).then(|| self.buffer.line(line))
+    }
+
+    fn chars_per_row(&self) -> usize {
+        self.wrap_column.unwrap_or(usize::MAX)
+    }
+}
+
+impl LineLayout for MonospaceLayout<'_> {
+    fn x_for_column(&self, line: usize, column: usize) -> f32 {
+        let Some(text) = self.line(line) else {
+            return 0.;
+        };
+        let column = column.min(text.len());
+        let chars = text[..column].chars().count();
+        let row_start = self.row_for_column(line, column) * self.chars_per_row();
+        (chars - row_start) as f32 * self.char_width
+    }
+
+    fn column_for_x(&self, line: usize, row: usize, x: f32) -> usize {
+        let Some(text) = self.line(line) else {
+            return 0;
+        };
+        let row_start = row.saturating_mul(self.chars_per_row());
+        // A row ends before the first char of the next one, so the cursor stays on it.
+        let row_end = if row + 1 < self.row_count(line) {
+            row_start + self.chars_per_row() - 1
+        } else {
+            usize::MAX
+        };
+        let target = ((x / self.char_width).round().max(0.) as usize)
+            .saturating_add(row_start)
+            .min(row_end);
+        text.char_indices()
+            .nth(target)
+            .map(|(idx, _)| idx)
+            .unwrap_or(text.len())
+    }
+
+    fn row_count(&self, line: usize) -> usize {
+        let chars = self.line(line).map_or(0, |text| text.chars().count());
+        chars.div_ceil(self.chars_per_row()).max(1)
+    }
+
+    fn row_for_column(&self, line: usize, column: usize) -> usize {
+        let Some(text) = self.line(line) else {
+            return 0;
+        };
+        let chars = text[..column.min(text.len())].chars().count();
+        (chars / self.chars_per_row()).min(self.row_count(line) - 1)
+    }
+}
+
+/// Offset of the grapheme cluster boundary before `offset`, so emoji sequences and combining marks
+/// are traversed as one unit.
+pub fn prev_grapheme_boundary(buffer: &TextBuffer, offset: usize) -> usize {
+    let offset = buffer.floor_char_boundary(offset);
+    let range = buffer.line_range(buffer.line_for_offset(offset));
+    if offset <= range.start {
+        return buffer.prev_char_boundary(offset);
+    }
+    let text = buffer.slice(range.start..offset);
+    let last = text.grapheme_indices(true).next_back().map(|(idx, _)| idx);
+    range.start + last.unwrap_or(0)
+}
+
+/// Offset of the grapheme cluster boundary after `offset`.
+pub fn next_grapheme_boundary(buffer: &TextBuffer, offset: usize) -> usize {
+    let offset = buffer.floor_char_boundary(offset);
+    let range = buffer.line_range(buffer.line_for_offset(offset));
+    if offset >= range.end {
+        return buffer.next_char_boundary(offset);
+    }
+    let text = buffer.slice(offset..range.end);
+    let first = text.graphemes(true).next().map(str::len);
+    offset + first.unwrap_or(text.len())
+}
+
+/// Start of the word before `offset`, using Unicode word boundaries. When no word precedes
+/// `offset` on its line, stops at the line start first.
+pub fn prev_word_start(buffer: &TextBuffer, offset: usize) -> usize {
+    let offset = buffer.floor_char_boundary(offset);
+    let mut line = buffer.line_for_offset(offset);
+    loop {
+        let range = buffer.line_range(line);
+        let text = buffer.slice(range.clone());
+        let word_start = text
+            .split_word_bound_indices()
+            .rev()
+            .map(|(idx, segment)| (range.start + idx, segment))
+            .find(|&(start, segment)| start < offset && is_word_segment(segment));
+        if let Some((start, _)) = word_start {
+            return start;
+        }
+        if offset > range.start {
+            return range.start;
+        }
+        if line == 0 {
+            return 0;
+        }
+        line -= 1;
+    }
+}
+
+/// End of the word after `offset`, using Unicode word boundaries. When no word follows `offset`
+/// on its line, stops at the line end first.
+pub fn next_word_end(buffer: &TextBuffer, offset: usize) -> usize {
+    let offset = buffer.floor_char_boundary(offset);
+    let mut line = buffer.line_for_offset(offset);
+    loop {
+        let range = buffer.line_range(line);
+        let text = buffer.slice(range.clone());
+        let word_end = text
+            .split_word_bound_indices()
+            .map(|(idx, segment)| (range.start + idx + segment.len(), segment))
+            .find(|&(end, segment)| end > offset && is_word_segment(segment));
+        if let Some((end, _)) = word_end {
+            return end;
+        }
+        if offset < range.end {
+            return range.end;
+        }
+        if line + 1 >= buffer.line_count() {
+            return buffer.len();
+        }
+        line += 1;
+    }
+}
+
+/// Start of the blank line above the paragraph containing `offset`, skipping any blank lines the
+/// cursor is already on.
+pub fn prev_paragraph_start(buffer: &TextBuffer, offset: usize) -> usize {
+    let mut line = buffer.line_for_offset(offset);
+    while line > 0 && is_blank_line(buffer, line) {
+        line -= 1;
+    }
+    while line > 0 && !is_blank_line(buffer, line) {
+        line -= 1;
+    }
+    buffer.line_start(line)
+}
+
+/// Start of the blank line below the paragraph containing `offset`, or the end of the buffer.
+pub fn next_paragraph_start(buffer: &TextBuffer, offset: usize) -> usize {
+    let last_line = buffer.line_count() - 1;
+    let mut line = buffer.line_for_offset(offset);
+    while line < last_line && is_blank_line(buffer, line) {
+        line += 1;
+    }
+    while line < last_line && !is_blank_line(buffer, line) {
+        line += 1;
+    }
+    if is_blank_line(buffer, line) {
+        buffer.line_start(line)
+    } else {
+        buffer.len()
+    }
+}
+
+fn is_word_segment(segment: &str) -> bool {
+    segment.chars().any(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn is_blank_line(buffer: &TextBuffer, line: usize) -> bool {
+    buffer
+        .slice(buffer.line_range(line))
+        .chars()
+        .all(char::is_whitespace)
+}
+
+/// Range of the word containing `offset`, or the word that ends exactly at `offset`.
+pub fn word_range_at(buffer: &TextBuffer, offset: usize) -> Option<Range<usize>> {
+    let offset = buffer.floor_char_boundary(offset);
+    let range = buffer.line_range(buffer.line_for_offset(offset));
+    let text = buffer.slice(range.clone());
+    let words = text
+        .split_word_bound_indices()
+        .filter(|(_, segment)| is_word_segment(segment))
+        .map(|(idx, segment)| range.start + idx..range.start + idx + segment.len())
+        .collect::<Vec<_>>();
+    words
+        .iter()
+        .find(|word| word.contains(&offset))
+        .or_else(|| words.iter().find(|word| word.end == offset))
+        .cloned()
+}
+
+/// Unit that mouse selections snap to, chosen by click count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionGranularity {
+    Character,
+    Word,
+    Line,
+    Paragraph,
+}
+
+impl SelectionGranularity {
+    pub fn from_click_count(click_count: usize) -> Self {
+        match click_count {
+            0 | 1 => Self::Character,
+            2 => Self::Word,
+            3 => Self::Line,
+            _ => Self::Paragraph,
+        }
+    }
+}
+
+/// Range of the unit of `granularity` around `offset`. Lines and paragraphs include their trailing
+/// newline; outside a word, the run of whitespace or punctuation under `offset` is used instead.
+pub fn granular_range_at(
+    buffer: &TextBuffer,
+    offset: usize,
+    granularity: SelectionGranularity,
+) -> Range<usize> {
+    let offset = buffer.floor_char_boundary(offset);
+    match granularity {
+        SelectionGranularity::Character => offset..offset,
+        SelectionGranularity::Word => word_range_at(buffer, offset)
+            .or_else(|| segment_range_at(buffer, offset))
+            .unwrap_or(offset..offset),
+        SelectionGranularity::Line => {
+            let line = buffer.line_for_offset(offset);
+            buffer.line_start(line)..buffer.line_start(line + 1)
+        }
+        SelectionGranularity::Paragraph => {
+            let line = buffer.line_for_offset(offset);
+            let blank = is_blank_line(buffer, line);
+            let mut first = line;
+            while first > 0 && is_blank_line(buffer, first - 1) == blank {
+                first -= 1;
+            }
+            let mut last = line;
+            while last + 1 < buffer.line_count() && is_blank_line(buffer, last + 1) == blank {
+                last += 1;
+            }
+            buffer.line_start(first)..buffer.line_start(last + 1)
+        }
+    }
+}
+
+fn segment_range_at(buffer: &TextBuffer, offset: usize) -> Option<Range<usize>> {
+    let range = buffer.line_range(buffer.line_for_offset(offset));
+    let text = buffer.slice(range.clone());
+    text.split_word_bound_indices()
+        .map(|(idx, segment)| range.start + idx..range.start + idx + segment.len())
+        .find(|segment| segment.contains(&offset))
+}
@@ -0,0 +1,162 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::file::write_file_atomic;
+
+/// Unsaved buffer contents kept outside the notes themselves, so edits survive a crash. Each
+/// buffer has one entry, rewritten as it is edited and removed once the buffer is saved.
+///
+/// An entry is the buffer's file path on the first line (empty for a note never saved) followed
+/// by the contents as they would be written to that file.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    dir: PathBuf,
+}
+
+/// A journal entry found on startup.
+#[derive(Debug, Clone)]
+pub struct RecoveryEntry {
+    pub journal_path: PathBuf,
+    pub path: Option<PathBuf>,
+    pub contents: String,
+    pub modified: SystemTime,
+}
+
+const EXTENSION: &str = "journal";
+
+impl Journal {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The journal in the platform's local app-data directory, e.g.
+    /// `~/.local/share/zeno/recovery` on Linux.
+    pub fn in_app_data() -> Option<Self> {
+        dirs::data_local_dir().map(|dir| Self::new(dir.join("zeno").join("recovery")))
+    }
+
+    fn entry_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).with_extension(EXTENSION)
+    }
+
+    pub fn write(&self, id: &str, path: Option<&Path>, contents: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let header = path
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let entry = format!("{header}\n{contents}");
+        write_file_atomic(&self.entry_path(id), entry.as_bytes())
+    }
+
+    pub fn remove(&self, id: &str) -> io::Result<()> {
+        remove_if_exists(&self.entry_path(id))
+    }
+
+    /// Every entry in the journal, most recently written first. Unreadable entries are skipped.
+    pub fn entries(&self) -> io::Result<Vec<RecoveryEntry>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut entries = Vec::new();
+        for dir_entry in dir {
+            let journal_path = dir_entry?.path();
+            if journal_path
+                .extension()
+                .is_none_or(|extension| extension != EXTENSION)
+            {
+                continue;
+            }
+            let (Ok(entry), Ok(metadata)) = (
+                fs::read_to_string(&journal_path),
+                fs::metadata(&journal_path),
+            ) else {
+                continue;
+            };
+            let (header, contents) = entry.split_once('\n').unwrap_or((&entry, ""));
+            entries.push(RecoveryEntry {
+                path: (!header.is_empty()).then(|| PathBuf::from(header)),
+                contents: contents.to_string(),
+                modified: metadata.modified()?,
+                journal_path,
+            });
+        }
+        entries.sort_by_key(|entry| Reverse(entry.modified));
+        Ok(entries)
+    }
+}
+
+impl RecoveryEntry {
+    /// Whether the entry holds edits made after its file was last written. Always true for notes
+    /// never saved or since deleted.
+    pub fn is_newer_than_file(&self) -> bool {
+        let Some(path) = &self.path else {
+            return true;
+        };
+        match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(file_modified) => self.modified > file_modified,
+            Err(_) => true,
+        }
+    }
+
+    pub fn discard(&self) -> io::Result<()> {
+        remove_if_exists(&self.journal_path)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use super::Journal;
+
+    #[test]
+    fn records_and_recovers_unsaved_buffers() {
+        let dir = std::env::temp_dir().join(format!("zeno-journal-test-{}", std::process::id()));
+        let journal = Journal::new(dir.join("recovery"));
+        assert!(journal.entries().unwrap().is_empty());
+
+        let note = dir.join("note.md");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&note, "saved\n").unwrap();
+        journal.write("a", Some(&note), "edited\ntwice\n").unwrap();
+        journal.write("b", None, "scratch").unwrap();
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        let edited = entries.iter().find(|entry| entry.path.is_some()).unwrap();
+        assert_eq!(edited.path.as_deref(), Some(note.as_path()));
+        assert_eq!(edited.contents, "edited\ntwice\n");
+        let scratch = entries.iter().find(|entry| entry.path.is_none()).unwrap();
+        assert_eq!(scratch.contents, "scratch");
+        assert!(scratch.is_newer_than_file());
+
+        // A file written after the journal entry supersedes it.
+        let later = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&note)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(!edited.is_newer_than_file());
+
+        journal.remove("a").unwrap();
+        scratch.discard().unwrap();
+        assert!(journal.entries().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
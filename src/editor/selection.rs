@@ -0,0 +1,127 @@
+use std::ops::Range;
+
+use super::BufferEdit;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub cursor: usize,
+    /// Display x the cursor tries to return to while moving up and down across shorter lines.
+    pub goal_x: Option<f32>,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, cursor: usize) -> Self {
+        Self {
+            anchor,
+            cursor,
+            goal_x: None,
+        }
+    }
+
+    pub fn cursor_at(offset: usize) -> Self {
+        Self::new(offset, offset)
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.cursor)..self.anchor.max(self.cursor)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.cursor
+    }
+
+    pub fn reversed(&self) -> bool {
+        self.cursor < self.anchor
+    }
+}
+
+/// One view's selections into a buffer that several views show, held by the view while another
+/// one edits through the shared `EditorState`. Sorted, non-overlapping and never empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionSet {
+    pub selections: Vec<Selection>,
+    /// Index into `selections` of the primary selection.
+    pub primary: usize,
+}
+
+impl Default for SelectionSet {
+    fn default() -> Self {
+        Self::cursor_at(0)
+    }
+}
+
+impl SelectionSet {
+    pub fn cursor_at(offset: usize) -> Self {
+        Self {
+            selections: vec![Selection::cursor_at(offset)],
+            primary: 0,
+        }
+    }
+
+    pub fn primary(&self) -> &Selection {
+        &self.selections[self.primary]
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.primary().cursor
+    }
+
+    /// Keeps the selections on the same text across an edit made through another view. Offsets
+    /// before the edit stay, those after it shift, and those inside the replaced text stay put
+    /// but no further than the end of the replacement.
+    pub fn apply_buffer_edit(&mut self, edit: &BufferEdit) {
+        let map = |offset: usize| {
+            if offset <= edit.start {
+                offset
+            } else if offset >= edit.old_end {
+                offset - edit.old_end + edit.new_end
+            } else {
+                offset.min(edit.new_end)
+            }
+        };
+        for selection in &mut self.selections {
+            *selection = Selection::new(map(selection.anchor), map(selection.cursor));
+        }
+        self.primary = normalize_selections(&mut self.selections, self.primary);
+    }
+}
+
+/// Sorts `selections` by position and merges any that overlap, or that touch while one of them is
+/// a bare cursor. Returns the new index of the selection that was at `primary`.
+pub fn normalize_selections(selections: &mut Vec<Selection>, primary: usize) -> usize {
+    let primary_cursor = selections.get(primary).map(|s| s.cursor).unwrap_or(0);
+    selections.sort_by_key(|s| (s.range().start, s.range().end));
+
+    let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+    for selection in selections.drain(..) {
+        if let Some(last) = merged.last_mut() {
+            let last_range = last.range();
+            let range = selection.range();
+            let touching =
+                range.start == last_range.end && (last.is_empty() || selection.is_empty());
+            if range.start < last_range.end || touching {
+                let start = last_range.start;
+                let end = last_range.end.max(range.end);
+                *last = if selection.reversed() || (selection.is_empty() && last.reversed()) {
+                    Selection::new(end, start)
+                } else {
+                    Selection::new(start, end)
+                };
+                continue;
+            }
+        }
+        merged.push(selection);
+    }
+    *selections = merged;
+
+    selections
+        .iter()
+        .position(|s| s.range().contains(&primary_cursor) || s.cursor == primary_cursor)
+        .or_else(|| {
+            selections
+                .iter()
+                .position(|s| s.range().end == primary_cursor)
+        })
+        .unwrap_or(selections.len().saturating_sub(1))
+}
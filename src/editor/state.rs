@@ -1,10 +1,37 @@
 use std::ops::Range;
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+use super::history::{Edit, History, SelectionSnapshot, post_edit_starts};
+use super::movement::{
+    LineLayout, SelectionGranularity, granular_range_at, next_grapheme_boundary,
+    next_paragraph_start, next_word_end, prev_grapheme_boundary, prev_paragraph_start,
+    prev_word_start, word_range_at,
+};
+use super::selection::{Selection, SelectionSet, normalize_selections};
+use super::{BufferEdit, TextBuffer};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct EditorState {
-    text: String,
-    anchor: usize,
-    cursor: usize,
+    buffer: TextBuffer,
+    /// Sorted by position and non-overlapping. Never empty.
+    selections: Vec<Selection>,
+    /// Index into `selections` of the most recently added selection; single-selection APIs such as
+    /// `cursor()` and IME positioning refer to it.
+    primary: usize,
+    history: History,
+    /// Buffer edits applied since the last `take_buffer_edits`, in application order.
+    buffer_edits: Vec<BufferEdit>,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        Self {
+            buffer: TextBuffer::default(),
+            selections: vec![Selection::default()],
+            primary: 0,
+            history: History::default(),
+            buffer_edits: Vec::new(),
+        }
+    }
 }
 
 impl EditorState {
@@ -13,181 +40,723 @@ impl EditorState {
     }
 
     pub fn text(&self) -> &str {
-        &self.text
+        self.buffer.text()
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn selections(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    fn primary_selection(&self) -> &Selection {
+        &self.selections[self.primary]
     }
 
     pub fn cursor(&self) -> usize {
-        self.cursor
+        self.primary_selection().cursor
     }
 
+    #[allow(dead_code)]
     pub fn anchor(&self) -> usize {
-        self.anchor
+        self.primary_selection().anchor
     }
 
+    /// The primary selection's range.
     pub fn selection_range(&self) -> Range<usize> {
-        let start = self.anchor.min(self.cursor);
-        let end = self.anchor.max(self.cursor);
-        start..end
+        self.primary_selection().range()
     }
 
+    /// Whether the primary selection's cursor is before its anchor.
     pub fn selection_reversed(&self) -> bool {
-        self.cursor < self.anchor
+        self.primary_selection().reversed()
+    }
+
+    /// Text of every non-empty selection, joined by newlines.
+    pub fn selected_text(&self) -> String {
+        self.selections
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| self.buffer.slice(selection.range()))
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
     pub fn set_text(&mut self, text: String) {
-        self.text = text;
-        self.cursor = self.cursor.min(self.text.len());
-        self.anchor = self.anchor.min(self.text.len());
-        self.collapse_selection();
+        let len = self.buffer.len();
+        self.replace_buffer(0..len, &text);
+        let cursor = self.buffer.floor_char_boundary(self.cursor());
+        self.set_cursor(cursor);
     }
 
     pub fn set_cursor(&mut self, cursor: usize) {
-        self.cursor = cursor.min(self.text.len());
-        self.anchor = self.cursor;
+        let cursor = self.buffer.floor_char_boundary(cursor);
+        self.selections = vec![Selection::cursor_at(cursor)];
+        self.primary = 0;
+        self.history.break_group();
     }
 
     pub fn set_selection(&mut self, anchor: usize, cursor: usize) {
-        self.anchor = anchor.min(self.text.len());
-        self.cursor = cursor.min(self.text.len());
+        let anchor = self.buffer.floor_char_boundary(anchor);
+        let cursor = self.buffer.floor_char_boundary(cursor);
+        self.selections = vec![Selection::new(anchor, cursor)];
+        self.primary = 0;
+        self.history.break_group();
     }
 
+    /// Collapses every selection to its cursor.
+    #[allow(dead_code)]
     pub fn collapse_selection(&mut self) {
-        self.anchor = self.cursor;
+        for selection in &mut self.selections {
+            *selection = Selection::cursor_at(selection.cursor);
+        }
+        self.primary = normalize_selections(&mut self.selections, self.primary);
+        self.history.break_group();
+    }
+
+    /// Adds a selection and makes it the primary one, merging it with any it overlaps.
+    pub fn add_selection(&mut self, anchor: usize, cursor: usize) {
+        let anchor = self.buffer.floor_char_boundary(anchor);
+        let cursor = self.buffer.floor_char_boundary(cursor);
+        self.selections.push(Selection::new(anchor, cursor));
+        let added = self.selections.len() - 1;
+        self.primary = normalize_selections(&mut self.selections, added);
+        self.history.break_group();
+    }
+
+    /// Selects from `origin` to the unit of `granularity` under `offset`, so dragging after a
+    /// double- or triple-click extends by whole words, lines or paragraphs.
+    pub fn select_granular(
+        &mut self,
+        origin: Range<usize>,
+        offset: usize,
+        granularity: SelectionGranularity,
+    ) {
+        let head = granular_range_at(&self.buffer, offset, granularity);
+        if head.start < origin.start {
+            self.set_selection(origin.end, head.start);
+        } else {
+            self.set_selection(origin.start, head.end.max(origin.end));
+        }
+    }
+
+    /// Drops every selection except the primary one.
+    pub fn clear_extra_selections(&mut self) {
+        let primary = *self.primary_selection();
+        self.selections = vec![primary];
+        self.primary = 0;
+        self.history.break_group();
     }
 
     pub fn insert_str(&mut self, s: &str) {
-        let range = self.selection_range();
-        self.replace_range(range, s);
+        self.edit_selections(|_, selection| Some((selection.range(), s.to_string())));
+    }
+
+    /// Replaces text for an input method: `range` if it names one, else each range of `marked`,
+    /// the text being composed at each cursor, else every selection. Typing with several cursors
+    /// comes through here, so all of them get the text in one undoable edit. Returns where the
+    /// new text ended up, one range per edit.
+    pub fn replace_input(
+        &mut self,
+        range: Option<Range<usize>>,
+        marked: &[Range<usize>],
+        new_text: &str,
+    ) -> Vec<Range<usize>> {
+        if let Some(range) = range {
+            let range = self.buffer.clip_range(range);
+            self.replace_range(range.clone(), new_text);
+            let inserted = range.start..range.start + new_text.len();
+            return std::iter::once(inserted).collect();
+        }
+        if marked.is_empty() {
+            self.insert_str(new_text);
+        } else {
+            let selection_before = self.selection_snapshot();
+            self.selections = marked
+                .iter()
+                .map(|range| Selection::new(range.start, range.end))
+                .collect();
+            self.primary = self.primary.min(self.selections.len() - 1);
+            self.edit_selections_from(selection_before, |_, selection| {
+                Some((selection.range(), new_text.to_string()))
+            });
+        }
+        self.selections
+            .iter()
+            .map(|selection| selection.cursor.saturating_sub(new_text.len())..selection.cursor)
+            .collect()
+    }
+
+    /// Selects `range` of the `len` bytes before each cursor, as an input method does within the
+    /// text it is composing.
+    pub fn select_before_cursors(&mut self, len: usize, range: Range<usize>) {
+        self.update_selections(|buffer, selection| {
+            let start = selection.cursor.saturating_sub(len);
+            let anchor = (start + range.start).min(selection.cursor);
+            let cursor = (start + range.end).min(selection.cursor);
+            Selection::new(
+                buffer.floor_char_boundary(anchor),
+                buffer.floor_char_boundary(cursor),
+            )
+        });
+    }
+
+    /// Replaces every selection with text computed from the selected text and the text between the
+    /// start of its line and the selection.
+    pub fn replace_selections_with(&mut self, mut f: impl FnMut(&str, &str) -> String) {
+        self.edit_selections(|buffer, selection| {
+            let range = selection.range();
+            let line_start = buffer.line_start(buffer.line_for_offset(range.start));
+            let selected = buffer.slice(range.clone());
+            let line_prefix = buffer.slice(line_start..range.start);
+            Some((range, f(&selected, &line_prefix)))
+        });
     }
 
     pub fn backspace(&mut self) {
-        let selection = self.selection_range();
-        if !selection.is_empty() {
-            self.replace_range(selection, "");
+        self.delete_selections_or(prev_grapheme_boundary);
+    }
+
+    pub fn delete_forward(&mut self) {
+        self.delete_selections_or(next_grapheme_boundary);
+    }
+
+    pub fn delete_to_prev_word_start(&mut self) {
+        self.delete_selections_or(prev_word_start);
+    }
+
+    pub fn delete_to_next_word_end(&mut self) {
+        self.delete_selections_or(next_word_end);
+    }
+
+    /// Deletes each non-empty selection, or the text between each cursor and `target(cursor)`.
+    fn delete_selections_or(&mut self, target: fn(&TextBuffer, usize) -> usize) {
+        self.edit_selections(|buffer, selection| {
+            let range = if selection.is_empty() {
+                let target = target(buffer, selection.cursor);
+                target.min(selection.cursor)..target.max(selection.cursor)
+            } else {
+                selection.range()
+            };
+            (!range.is_empty()).then(|| (range, String::new()))
+        });
+    }
+
+    pub fn move_left(&mut self, extend_selection: bool) {
+        self.update_selections(|buffer, selection| {
+            if !extend_selection && !selection.is_empty() {
+                return Selection::cursor_at(selection.range().start);
+            }
+            moved(
+                selection,
+                prev_grapheme_boundary(buffer, selection.cursor),
+                extend_selection,
+            )
+        });
+    }
+
+    pub fn move_right(&mut self, extend_selection: bool) {
+        self.update_selections(|buffer, selection| {
+            if !extend_selection && !selection.is_empty() {
+                return Selection::cursor_at(selection.range().end);
+            }
+            moved(
+                selection,
+                next_grapheme_boundary(buffer, selection.cursor),
+                extend_selection,
+            )
+        });
+    }
+
+    pub fn move_to_prev_word_start(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, prev_word_start);
+    }
+
+    pub fn move_to_next_word_end(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, next_word_end);
+    }
+
+    pub fn move_to_prev_paragraph(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, prev_paragraph_start);
+    }
+
+    pub fn move_to_next_paragraph(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, next_paragraph_start);
+    }
+
+    pub fn move_to_line_start(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, |buffer, offset| {
+            buffer.line_start(buffer.line_for_offset(offset))
+        });
+    }
+
+    pub fn move_to_line_end(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, |buffer, offset| {
+            buffer.line_end(buffer.line_for_offset(offset))
+        });
+    }
+
+    pub fn move_to_beginning(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, |_, _| 0);
+    }
+
+    pub fn move_to_end(&mut self, extend_selection: bool) {
+        self.move_cursors(extend_selection, |buffer, _| buffer.len());
+    }
+
+    pub fn move_up(&mut self, extend_selection: bool, layout: &dyn LineLayout) {
+        self.update_selections(|buffer, selection| {
+            vertically_moved(buffer, selection, -1, extend_selection, layout)
+        });
+    }
+
+    pub fn move_down(&mut self, extend_selection: bool, layout: &dyn LineLayout) {
+        self.update_selections(|buffer, selection| {
+            vertically_moved(buffer, selection, 1, extend_selection, layout)
+        });
+    }
+
+    pub fn add_cursor_above(&mut self, layout: &dyn LineLayout) {
+        self.add_cursor_vertically(-1, layout);
+    }
+
+    pub fn add_cursor_below(&mut self, layout: &dyn LineLayout) {
+        self.add_cursor_vertically(1, layout);
+    }
+
+    fn add_cursor_vertically(&mut self, direction: isize, layout: &dyn LineLayout) {
+        let primary = *self.primary_selection();
+        let (line, column) = self.buffer.offset_to_point(primary.cursor);
+        let Some((target_line, target_row)) =
+            adjacent_row(&self.buffer, layout, line, column, direction)
+        else {
             return;
-        }
+        };
 
-        if self.cursor == 0 {
+        let goal_x = primary
+            .goal_x
+            .unwrap_or_else(|| layout.x_for_column(line, column));
+        let column = layout.column_for_x(target_line, target_row, goal_x);
+        let offset = self.buffer.point_to_offset(target_line, column);
+        self.add_selection(offset, offset);
+        self.selections[self.primary].goal_x = Some(goal_x);
+    }
+
+    /// Selects the word under the primary cursor, or when the primary selection is non-empty adds
+    /// a selection for the next occurrence of its text, wrapping around the end of the buffer.
+    pub fn select_next_occurrence(&mut self) {
+        let primary = *self.primary_selection();
+        if primary.is_empty() {
+            if let Some(word) = word_range_at(&self.buffer, primary.cursor) {
+                self.selections[self.primary] = Selection::new(word.start, word.end);
+                self.primary = normalize_selections(&mut self.selections, self.primary);
+                self.history.break_group();
+            }
             return;
         }
 
-        let prev = prev_char_boundary(&self.text, self.cursor);
-        self.replace_range(prev..self.cursor, "");
+        let needle = self.buffer.slice(primary.range());
+        let search_from = primary.range().end;
+        let next = [search_from..self.len(), 0..search_from]
+            .into_iter()
+            .find_map(|within| {
+                let mut from = within.start;
+                while let Some(start) = self.buffer.find(&needle, from..within.end) {
+                    let range = start..start + needle.len();
+                    if self
+                        .selections
+                        .iter()
+                        .all(|selection| selection.range() != range)
+                    {
+                        return Some(range);
+                    }
+                    from = self.buffer.next_char_boundary(start);
+                }
+                None
+            });
+        if let Some(range) = next {
+            self.add_selection(range.start, range.end);
+        }
+    }
+
+    /// Replaces all selections with a rectangular block spanning `anchor` to `head`, each given
+    /// as a `(line, display x)` pair. Columns are measured on the first visual row of soft-wrapped
+    /// lines. The selection on `head`'s line becomes primary.
+    pub fn select_columns(
+        &mut self,
+        anchor: (usize, f32),
+        head: (usize, f32),
+        layout: &dyn LineLayout,
+    ) {
+        let (anchor_line, anchor_x) = anchor;
+        let (head_line, head_x) = head;
+        let last_line = self.buffer.line_count() - 1;
+        let anchor_line = anchor_line.min(last_line);
+        let head_line = head_line.min(last_line);
+
+        let lines = anchor_line.min(head_line)..=anchor_line.max(head_line);
+        self.selections = lines
+            .map(|line| {
+                let anchor_column = layout.column_for_x(line, 0, anchor_x);
+                let head_column = layout.column_for_x(line, 0, head_x);
+                Selection {
+                    anchor: self.buffer.point_to_offset(line, anchor_column),
+                    cursor: self.buffer.point_to_offset(line, head_column),
+                    goal_x: Some(head_x),
+                }
+            })
+            .collect();
+        self.primary = if head_line >= anchor_line {
+            self.selections.len() - 1
+        } else {
+            0
+        };
+        self.history.break_group();
     }
 
-    pub fn delete_forward(&mut self) {
-        let selection = self.selection_range();
-        if !selection.is_empty() {
-            self.replace_range(selection, "");
+    pub fn select_all(&mut self) {
+        self.set_selection(0, self.len());
+    }
+
+    /// Moves every cursor to `target(cursor)`, extending or collapsing each selection.
+    fn move_cursors(&mut self, extend_selection: bool, target: fn(&TextBuffer, usize) -> usize) {
+        self.update_selections(|buffer, selection| {
+            moved(
+                selection,
+                target(buffer, selection.cursor),
+                extend_selection,
+            )
+        });
+    }
+
+    fn update_selections(&mut self, mut f: impl FnMut(&TextBuffer, &Selection) -> Selection) {
+        for selection in &mut self.selections {
+            *selection = f(&self.buffer, selection);
+        }
+        self.primary = normalize_selections(&mut self.selections, self.primary);
+        self.history.break_group();
+    }
+
+    /// Applies one edit per selection as a single undoable transaction. `f` returns the range to
+    /// replace and its replacement, or `None` to leave that selection alone. Edited selections
+    /// collapse to the end of their inserted text.
+    fn edit_selections(
+        &mut self,
+        f: impl FnMut(&TextBuffer, &Selection) -> Option<(Range<usize>, String)>,
+    ) {
+        self.edit_selections_from(self.selection_snapshot(), f);
+    }
+
+    /// `edit_selections` recording `selection_before` as what undoing restores.
+    fn edit_selections_from(
+        &mut self,
+        selection_before: SelectionSnapshot,
+        mut f: impl FnMut(&TextBuffer, &Selection) -> Option<(Range<usize>, String)>,
+    ) {
+        let mut owners = Vec::new();
+        let mut edits: Vec<Edit> = Vec::new();
+        let mut min_start = 0;
+        for (index, selection) in self.selections.iter().enumerate() {
+            let Some((range, new_text)) = f(&self.buffer, selection) else {
+                continue;
+            };
+            let range = self.buffer.clip_range(range);
+            let start = range.start.max(min_start);
+            let end = range.end.max(start);
+            if start == end && new_text.is_empty() {
+                continue;
+            }
+            min_start = end;
+            owners.push(index);
+            edits.push(Edit {
+                start,
+                old_text: self.buffer.slice(start..end),
+                new_text,
+            });
+        }
+        if edits.is_empty() {
             return;
         }
 
-        if self.cursor >= self.text.len() {
+        for edit in edits.iter().rev() {
+            self.replace_buffer(edit.old_range(), &edit.new_text);
+        }
+
+        let post_starts = post_edit_starts(&edits);
+        for (index, selection) in self.selections.iter_mut().enumerate() {
+            *selection = match owners.iter().position(|&owner| owner == index) {
+                Some(i) => Selection::cursor_at(post_starts[i] + edits[i].new_text.len()),
+                None => Selection::new(
+                    map_offset(&edits, &post_starts, selection.anchor),
+                    map_offset(&edits, &post_starts, selection.cursor),
+                ),
+            };
+        }
+        self.primary = normalize_selections(&mut self.selections, self.primary);
+
+        self.history
+            .record(edits, selection_before, self.selection_snapshot());
+    }
+
+    /// Replaces an explicit range, collapsing to a single cursor after the inserted text.
+    pub fn replace_range(&mut self, range: Range<usize>, new_text: &str) {
+        let selection_before = self.selection_snapshot();
+        let range = self.buffer.clip_range(range);
+        let old_text = self.buffer.slice(range.clone());
+        if old_text.is_empty() && new_text.is_empty() {
+            self.selections = vec![Selection::cursor_at(range.start)];
+            self.primary = 0;
             return;
         }
 
-        let next = next_char_boundary(&self.text, self.cursor);
-        self.replace_range(self.cursor..next, "");
+        self.replace_buffer(range.clone(), new_text);
+        self.selections = vec![Selection::cursor_at(range.start + new_text.len())];
+        self.primary = 0;
+
+        self.history.record(
+            vec![Edit {
+                start: range.start,
+                old_text,
+                new_text: new_text.to_string(),
+            }],
+            selection_before,
+            self.selection_snapshot(),
+        );
     }
 
-    pub fn move_left(&mut self, extend_selection: bool) {
-        if !extend_selection {
-            let selection = self.selection_range();
-            if !selection.is_empty() {
-                self.cursor = selection.start;
-                self.anchor = self.cursor;
-                return;
+    /// Replaces several ranges, sorted and not overlapping, as one undoable transaction that is
+    /// never grouped with typing. Selections stay on the text around the edits.
+    pub fn edit_ranges(&mut self, ranges: impl IntoIterator<Item = (Range<usize>, String)>) {
+        let selection_before = self.selection_snapshot();
+        let mut edits: Vec<Edit> = Vec::new();
+        let mut min_start = 0;
+        for (range, new_text) in ranges {
+            let range = self.buffer.clip_range(range);
+            let start = range.start.max(min_start);
+            let end = range.end.max(start);
+            let old_text = self.buffer.slice(start..end);
+            if old_text == new_text {
+                continue;
             }
+            min_start = end;
+            edits.push(Edit {
+                start,
+                old_text,
+                new_text,
+            });
+        }
+        if edits.is_empty() {
+            return;
         }
 
-        self.cursor = prev_char_boundary(&self.text, self.cursor);
-        if !extend_selection {
-            self.anchor = self.cursor;
+        for edit in edits.iter().rev() {
+            self.replace_buffer(edit.old_range(), &edit.new_text);
+        }
+        let post_starts = post_edit_starts(&edits);
+        for selection in &mut self.selections {
+            *selection = Selection::new(
+                map_offset(&edits, &post_starts, selection.anchor),
+                map_offset(&edits, &post_starts, selection.cursor),
+            );
         }
+        self.primary = normalize_selections(&mut self.selections, self.primary);
+
+        self.history.break_group();
+        self.history
+            .record(edits, selection_before, self.selection_snapshot());
+        self.history.break_group();
     }
 
-    pub fn move_right(&mut self, extend_selection: bool) {
-        if !extend_selection {
-            let selection = self.selection_range();
-            if !selection.is_empty() {
-                self.cursor = selection.end;
-                self.anchor = self.cursor;
-                return;
-            }
+    /// Replaces the whole text with `text` as one undoable edit that is never grouped with
+    /// typing, e.g. to reload a file another program changed. Only the part that differs is
+    /// replaced, so selections before and after it stay on the same text.
+    pub fn replace_all(&mut self, text: &str) {
+        let old = self.buffer.slice(0..self.buffer.len());
+        let mut prefix = old
+            .bytes()
+            .zip(text.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(prefix) {
+            prefix -= 1;
         }
-
-        self.cursor = next_char_boundary(&self.text, self.cursor);
-        if !extend_selection {
-            self.anchor = self.cursor;
+        let mut suffix = old[prefix..]
+            .bytes()
+            .rev()
+            .zip(text[prefix..].bytes().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(old.len() - suffix) {
+            suffix -= 1;
         }
+        let replaced = prefix..old.len() - suffix;
+        self.edit_ranges([(replaced, text[prefix..text.len() - suffix].to_string())]);
     }
 
-    pub fn select_all(&mut self) {
-        self.anchor = 0;
-        self.cursor = self.text.len();
+    pub fn undo(&mut self) -> bool {
+        let Some(transaction) = self.history.pop_undo() else {
+            return false;
+        };
+        let post_starts = transaction.post_edit_starts();
+        for (edit, &start) in transaction.edits.iter().zip(&post_starts).rev() {
+            self.replace_buffer(start..start + edit.new_text.len(), &edit.old_text);
+        }
+        self.restore_selections(transaction.selection_before);
+        true
     }
 
-    pub fn replace_range(&mut self, range: Range<usize>, new_text: &str) {
-        let range = normalize_range_to_char_boundaries(&self.text, range);
-        if range.start > range.end || range.end > self.text.len() {
-            return;
+    pub fn redo(&mut self) -> bool {
+        let Some(transaction) = self.history.pop_redo() else {
+            return false;
+        };
+        for edit in transaction.edits.iter().rev() {
+            self.replace_buffer(edit.old_range(), &edit.new_text);
         }
+        self.restore_selections(transaction.selection_after);
+        true
+    }
 
-        let mut next =
-            String::with_capacity(self.text.len() - (range.end - range.start) + new_text.len());
-        next.push_str(&self.text[..range.start]);
-        next.push_str(new_text);
-        next.push_str(&self.text[range.end..]);
-        self.text = next;
+    fn replace_buffer(&mut self, range: Range<usize>, new_text: &str) {
+        let edit = self.buffer.replace(range, new_text);
+        self.buffer_edits.push(edit);
+    }
 
-        let next_cursor = range.start + new_text.len();
-        self.cursor = next_cursor.min(self.text.len());
-        self.anchor = self.cursor;
+    /// Exchanges the selections edits and motions apply to with `set`, so that views sharing the
+    /// buffer and its history each keep their own. Selections swapped in are clamped to the
+    /// buffer.
+    pub fn swap_selections(&mut self, set: &mut SelectionSet) {
+        std::mem::swap(&mut self.selections, &mut set.selections);
+        std::mem::swap(&mut self.primary, &mut set.primary);
+        for selection in &mut self.selections {
+            selection.anchor = self.buffer.floor_char_boundary(selection.anchor);
+            selection.cursor = self.buffer.floor_char_boundary(selection.cursor);
+        }
+        self.primary = normalize_selections(&mut self.selections, self.primary);
     }
-}
 
-fn prev_char_boundary(s: &str, idx: usize) -> usize {
-    let idx = idx.min(s.len());
-    if idx == 0 {
-        return 0;
+    /// Stops the next edit from being undone together with the previous one, e.g. when it is
+    /// made through another view.
+    pub fn break_undo_group(&mut self) {
+        self.history.break_group();
     }
-    let mut i = idx - 1;
-    while i > 0 && !s.is_char_boundary(i) {
-        i -= 1;
+
+    /// Drains the buffer edits applied since the previous call, oldest first, so views can update
+    /// derived state incrementally.
+    pub fn take_buffer_edits(&mut self) -> Vec<BufferEdit> {
+        std::mem::take(&mut self.buffer_edits)
     }
-    i
-}
 
-fn next_char_boundary(s: &str, idx: usize) -> usize {
-    let idx = idx.min(s.len());
-    if idx == s.len() {
-        return idx;
+    fn selection_snapshot(&self) -> SelectionSnapshot {
+        let selections = self
+            .selections
+            .iter()
+            .map(|selection| Selection::new(selection.anchor, selection.cursor))
+            .collect();
+        (selections, self.primary)
     }
-    let mut i = idx + 1;
-    while i < s.len() && !s.is_char_boundary(i) {
-        i += 1;
+
+    fn restore_selections(&mut self, (selections, primary): SelectionSnapshot) {
+        self.selections = selections
+            .into_iter()
+            .map(|selection| {
+                Selection::new(
+                    self.buffer.floor_char_boundary(selection.anchor),
+                    self.buffer.floor_char_boundary(selection.cursor),
+                )
+            })
+            .collect();
+        self.primary = normalize_selections(&mut self.selections, primary);
     }
-    i.min(s.len())
 }
 
-fn normalize_range_to_char_boundaries(s: &str, range: Range<usize>) -> Range<usize> {
-    let mut start = range.start.min(s.len());
-    let mut end = range.end.min(s.len());
-    if start > end {
-        (start, end) = (end, start);
+fn moved(selection: &Selection, cursor: usize, extend_selection: bool) -> Selection {
+    let anchor = if extend_selection {
+        selection.anchor
+    } else {
+        cursor
+    };
+    Selection::new(anchor, cursor)
+}
+
+fn vertically_moved(
+    buffer: &TextBuffer,
+    selection: &Selection,
+    direction: isize,
+    extend_selection: bool,
+    layout: &dyn LineLayout,
+) -> Selection {
+    let (line, column) = buffer.offset_to_point(selection.cursor);
+    let goal_x = selection
+        .goal_x
+        .unwrap_or_else(|| layout.x_for_column(line, column));
+    let cursor = match adjacent_row(buffer, layout, line, column, direction) {
+        Some((line, row)) => buffer.point_to_offset(line, layout.column_for_x(line, row, goal_x)),
+        None if direction < 0 => 0,
+        None => buffer.len(),
+    };
+    Selection {
+        goal_x: Some(goal_x),
+        ..moved(selection, cursor, extend_selection)
     }
-    while start > 0 && !s.is_char_boundary(start) {
-        start -= 1;
+}
+
+/// The `(line, visual row)` one row above (`direction < 0`) or below the row holding `column`,
+/// or `None` at the start or end of the buffer.
+fn adjacent_row(
+    buffer: &TextBuffer,
+    layout: &dyn LineLayout,
+    line: usize,
+    column: usize,
+    direction: isize,
+) -> Option<(usize, usize)> {
+    let row = layout.row_for_column(line, column);
+    if direction < 0 {
+        if row > 0 {
+            return Some((line, row - 1));
+        }
+        let line = line.checked_sub(1)?;
+        Some((line, layout.row_count(line) - 1))
+    } else if row + 1 < layout.row_count(line) {
+        Some((line, row + 1))
+    } else {
+        Some(line + 1)
+            .filter(|&line| line < buffer.line_count())
+            .map(|line| (line, 0))
     }
-    while end < s.len() && !s.is_char_boundary(end) {
-        end += 1;
+}
+
+/// Maps an offset from before `edits` were applied to after. Offsets inside a replaced range move
+/// to the end of its replacement.
+fn map_offset(edits: &[Edit], post_starts: &[usize], offset: usize) -> usize {
+    let mut mapped = offset;
+    for (edit, &post_start) in edits.iter().zip(post_starts) {
+        let old_range = edit.old_range();
+        if offset < old_range.start || (offset == old_range.start && !old_range.is_empty()) {
+            break;
+        }
+        if offset < old_range.end {
+            return post_start + edit.new_text.len();
+        }
+        mapped = offset - old_range.end + post_start + edit.new_text.len();
     }
-    start..end.min(s.len())
+    mapped
 }
 
 #[cfg(test)]
 mod tests {
     use super::EditorState;
+    use crate::editor::movement::{SelectionGranularity, granular_range_at};
+    use crate::editor::{MonospaceLayout, SelectionSet};
 
     #[test]
     fn inserts_characters() {
@@ -242,4 +811,417 @@ mod tests {
         assert_eq!(editor.text(), "a\nb");
         assert_eq!(editor.cursor(), 3);
     }
+
+    #[test]
+    fn undo_groups_consecutive_typing_and_restores_selection() {
+        let mut editor = EditorState::new();
+        editor.insert_str("a");
+        editor.insert_str("b");
+        editor.insert_str("c");
+        editor.select_all();
+        editor.insert_str("x");
+        assert_eq!(editor.text(), "x");
+
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "abc");
+        assert_eq!(editor.selection_range(), 0..3);
+
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "");
+        assert_eq!(editor.cursor(), 0);
+        assert!(!editor.undo());
+
+        assert!(editor.redo());
+        assert_eq!(editor.text(), "abc");
+        assert_eq!(editor.cursor(), 3);
+        assert!(editor.redo());
+        assert_eq!(editor.text(), "x");
+        assert!(!editor.redo());
+    }
+
+    #[test]
+    fn cursor_movement_and_newlines_break_undo_groups() {
+        let mut editor = EditorState::new();
+        editor.insert_str("a");
+        editor.insert_str("b");
+        editor.insert_str("\n");
+        editor.insert_str("c");
+        editor.move_left(false);
+        editor.insert_str("d");
+        assert_eq!(editor.text(), "ab\ndc");
+
+        editor.undo();
+        assert_eq!(editor.text(), "ab\nc");
+        editor.undo();
+        assert_eq!(editor.text(), "ab\n");
+        editor.undo();
+        assert_eq!(editor.text(), "ab");
+        editor.undo();
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn backspaces_group_and_new_edits_clear_redo() {
+        let mut editor = EditorState::new();
+        editor.insert_str("hello");
+        editor.move_left(false);
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(editor.text(), "heo");
+        editor.undo();
+        assert_eq!(editor.text(), "hello");
+        assert_eq!(editor.cursor(), 4);
+
+        editor.insert_str("!");
+        assert!(!editor.redo());
+        assert_eq!(editor.text(), "hell!o");
+    }
+
+    #[test]
+    fn vertical_motion_keeps_goal_column_across_short_lines() {
+        let mut editor = EditorState::new();
+        editor.insert_str("hello world\nhi\nsomething longer");
+        editor.set_cursor(8);
+        let buffer = editor.buffer().clone();
+        let layout = MonospaceLayout::new(&buffer);
+
+        editor.move_down(false, &layout);
+        assert_eq!(editor.cursor(), 14);
+        editor.move_down(false, &layout);
+        assert_eq!(editor.cursor(), 23);
+        editor.move_up(false, &layout);
+        editor.move_up(false, &layout);
+        assert_eq!(editor.cursor(), 8);
+
+        editor.move_up(true, &layout);
+        assert_eq!(editor.cursor(), 0);
+        assert_eq!(editor.selection_range(), 0..8);
+    }
+
+    #[test]
+    fn vertical_motion_follows_soft_wrapped_rows() {
+        let mut editor = EditorState::new();
+        editor.insert_str("abcdefghij\nxy");
+        editor.set_cursor(2);
+        // Goal columns are kept in display x, here 8 per char.
+        let buffer = editor.buffer().clone();
+        let layout = MonospaceLayout::new(&buffer).with_char_width(8.).wrapped(4);
+
+        editor.move_down(false, &layout);
+        assert_eq!(editor.cursor(), 6);
+        editor.move_down(false, &layout);
+        assert_eq!(editor.cursor(), 10);
+        editor.move_down(false, &layout);
+        assert_eq!(editor.cursor(), 13);
+        editor.move_up(false, &layout);
+        assert_eq!(editor.cursor(), 10);
+        editor.move_up(false, &layout);
+        editor.move_up(false, &layout);
+        assert_eq!(editor.cursor(), 2);
+    }
+
+    #[test]
+    fn line_and_document_motions() {
+        let mut editor = EditorState::new();
+        editor.insert_str("first\nsecond");
+        editor.set_cursor(9);
+        editor.move_to_line_start(false);
+        assert_eq!(editor.cursor(), 6);
+        editor.move_to_line_end(true);
+        assert_eq!(editor.selection_range(), 6..12);
+        editor.move_to_beginning(true);
+        assert_eq!(editor.selection_range(), 0..6);
+        editor.move_to_end(false);
+        assert_eq!(editor.cursor(), 12);
+        assert!(editor.selection_range().is_empty());
+    }
+
+    #[test]
+    fn word_motions_use_unicode_word_boundaries() {
+        let mut editor = EditorState::new();
+        editor.insert_str("café au-lait, 漢字\nnext");
+        editor.set_cursor(0);
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor(), "café".len());
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor(), "café au".len());
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor(), "café au-lait".len());
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor(), "café au-lait, 漢".len());
+
+        editor.move_to_end(false);
+        editor.move_to_prev_word_start(false);
+        assert_eq!(editor.cursor(), "café au-lait, 漢字\n".len());
+        editor.move_to_prev_word_start(true);
+        assert_eq!(editor.cursor(), "café au-lait, 漢".len());
+        assert_eq!(editor.selection_range().len(), "字\n".len());
+    }
+
+    #[test]
+    fn word_deletion_removes_whole_words() {
+        let mut editor = EditorState::new();
+        editor.insert_str("hello brave world");
+        editor.delete_to_prev_word_start();
+        assert_eq!(editor.text(), "hello brave ");
+        editor.set_cursor(0);
+        editor.delete_to_next_word_end();
+        assert_eq!(editor.text(), " brave ");
+    }
+
+    #[test]
+    fn grapheme_clusters_move_and_delete_as_one_unit() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let accented = "e\u{301}";
+        let mut editor = EditorState::new();
+        editor.insert_str(&format!("a{family}{accented}"));
+        editor.move_left(false);
+        assert_eq!(editor.cursor(), 1 + family.len());
+        editor.move_left(false);
+        assert_eq!(editor.cursor(), 1);
+        editor.move_right(false);
+        assert_eq!(editor.cursor(), 1 + family.len());
+
+        editor.move_to_end(false);
+        editor.backspace();
+        assert_eq!(editor.text(), format!("a{family}"));
+        editor.backspace();
+        assert_eq!(editor.text(), "a");
+    }
+
+    #[test]
+    fn paragraph_motions_jump_between_blank_lines() {
+        let mut editor = EditorState::new();
+        editor.insert_str("one\ntwo\n\nthree\n\n\nfour");
+        editor.set_cursor(0);
+        editor.move_to_next_paragraph(false);
+        assert_eq!(editor.cursor(), 8);
+        editor.move_to_next_paragraph(false);
+        assert_eq!(editor.cursor(), 15);
+        editor.move_to_next_paragraph(false);
+        assert_eq!(editor.cursor(), editor.len());
+        editor.move_to_prev_paragraph(false);
+        assert_eq!(editor.cursor(), 16);
+        editor.move_to_prev_paragraph(false);
+        assert_eq!(editor.cursor(), 8);
+        editor.move_to_prev_paragraph(false);
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn edits_apply_to_every_selection_and_undo_together() {
+        let mut editor = EditorState::new();
+        editor.insert_str("a\nb\nc");
+        editor.set_cursor(1);
+        editor.add_selection(3, 3);
+        editor.add_selection(5, 5);
+        editor.insert_str("!");
+        editor.insert_str("?");
+        assert_eq!(editor.text(), "a!?\nb!?\nc!?");
+        assert_eq!(
+            editor
+                .selections()
+                .iter()
+                .map(|s| s.cursor)
+                .collect::<Vec<_>>(),
+            vec![3, 7, 11]
+        );
+
+        editor.backspace();
+        assert_eq!(editor.text(), "a!\nb!\nc!");
+
+        editor.undo();
+        assert_eq!(editor.text(), "a!?\nb!?\nc!?");
+        editor.undo();
+        assert_eq!(editor.text(), "a\nb\nc");
+        assert_eq!(editor.selections().len(), 3);
+        assert_eq!(editor.cursor(), 5);
+    }
+
+    #[test]
+    fn input_methods_type_and_compose_at_every_selection() {
+        let mut editor = EditorState::new();
+        editor.insert_str("a\nb\nc");
+        editor.set_selection(0, 1);
+        editor.add_selection(4, 5);
+        editor.add_selection(3, 3);
+        let inserted = editor.replace_input(None, &[], "x");
+        assert_eq!(editor.text(), "x\nbx\nx");
+        assert_eq!(inserted, [0..1, 3..4, 5..6]);
+        assert_eq!(editor.selections().len(), 3);
+        assert_eq!(editor.selection_range(), 4..4);
+        editor.undo();
+        assert_eq!(editor.text(), "a\nb\nc");
+        editor.redo();
+
+        // Composing marks the text at each cursor and replaces it until it is committed.
+        let marked = editor.replace_input(None, &[], "n");
+        assert_eq!(marked, [1..2, 5..6, 8..9]);
+        let marked = editor.replace_input(None, &marked, "ni");
+        assert_eq!(editor.text(), "xni\nbxni\nxni");
+        editor.select_before_cursors(2, 0..1);
+        assert_eq!(editor.selection_range(), 6..7);
+        let committed = editor.replace_input(None, &marked, "你");
+        assert_eq!(editor.text(), "x你\nbx你\nx你");
+        assert_eq!(committed, [1..4, 7..10, 12..15]);
+        assert_eq!(editor.selections().len(), 3);
+
+        // An explicit range replaces just that range.
+        editor.replace_input(Some(0..1), &[], "y");
+        assert_eq!(editor.text(), "y你\nbx你\nx你");
+        assert_eq!(editor.selections().len(), 1);
+
+        editor.undo();
+        editor.undo();
+        editor.undo();
+        editor.undo();
+        editor.undo();
+        assert_eq!(editor.text(), "a\nb\nc");
+    }
+
+    #[test]
+    fn replacing_all_text_is_one_undoable_edit_of_the_changed_part() {
+        let mut editor = EditorState::new();
+        editor.insert_str("one\ntwo é\nthree");
+        editor.set_cursor(editor.len());
+        editor.replace_all("one\ntwo è, edited\nthree");
+        assert_eq!(editor.text(), "one\ntwo è, edited\nthree");
+        assert_eq!(editor.cursor(), editor.len());
+        let edits = editor.take_buffer_edits();
+        assert_eq!(edits.last().map(|edit| edit.start), Some("one\ntwo ".len()));
+
+        editor.undo();
+        assert_eq!(editor.text(), "one\ntwo é\nthree");
+        editor.undo();
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn overlapping_selections_merge_after_motion() {
+        let mut editor = EditorState::new();
+        editor.insert_str("abc");
+        editor.set_cursor(1);
+        editor.add_selection(2, 2);
+        editor.move_to_line_start(false);
+        assert_eq!(editor.selections().len(), 1);
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn add_cursor_below_and_select_next_occurrence() {
+        let mut editor = EditorState::new();
+        editor.insert_str("foo bar\nx\nfoo baz foo");
+        let buffer = editor.buffer().clone();
+        let layout = MonospaceLayout::new(&buffer);
+
+        editor.set_cursor(5);
+        editor.add_cursor_below(&layout);
+        editor.add_cursor_below(&layout);
+        let cursors: Vec<_> = editor.selections().iter().map(|s| s.cursor).collect();
+        assert_eq!(cursors, vec![5, 9, 15]);
+
+        editor.set_cursor(1);
+        editor.select_next_occurrence();
+        assert_eq!(editor.selection_range(), 0..3);
+        editor.select_next_occurrence();
+        editor.select_next_occurrence();
+        editor.select_next_occurrence();
+        let ranges: Vec<_> = editor.selections().iter().map(|s| s.range()).collect();
+        assert_eq!(ranges, vec![0..3, 10..13, 18..21]);
+
+        editor.insert_str("qux");
+        assert_eq!(editor.text(), "qux bar\nx\nqux baz qux");
+    }
+
+    #[test]
+    fn column_selection_spans_lines() {
+        let mut editor = EditorState::new();
+        editor.insert_str("abcdef\nab\nabcdef");
+        let buffer = editor.buffer().clone();
+        let layout = MonospaceLayout::new(&buffer);
+
+        editor.select_columns((0, 1.), (2, 4.), &layout);
+        let ranges: Vec<_> = editor.selections().iter().map(|s| s.range()).collect();
+        assert_eq!(ranges, vec![1..4, 8..9, 11..14]);
+        assert_eq!(editor.cursor(), 14);
+    }
+
+    #[test]
+    fn granular_selection_extends_by_whole_units() {
+        let mut editor = EditorState::new();
+        editor.insert_str("one two three\nfour\n\nfive");
+
+        let word = granular_range_at(editor.buffer(), 5, SelectionGranularity::Word);
+        assert_eq!(word, 4..7);
+        editor.select_granular(word.clone(), 10, SelectionGranularity::Word);
+        assert_eq!(editor.selection_range(), 4..13);
+        editor.select_granular(word, 1, SelectionGranularity::Word);
+        assert_eq!(editor.selection_range(), 0..7);
+        assert!(editor.selection_reversed());
+
+        let line = granular_range_at(editor.buffer(), 2, SelectionGranularity::Line);
+        assert_eq!(line, 0..14);
+        editor.select_granular(line, 15, SelectionGranularity::Line);
+        assert_eq!(editor.selection_range(), 0..19);
+
+        let paragraph = granular_range_at(editor.buffer(), 15, SelectionGranularity::Paragraph);
+        assert_eq!(paragraph, 0..19);
+        assert_eq!(
+            granular_range_at(editor.buffer(), 3, SelectionGranularity::Word),
+            0..3
+        );
+    }
+
+    #[test]
+    fn views_keep_their_own_selections_across_each_others_edits() {
+        let mut editor = EditorState::new();
+        editor.insert_str("one two");
+        editor.take_buffer_edits();
+        let mut first = SelectionSet::default();
+        let mut second = SelectionSet::default();
+
+        // The second view selects "two"; the first types at the start.
+        editor.swap_selections(&mut second);
+        editor.set_selection(4, 7);
+        editor.swap_selections(&mut second);
+        editor.swap_selections(&mut first);
+        editor.set_cursor(0);
+        editor.insert_str(">> ");
+        editor.swap_selections(&mut first);
+        for edit in editor.take_buffer_edits() {
+            second.apply_buffer_edit(&edit);
+        }
+        assert_eq!(editor.text(), ">> one two");
+        assert_eq!(first.cursor(), 3);
+        assert_eq!(second.primary().range(), 7..10);
+
+        // Deleting around the second view's selection pulls it back to the deletion.
+        editor.swap_selections(&mut first);
+        editor.set_selection(5, 10);
+        editor.backspace();
+        editor.swap_selections(&mut first);
+        for edit in editor.take_buffer_edits() {
+            second.apply_buffer_edit(&edit);
+        }
+        assert_eq!(editor.text(), ">> on");
+        assert_eq!(second.primary().range(), 5..5);
+    }
+
+    #[test]
+    fn range_edits_undo_as_one_step_and_keep_selections() {
+        let mut editor = EditorState::new();
+        editor.insert_str("see [[a]] and [[a|b]]");
+        editor.insert_str("!");
+        editor.set_selection(10, 13);
+        editor.edit_ranges([(6..7, "plan".to_string()), (16..17, "plan".to_string())]);
+        assert_eq!(editor.text(), "see [[plan]] and [[plan|b]]!");
+        assert_eq!(editor.selected_text(), "and");
+
+        editor.insert_str("or");
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "see [[plan]] and [[plan|b]]!");
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "see [[a]] and [[a|b]]!");
+        assert_eq!(editor.selected_text(), "and");
+    }
 }
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use futures::channel::mpsc::UnboundedSender;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Reports changes to one file, whoever makes them. Stops when dropped.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    /// Sends on `changes` whenever the file at `path` may have changed. The file's directory is
+    /// watched rather than the file itself, so replacing it with a rename, as atomic saves by
+    /// Zeno and most other tools do, is seen too.
+    pub fn new(path: &Path, changes: UnboundedSender<()>) -> notify::Result<Self> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !event.kind.is_access() && event.paths.contains(&path) {
+                changes.unbounded_send(()).ok();
+            }
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Reports changes anywhere inside a folder, such as a vault. Stops when dropped.
+pub struct TreeWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl TreeWatcher {
+    /// Sends the paths of the files and folders in `dir` that may have changed on `changes`.
+    /// Hidden files, such as the temporary files of atomic saves, are reported too.
+    pub fn new(dir: &Path, changes: UnboundedSender<Vec<PathBuf>>) -> notify::Result<Self> {
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !event.kind.is_access() && !event.paths.is_empty() {
+                changes.unbounded_send(event.paths).ok();
+            }
+        })?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        Ok(Self { _watcher: watcher })
+    }
+}
@@ -1,26 +1,110 @@
 mod editor;
 mod markdown;
+mod settings;
 mod ui;
+mod vault;
+
+use std::path::PathBuf;
 
 use gpui::AppContext;
-use gpui::{
-    App, Application, Bounds, Focusable, KeyBinding, WindowBounds, WindowOptions, px, size,
-};
+use gpui::{App, Application, Bounds, KeyBinding, WindowBounds, WindowOptions, px, size};
 
+use settings::AutosaveSettings;
 use ui::Workspace;
-use ui::editor::{Backspace, Delete, Left, Newline, Right, SelectAll, SelectLeft, SelectRight};
+use ui::editor::{
+    AddCursorAbove, AddCursorBelow, Backspace, Cancel, ConfirmCompletion, Copy, Cut, Delete,
+    DeleteToNextWordEnd, DeleteToPreviousWordStart, Down, End, Home, Left, MoveToBeginning,
+    MoveToEnd, MoveToNextParagraph, MoveToNextWordEnd, MoveToPreviousParagraph,
+    MoveToPreviousWordStart, Newline, NextCompletion, Paste, PreviousCompletion, Redo, Right, Save,
+    SaveAs, SelectAll, SelectDown, SelectEnd, SelectHome, SelectLeft, SelectNextOccurrence,
+    SelectRight, SelectToBeginning, SelectToEnd, SelectToNextParagraph, SelectToNextWordEnd,
+    SelectToPreviousParagraph, SelectToPreviousWordStart, SelectUp, ToggleReadableWidth, Undo, Up,
+};
+use ui::file_tree::{DeleteEntry, NewFolder, NewNote, RenameEntry};
+use ui::workspace::{
+    ActivatePaneDown, ActivatePaneLeft, ActivatePaneRight, ActivatePaneUp, CloseTab, Open,
+    OpenFolder, SplitDown, SplitRight,
+};
 
 fn main() {
     Application::new().run(|cx: &mut App| {
+        cx.set_global(AutosaveSettings::from_env());
         cx.bind_keys([
             KeyBinding::new("backspace", Backspace, None),
             KeyBinding::new("delete", Delete, None),
+            KeyBinding::new("alt-backspace", DeleteToPreviousWordStart, None),
+            KeyBinding::new("ctrl-backspace", DeleteToPreviousWordStart, None),
+            KeyBinding::new("alt-delete", DeleteToNextWordEnd, None),
+            KeyBinding::new("ctrl-delete", DeleteToNextWordEnd, None),
             KeyBinding::new("left", Left, None),
             KeyBinding::new("right", Right, None),
+            KeyBinding::new("up", Up, None),
+            KeyBinding::new("down", Down, None),
+            KeyBinding::new("home", Home, None),
+            KeyBinding::new("end", End, None),
+            KeyBinding::new("cmd-left", Home, None),
+            KeyBinding::new("cmd-right", End, None),
+            KeyBinding::new("cmd-up", MoveToBeginning, None),
+            KeyBinding::new("cmd-down", MoveToEnd, None),
+            KeyBinding::new("alt-left", MoveToPreviousWordStart, None),
+            KeyBinding::new("ctrl-left", MoveToPreviousWordStart, None),
+            KeyBinding::new("alt-right", MoveToNextWordEnd, None),
+            KeyBinding::new("ctrl-right", MoveToNextWordEnd, None),
+            KeyBinding::new("alt-up", MoveToPreviousParagraph, None),
+            KeyBinding::new("alt-down", MoveToNextParagraph, None),
             KeyBinding::new("shift-left", SelectLeft, None),
             KeyBinding::new("shift-right", SelectRight, None),
+            KeyBinding::new("shift-up", SelectUp, None),
+            KeyBinding::new("shift-down", SelectDown, None),
+            KeyBinding::new("shift-home", SelectHome, None),
+            KeyBinding::new("shift-end", SelectEnd, None),
+            KeyBinding::new("cmd-shift-left", SelectHome, None),
+            KeyBinding::new("cmd-shift-right", SelectEnd, None),
+            KeyBinding::new("cmd-shift-up", SelectToBeginning, None),
+            KeyBinding::new("cmd-shift-down", SelectToEnd, None),
+            KeyBinding::new("alt-shift-left", SelectToPreviousWordStart, None),
+            KeyBinding::new("ctrl-shift-left", SelectToPreviousWordStart, None),
+            KeyBinding::new("alt-shift-right", SelectToNextWordEnd, None),
+            KeyBinding::new("ctrl-shift-right", SelectToNextWordEnd, None),
+            KeyBinding::new("alt-shift-up", SelectToPreviousParagraph, None),
+            KeyBinding::new("alt-shift-down", SelectToNextParagraph, None),
             KeyBinding::new("cmd-a", SelectAll, None),
             KeyBinding::new("enter", Newline, None),
+            KeyBinding::new("cmd-z", Undo, None),
+            KeyBinding::new("cmd-shift-z", Redo, None),
+            KeyBinding::new("cmd-x", Cut, None),
+            KeyBinding::new("cmd-c", Copy, None),
+            KeyBinding::new("cmd-v", Paste, None),
+            KeyBinding::new("cmd-alt-up", AddCursorAbove, None),
+            KeyBinding::new("cmd-alt-down", AddCursorBelow, None),
+            KeyBinding::new("cmd-d", SelectNextOccurrence, None),
+            KeyBinding::new("escape", Cancel, None),
+            KeyBinding::new("cmd-alt-w", ToggleReadableWidth, None),
+            KeyBinding::new("cmd-o", Open, None),
+            KeyBinding::new("cmd-s", Save, None),
+            KeyBinding::new("cmd-shift-s", SaveAs, None),
+            KeyBinding::new("cmd-shift-o", OpenFolder, None),
+            KeyBinding::new("cmd-w", CloseTab, None),
+            KeyBinding::new("cmd-\\", SplitRight, None),
+            KeyBinding::new("cmd-shift-\\", SplitDown, None),
+            KeyBinding::new("cmd-k left", ActivatePaneLeft, None),
+            KeyBinding::new("cmd-k right", ActivatePaneRight, None),
+            KeyBinding::new("cmd-k up", ActivatePaneUp, None),
+            KeyBinding::new("cmd-k down", ActivatePaneDown, None),
+            KeyBinding::new("up", PreviousCompletion, Some("ZenoEditor && completing")),
+            KeyBinding::new("down", NextCompletion, Some("ZenoEditor && completing")),
+            KeyBinding::new("enter", ConfirmCompletion, Some("ZenoEditor && completing")),
+            KeyBinding::new("tab", ConfirmCompletion, Some("ZenoEditor && completing")),
+            KeyBinding::new("cmd-n", NewNote, Some("ZenoFileTree")),
+            KeyBinding::new("cmd-shift-n", NewFolder, Some("ZenoFileTree")),
+            KeyBinding::new("enter", RenameEntry, Some("ZenoFileTree && !renaming")),
+            KeyBinding::new("f2", RenameEntry, Some("ZenoFileTree && !renaming")),
+            KeyBinding::new(
+                "cmd-backspace",
+                DeleteEntry,
+                Some("ZenoFileTree && !renaming"),
+            ),
+            KeyBinding::new("delete", DeleteEntry, Some("ZenoFileTree && !renaming")),
         ]);
 
         let bounds = Bounds::centered(None, size(px(1200.), px(800.)), cx);
@@ -30,12 +114,18 @@ fn main() {
                     window_bounds: Some(WindowBounds::Windowed(bounds)),
                     ..Default::default()
                 },
-                |_, cx| cx.new(Workspace::new),
+                |window, cx| cx.new(|cx| Workspace::new(window, cx)),
             )
             .unwrap();
         window
             .update(cx, |workspace, window, cx| {
-                window.focus(&workspace.editor.focus_handle(cx));
+                // `zeno <folder>` opens the folder as the vault.
+                if let Some(root) = std::env::args_os().nth(1).map(PathBuf::from)
+                    && root.is_dir()
+                {
+                    workspace.open_vault(root, cx);
+                }
+                workspace.focus_active_editor(window, cx);
                 cx.activate(true);
             })
             .unwrap();
@@ -0,0 +1,282 @@
+use std::ops::Range;
+
+use tree_sitter::{Node, Tree};
+
+use super::highlight::heading_level;
+
+/// A construct rendered in place: the syntax markers to hide when the cursor is elsewhere and how
+/// to style what remains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoration {
+    /// The whole construct, e.g. all of `**bold**`.
+    pub full_range: Range<usize>,
+    /// Markers hidden from the rendered line, e.g. each `**`. Sorted and disjoint.
+    pub concealed: Vec<Range<usize>>,
+    pub style: DecorationStyle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecorationStyle {
+    Heading { level: u8 },
+    Bold,
+    Italic,
+    InlineCode,
+    Link { url: String },
+}
+
+impl Decoration {
+    pub(crate) fn shifted(&self, by: usize) -> Self {
+        Self {
+            full_range: self.full_range.start + by..self.full_range.end + by,
+            concealed: self
+                .concealed
+                .iter()
+                .map(|range| range.start + by..range.end + by)
+                .collect(),
+            style: self.style.clone(),
+        }
+    }
+}
+
+/// Decorations for a parsed document: ATX headings from the block tree, emphasis, code spans and
+/// inline links from the inline trees. Sorted by `full_range.start`.
+pub(crate) fn markdown_decorations(
+    block_tree: &Tree,
+    inline_trees: &[Tree],
+    source: &str,
+) -> Vec<Decoration> {
+    let mut decorations = Vec::new();
+    visit(block_tree.root_node(), &mut |node| {
+        decorations.extend(heading_decoration(node));
+    });
+    for inline_tree in inline_trees {
+        visit(inline_tree.root_node(), &mut |node| {
+            decorations.extend(inline_decoration(node, source));
+        });
+    }
+    decorations.sort_by_key(|decoration| (decoration.full_range.start, decoration.full_range.end));
+    decorations
+}
+
+fn visit<'tree>(node: Node<'tree>, f: &mut impl FnMut(Node<'tree>)) {
+    f(node);
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit(child, f);
+    }
+}
+
+/// `# Title` conceals the marker and the whitespace after it. Headings without text are left
+/// alone so the line does not render empty.
+fn heading_decoration(node: Node) -> Option<Decoration> {
+    if node.kind() != "atx_heading" {
+        return None;
+    }
+    let level = heading_level(node)?;
+    let mut cursor = node.walk();
+    let marker = node
+        .children(&mut cursor)
+        .find(|child| child.kind().starts_with("atx_h"))?;
+    let content = node.child_by_field_name("heading_content")?;
+    Some(Decoration {
+        full_range: marker.start_byte()..content.end_byte(),
+        concealed: std::iter::once(marker.start_byte()..content.start_byte()).collect(),
+        style: DecorationStyle::Heading { level },
+    })
+}
+
+fn inline_decoration(node: Node, source: &str) -> Option<Decoration> {
+    let (delimiter, style) = match node.kind() {
+        "strong_emphasis" => ("emphasis_delimiter", DecorationStyle::Bold),
+        "emphasis" => ("emphasis_delimiter", DecorationStyle::Italic),
+        "code_span" => ("code_span_delimiter", DecorationStyle::InlineCode),
+        "inline_link" => return link_decoration(node, source),
+        _ => return None,
+    };
+    // Delimiters are direct children; those of nested emphasis belong to the nested node.
+    let mut cursor = node.walk();
+    let mut concealed: Vec<Range<usize>> = Vec::new();
+    for child in node.children(&mut cursor) {
+        if child.kind() != delimiter {
+            continue;
+        }
+        match concealed.last_mut() {
+            Some(last) if last.end == child.start_byte() => last.end = child.end_byte(),
+            _ => concealed.push(child.byte_range()),
+        }
+    }
+    Some(Decoration {
+        full_range: node.byte_range(),
+        concealed,
+        style,
+    })
+}
+
+/// `[text](url "title")` keeps only `text`.
+fn link_decoration(node: Node, source: &str) -> Option<Decoration> {
+    let mut cursor = node.walk();
+    let children = node.children(&mut cursor).collect::<Vec<_>>();
+    let text = children.iter().find(|child| child.kind() == "link_text")?;
+    let url = children
+        .iter()
+        .find(|child| child.kind() == "link_destination")
+        .and_then(|destination| source.get(destination.byte_range()))
+        .unwrap_or_default()
+        .to_string();
+    Some(Decoration {
+        full_range: node.byte_range(),
+        concealed: vec![
+            node.start_byte()..text.start_byte(),
+            text.end_byte()..node.end_byte(),
+        ],
+        style: DecorationStyle::Link { url },
+    })
+}
+
+/// One line as rendered with its markers concealed, and the mapping between byte offsets into the
+/// raw line and into `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcealedLine {
+    pub text: String,
+    /// Line-relative ranges removed from the raw line. Sorted and disjoint.
+    concealed: Vec<Range<usize>>,
+}
+
+impl ConcealedLine {
+    /// Conceals the markers of every decoration in `decorations` that fall within `raw`, the text
+    /// of the line starting at buffer offset `line_start`.
+    pub fn new(raw: &str, line_start: usize, decorations: &[Decoration]) -> Self {
+        let line_end = line_start + raw.len();
+        let mut concealed = decorations
+            .iter()
+            .flat_map(|decoration| &decoration.concealed)
+            .filter(|range| range.start < line_end && range.end > line_start)
+            .map(|range| {
+                range.start.max(line_start) - line_start..range.end.min(line_end) - line_start
+            })
+            .filter(|range| raw.is_char_boundary(range.start) && raw.is_char_boundary(range.end))
+            .collect::<Vec<_>>();
+        concealed.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(concealed.len());
+        for range in concealed {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        let mut text = String::with_capacity(raw.len());
+        let mut visible_from = 0;
+        for range in &merged {
+            text.push_str(&raw[visible_from..range.start]);
+            visible_from = range.end;
+        }
+        text.push_str(&raw[visible_from..]);
+
+        Self {
+            text,
+            concealed: merged,
+        }
+    }
+
+    pub fn is_concealing(&self) -> bool {
+        !self.concealed.is_empty()
+    }
+
+    /// Display offset of a raw line offset. Offsets inside a concealed marker map to where the
+    /// marker would have been.
+    pub fn display_offset(&self, raw: usize) -> usize {
+        let hidden: usize = self
+            .concealed
+            .iter()
+            .take_while(|range| range.start < raw)
+            .map(|range| range.end.min(raw) - range.start)
+            .sum();
+        raw - hidden
+    }
+
+    /// Raw line offset of a display offset: the position of the visible character displayed
+    /// there, i.e. after any markers concealed at that point.
+    pub fn raw_offset(&self, display: usize) -> usize {
+        let mut raw = display;
+        for range in &self.concealed {
+            if range.start > raw {
+                break;
+            }
+            raw += range.len();
+        }
+        raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConcealedLine, Decoration, DecorationStyle, markdown_decorations};
+    use tree_sitter_md::MarkdownParser;
+
+    fn decorations(source: &str) -> Vec<Decoration> {
+        let tree = MarkdownParser::default()
+            .parse(source.as_bytes(), None)
+            .unwrap();
+        markdown_decorations(tree.block_tree(), tree.inline_trees(), source)
+    }
+
+    fn conceal(source: &str) -> ConcealedLine {
+        ConcealedLine::new(source, 0, &decorations(source))
+    }
+
+    #[test]
+    fn emits_decorations_for_inline_constructs() {
+        let source = "## Title\n\nSome **bold**, *it*, `code` and [a link](https://zeno.dev).\n";
+        let decorations = decorations(source);
+        let styles = decorations
+            .iter()
+            .map(|decoration| &decoration.style)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            styles,
+            [
+                &DecorationStyle::Heading { level: 2 },
+                &DecorationStyle::Bold,
+                &DecorationStyle::Italic,
+                &DecorationStyle::InlineCode,
+                &DecorationStyle::Link {
+                    url: "https://zeno.dev".into()
+                },
+            ]
+        );
+        assert_eq!(decorations[0].concealed.first(), Some(&(0..3)));
+        let bold = &decorations[1];
+        assert_eq!(&source[bold.full_range.clone()], "**bold**");
+        assert_eq!(bold.concealed.len(), 2);
+    }
+
+    #[test]
+    fn conceals_markers_and_maps_offsets() {
+        assert_eq!(conceal("# Heading\n").text, "Heading\n");
+        assert_eq!(conceal("a **b** *c* `d` [e](f)").text, "a b c d e");
+
+        let line = conceal("x **bold** y");
+        assert_eq!(line.text, "x bold y");
+        assert_eq!(line.display_offset(0), 0);
+        assert_eq!(line.display_offset(3), 2);
+        assert_eq!(line.display_offset(4), 2);
+        assert_eq!(line.display_offset(8), 6);
+        assert_eq!(line.display_offset(12), 8);
+        assert_eq!(line.raw_offset(2), 4);
+        assert_eq!(line.raw_offset(6), 10);
+        assert_eq!(line.raw_offset(8), 12);
+        for display in 0..=line.text.len() {
+            assert_eq!(line.display_offset(line.raw_offset(display)), display);
+        }
+    }
+
+    #[test]
+    fn leaves_plain_lines_untouched() {
+        let line = conceal("just text");
+        assert!(!line.is_concealing());
+        assert_eq!(line.text, "just text");
+        assert_eq!(line.raw_offset(4), 4);
+    }
+}
@@ -1,15 +1,29 @@
 use std::ops::Range;
+use std::sync::OnceLock;
 
-use tree_sitter::{Parser, Query, QueryCursor, StreamingIterator};
+use tree_sitter::{Node, Query, QueryCursor, StreamingIterator, Tree};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use super::injection::injection_highlights;
+use super::links::find_wiki_links;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HighlightKind {
-    Heading,
+    Heading {
+        level: u8,
+    },
     Emphasis,
     Strong,
     Code,
     Link,
+    /// A whole `[[wiki link]]`. Its brackets are punctuation, and stay visible on lines whose
+    /// other markers are concealed.
+    WikiLink,
     Punctuation,
+    /// Tokens inside fenced code blocks, from the injected language's grammar.
+    Keyword,
+    String,
+    Comment,
+    Function,
     Other,
 }
 
@@ -19,31 +33,52 @@ pub struct HighlightSpan {
     pub kind: HighlightKind,
 }
 
-pub fn highlight_markdown(source: &str) -> Vec<HighlightSpan> {
-    if source.is_empty() {
-        return vec![];
-    }
-
-    let mut parser = Parser::new();
-    let language = tree_sitter_md::LANGUAGE.into();
-    if parser.set_language(&language).is_err() {
-        return vec![];
+/// Highlight spans for a parsed document: the block query over the block tree, the inline query
+/// over every inline tree, wiki links and the grammars injected into fenced code blocks. Sorted by
+/// `(start, end)` with duplicates removed.
+pub(crate) fn markdown_highlights(
+    block_tree: &Tree,
+    inline_trees: &[Tree],
+    source: &str,
+) -> Vec<HighlightSpan> {
+    let mut spans = Vec::new();
+    query_highlights(block_query(), block_tree.root_node(), source, &mut spans);
+    for inline_tree in inline_trees {
+        query_highlights(inline_query(), inline_tree.root_node(), source, &mut spans);
     }
+    injection_highlights(block_tree.root_node(), source, &mut spans);
+    wiki_link_highlights(source, &mut spans);
+    spans.sort_by_key(|span| (span.range.start, span.range.end, span.kind));
+    spans.dedup();
+    spans
+}
 
-    let tree = match parser.parse(source, None) {
-        Some(tree) => tree,
-        None => return vec![],
-    };
+/// Compiled queries are built once and shared by every syntax layer.
+fn block_query() -> &'static Query {
+    static QUERY: OnceLock<Query> = OnceLock::new();
+    QUERY.get_or_init(|| {
+        Query::new(
+            &tree_sitter_md::LANGUAGE.into(),
+            tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+        )
+        .expect("bundled Markdown block highlight query compiles")
+    })
+}
 
-    let query = match Query::new(&language, tree_sitter_md::HIGHLIGHT_QUERY_BLOCK) {
-        Ok(query) => query,
-        Err(_) => return vec![],
-    };
+fn inline_query() -> &'static Query {
+    static QUERY: OnceLock<Query> = OnceLock::new();
+    QUERY.get_or_init(|| {
+        Query::new(
+            &tree_sitter_md::INLINE_LANGUAGE.into(),
+            tree_sitter_md::HIGHLIGHT_QUERY_INLINE,
+        )
+        .expect("bundled Markdown inline highlight query compiles")
+    })
+}
 
+fn query_highlights(query: &Query, node: Node, source: &str, spans: &mut Vec<HighlightSpan>) {
     let mut cursor = QueryCursor::new();
-    let mut spans = Vec::new();
-
-    let mut captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+    let mut captures = cursor.captures(query, node, source.as_bytes());
     captures.advance();
     while let Some((m, capture_index)) = captures.get() {
         let capture = m.captures[*capture_index];
@@ -53,7 +88,12 @@ pub fn highlight_markdown(source: &str) -> Vec<HighlightSpan> {
             .copied()
             .unwrap_or("");
 
-        let kind = classify_capture_name(name);
+        let kind = match classify_capture_name(name) {
+            HighlightKind::Heading { .. } => HighlightKind::Heading {
+                level: capture.node.parent().and_then(heading_level).unwrap_or(1),
+            },
+            kind => kind,
+        };
         let range = capture.node.byte_range();
         if range.start < range.end && range.end <= source.len() {
             spans.push(HighlightSpan { range, kind });
@@ -61,22 +101,73 @@ pub fn highlight_markdown(source: &str) -> Vec<HighlightSpan> {
 
         captures.advance();
     }
+}
 
-    spans.sort_by_key(|s| (s.range.start, s.range.end));
-    spans
+/// tree-sitter-md knows nothing of wiki links and reads `[[Note]]` as a shortcut link inside
+/// brackets. Its link and punctuation spans there give way to one span for the wiki link.
+fn wiki_link_highlights(source: &str, spans: &mut Vec<HighlightSpan>) {
+    let links = find_wiki_links(source);
+    if links.is_empty() {
+        return;
+    }
+    spans.retain(|span| {
+        !matches!(span.kind, HighlightKind::Link | HighlightKind::Punctuation)
+            || !links.iter().any(|link| {
+                link.range.start <= span.range.start && span.range.end <= link.range.end
+            })
+    });
+    for link in links {
+        let range = link.range;
+        spans.extend([
+            HighlightSpan {
+                range: range.clone(),
+                kind: HighlightKind::WikiLink,
+            },
+            HighlightSpan {
+                range: range.start..range.start + 2,
+                kind: HighlightKind::Punctuation,
+            },
+            HighlightSpan {
+                range: range.end - 2..range.end,
+                kind: HighlightKind::Punctuation,
+            },
+        ]);
+    }
+}
+
+/// Level of an ATX or setext heading node, read from its marker or underline.
+pub(crate) fn heading_level(heading: Node) -> Option<u8> {
+    let mut cursor = heading.walk();
+    heading
+        .children(&mut cursor)
+        .find_map(|child| match child.kind() {
+            "atx_h1_marker" | "setext_h1_underline" => Some(1),
+            "atx_h2_marker" | "setext_h2_underline" => Some(2),
+            "atx_h3_marker" => Some(3),
+            "atx_h4_marker" => Some(4),
+            "atx_h5_marker" => Some(5),
+            "atx_h6_marker" => Some(6),
+            _ => None,
+        })
 }
 
+/// Maps the nvim-treesitter style capture names used by the bundled queries, e.g. `text.title`,
+/// `text.literal` or `text.uri`.
 fn classify_capture_name(name: &str) -> HighlightKind {
     let name = name.to_ascii_lowercase();
-    if name.contains("heading") {
-        HighlightKind::Heading
+    if name.contains("heading") || name.contains("title") {
+        // The level is filled in from the captured node.
+        HighlightKind::Heading { level: 1 }
     } else if name.contains("strong") || name.contains("bold") {
         HighlightKind::Strong
     } else if name.contains("emphasis") || name.contains("italic") {
         HighlightKind::Emphasis
-    } else if name.contains("code") {
+    } else if name.contains("code") || name.contains("literal") {
         HighlightKind::Code
-    } else if name.contains("link") || name.contains("url") {
+    } else if ["link", "url", "uri", "reference"]
+        .iter()
+        .any(|part| name.contains(part))
+    {
         HighlightKind::Link
     } else if name.contains("punctuation") || name.contains("delimiter") {
         HighlightKind::Punctuation
@@ -84,18 +175,3 @@ fn classify_capture_name(name: &str) -> HighlightKind {
         HighlightKind::Other
     }
 }
-
-#[cfg(test)]
-mod tests {
-    use super::highlight_markdown;
-
-    #[test]
-    fn highlight_spans_are_within_bounds() {
-        let source = "# Title\n\nHello **world**.\n";
-        let spans = highlight_markdown(source);
-        for span in spans {
-            assert!(span.range.start < span.range.end);
-            assert!(span.range.end <= source.len());
-        }
-    }
-}
@@ -0,0 +1,209 @@
+use std::sync::OnceLock;
+
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor, Range, StreamingIterator, Tree};
+
+use super::highlight::{HighlightKind, HighlightSpan};
+
+/// A grammar that can be injected into fenced code blocks, with its highlight query.
+struct InjectedLanguage {
+    language: Language,
+    query: Query,
+}
+
+impl InjectedLanguage {
+    #[cfg_attr(
+        not(any(feature = "lang-python", feature = "lang-rust", feature = "lang-sql")),
+        allow(dead_code)
+    )]
+    fn load(
+        cell: &'static OnceLock<InjectedLanguage>,
+        language: impl FnOnce() -> Language,
+        highlights: &str,
+    ) -> &'static Self {
+        cell.get_or_init(|| {
+            let language = language();
+            let query = Query::new(&language, highlights)
+                .expect("bundled highlight query compiles for its grammar");
+            Self { language, query }
+        })
+    }
+}
+
+/// Looks up the grammar for a fence info string such as `rust` or `py title="x"`. Only languages
+/// enabled through the `lang-*` cargo features are available.
+fn injected_language(info: &str) -> Option<&'static InjectedLanguage> {
+    let name = info.split_whitespace().next()?.to_ascii_lowercase();
+    match name.as_str() {
+        #[cfg(feature = "lang-python")]
+        "python" | "py" => {
+            static PYTHON: OnceLock<InjectedLanguage> = OnceLock::new();
+            Some(InjectedLanguage::load(
+                &PYTHON,
+                || tree_sitter_python::LANGUAGE.into(),
+                tree_sitter_python::HIGHLIGHTS_QUERY,
+            ))
+        }
+        #[cfg(feature = "lang-rust")]
+        "rust" | "rs" => {
+            static RUST: OnceLock<InjectedLanguage> = OnceLock::new();
+            Some(InjectedLanguage::load(
+                &RUST,
+                || tree_sitter_rust::LANGUAGE.into(),
+                tree_sitter_rust::HIGHLIGHTS_QUERY,
+            ))
+        }
+        #[cfg(feature = "lang-sql")]
+        "sql" => {
+            static SQL: OnceLock<InjectedLanguage> = OnceLock::new();
+            Some(InjectedLanguage::load(
+                &SQL,
+                || tree_sitter_sequel::LANGUAGE.into(),
+                tree_sitter_sequel::HIGHLIGHTS_QUERY,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Appends highlight spans for the contents of every fenced code block under `block_root` whose
+/// info string names an enabled language. Spans nest inside the block's own `Code` span.
+pub(crate) fn injection_highlights(block_root: Node, source: &str, spans: &mut Vec<HighlightSpan>) {
+    let mut cursor = block_root.walk();
+    let mut stack = vec![block_root];
+    while let Some(node) = stack.pop() {
+        if node.kind() != "fenced_code_block" {
+            stack.extend(node.named_children(&mut cursor));
+            continue;
+        }
+
+        let mut info = None;
+        let mut content = None;
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "info_string" => info = child.utf8_text(source.as_bytes()).ok(),
+                "code_fence_content" => content = Some(child),
+                _ => {}
+            }
+        }
+        if let (Some(language), Some(content)) = (info.and_then(injected_language), content)
+            && let Some(tree) = parse_injection(language, content, source)
+        {
+            query_injection(language, &tree, source, spans);
+        }
+    }
+}
+
+/// Parses the fence content in place, skipping container prefixes such as `> ` so offsets stay
+/// relative to the whole source.
+fn parse_injection(language: &InjectedLanguage, content: Node, source: &str) -> Option<Tree> {
+    let mut ranges = Vec::new();
+    let mut range = content.range();
+    let mut cursor = content.walk();
+    for child in content.named_children(&mut cursor) {
+        ranges.push(Range {
+            end_byte: child.start_byte(),
+            end_point: child.start_position(),
+            ..range
+        });
+        range.start_byte = child.end_byte();
+        range.start_point = child.end_position();
+    }
+    ranges.push(range);
+    ranges.retain(|range| range.start_byte < range.end_byte);
+    if ranges.is_empty() {
+        return None;
+    }
+
+    let mut parser = Parser::new();
+    parser.set_language(&language.language).ok()?;
+    parser.set_included_ranges(&ranges).ok()?;
+    parser.parse(source, None)
+}
+
+fn query_injection(
+    language: &InjectedLanguage,
+    tree: &Tree,
+    source: &str,
+    spans: &mut Vec<HighlightSpan>,
+) {
+    let mut cursor = QueryCursor::new();
+    let mut captures = cursor.captures(&language.query, tree.root_node(), source.as_bytes());
+    captures.advance();
+    while let Some((m, capture_index)) = captures.get() {
+        let capture = m.captures[*capture_index];
+        let name = language.query.capture_names()[capture.index as usize];
+        let range = capture.node.byte_range();
+        if let Some(kind) = classify_code_capture(name)
+            && range.start < range.end
+        {
+            spans.push(HighlightSpan { range, kind });
+        }
+        captures.advance();
+    }
+}
+
+/// Maps the capture names of the bundled language queries onto the token kinds we colour.
+fn classify_code_capture(name: &str) -> Option<HighlightKind> {
+    let root = name.split('.').next().unwrap_or(name);
+    match root {
+        "keyword" | "conditional" | "storageclass" => Some(HighlightKind::Keyword),
+        "type" if name == "type.qualifier" => Some(HighlightKind::Keyword),
+        "string" => Some(HighlightKind::String),
+        "comment" => Some(HighlightKind::Comment),
+        "function" => Some(HighlightKind::Function),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tree_sitter_md::MarkdownParser;
+
+    use crate::markdown::highlight::{HighlightKind, markdown_highlights};
+
+    /// Kinds of the spans covering exactly the first occurrence of `needle` in `source`.
+    fn kinds_of(source: &str, needle: &str) -> Vec<HighlightKind> {
+        let tree = MarkdownParser::default()
+            .parse(source.as_bytes(), None)
+            .unwrap();
+        let start = source.find(needle).unwrap();
+        markdown_highlights(tree.block_tree(), tree.inline_trees(), source)
+            .into_iter()
+            .filter(|span| span.range == (start..start + needle.len()))
+            .map(|span| span.kind)
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn highlights_rust_fences() {
+        let source = "```rust\n// greet\nfn main() { println!(\"hi\"); }\n```\n";
+        assert_eq!(kinds_of(source, "// greet"), [HighlightKind::Comment]);
+        assert_eq!(kinds_of(source, "fn"), [HighlightKind::Keyword]);
+        assert_eq!(kinds_of(source, "main"), [HighlightKind::Function]);
+        assert_eq!(kinds_of(source, "\"hi\""), [HighlightKind::String]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-python")]
+    fn highlights_python_fences_inside_block_quotes() {
+        let source = "> ```py\n> def greet():\n>     return 'hi'\n> ```\n";
+        assert_eq!(kinds_of(source, "def"), [HighlightKind::Keyword]);
+        assert_eq!(kinds_of(source, "greet"), [HighlightKind::Function]);
+        assert_eq!(kinds_of(source, "'hi'"), [HighlightKind::String]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-sql")]
+    fn highlights_sql_fences() {
+        let source = "```sql\nSELECT name FROM notes; -- all\n```\n";
+        assert_eq!(kinds_of(source, "SELECT"), [HighlightKind::Keyword]);
+        assert_eq!(kinds_of(source, "-- all"), [HighlightKind::Comment]);
+    }
+
+    #[test]
+    fn leaves_unknown_languages_as_plain_code() {
+        let source = "```brainfuck\n+[-->-[>>+>-----<<]<--<---]\n```\n";
+        assert_eq!(kinds_of(source, "+"), []);
+    }
+}
@@ -0,0 +1,386 @@
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// `[[Target]]`, optionally with `#Heading`, `^block` and `|Label` after the target.
+    Wiki,
+    /// `[text](destination)` or `![alt](destination)`. `angle` is set when the destination is
+    /// written `<like this>`, which allows spaces without escaping.
+    Markdown { angle: bool },
+}
+
+/// A link in a note, found by [`find_links`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// The whole link, brackets included.
+    pub range: Range<usize>,
+    /// The part naming what is linked to: the note of a wiki link, or the path of a Markdown
+    /// link's destination without its `#fragment`. Replacing just this keeps aliases, headings
+    /// and titles.
+    pub target: Range<usize>,
+}
+
+/// A wiki link split into its parts, found by [`find_wiki_links`]. Each part is trimmed and
+/// excludes the character introducing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiLink {
+    /// The whole link, brackets included.
+    pub range: Range<usize>,
+    /// The note linked to: `Target` in `[[Target#Heading|Label]]`.
+    pub target: Range<usize>,
+    /// The heading after `#`, if any.
+    pub heading: Option<Range<usize>>,
+    /// The block after `^`, if any; written `[[Target^block]]` or `[[Target#^block]]`.
+    pub block: Option<Range<usize>>,
+    /// The text shown in place of the link, after `|`.
+    pub label: Option<Range<usize>>,
+}
+
+/// Every wiki link in `text` with its parts, in order, leaving out those inside code spans and
+/// fenced code blocks.
+pub fn find_wiki_links(text: &str) -> Vec<WikiLink> {
+    find_links(text)
+        .into_iter()
+        .filter(|link| link.kind == LinkKind::Wiki)
+        .map(|link| wiki_link_parts(text, link.range, link.target))
+        .collect()
+}
+
+/// The wiki link being typed in `line` with the cursor at `cursor`, and what has been typed of it
+/// so far. The range runs from just after its `[[` to the cursor, or past the closing `]]` if the
+/// link already has one, so that completing it replaces the whole link.
+pub fn wiki_link_query(line: &str, cursor: usize) -> Option<(Range<usize>, &str)> {
+    let before = &line[..cursor];
+    let start = before.rfind("[[")? + 2;
+    let query = &before[start..];
+    if query.contains(['[', ']', '|', '\n']) {
+        return None;
+    }
+    let after = &line[cursor..];
+    let end = match (after.find("]]"), after.find("[[")) {
+        (Some(close), reopen) if reopen.is_none_or(|reopen| close < reopen) => cursor + close + 2,
+        _ => cursor,
+    };
+    Some((start..end, query))
+}
+
+/// Every wiki link and inline Markdown link in `text`, in order, leaving out those inside code
+/// spans and fenced code blocks. Links do not span lines.
+pub fn find_links(text: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    for (offset, line) in lines_outside_fences(text) {
+        scan_line(line, offset, &mut links);
+    }
+    links
+}
+
+/// The lines of `text` outside fenced code blocks, fences excluded, with the offset of each.
+pub(super) fn lines_outside_fences(text: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    // The fence character and length of the fenced code block the line is in.
+    let mut fence: Option<(u8, usize)> = None;
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        let offset = line_start;
+        line_start += line.len();
+
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let marker = trimmed.bytes().next().filter(|&b| b == b'`' || b == b'~');
+        let run = marker.map_or(0, |marker| {
+            trimmed.bytes().take_while(|&b| b == marker).count()
+        });
+        if indent < 4 && run >= 3 {
+            match fence {
+                None => {
+                    fence = marker.map(|marker| (marker, run));
+                    continue;
+                }
+                Some((open, len))
+                    if marker == Some(open) && run >= len && trimmed[run..].trim().is_empty() =>
+                {
+                    fence = None;
+                    continue;
+                }
+                Some(_) => {}
+            }
+        }
+        if fence.is_none() {
+            lines.push((offset, line));
+        }
+    }
+    lines
+}
+
+fn scan_line(line: &str, offset: usize, links: &mut Vec<Link>) {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let run = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+                i = code_span_end(bytes, i, run).unwrap_or(i + run);
+            }
+            b'[' if bytes.get(i + 1) == Some(&b'[') => match wiki_link(line, i) {
+                Some((range, target)) => {
+                    i = range.end;
+                    links.push(Link {
+                        kind: LinkKind::Wiki,
+                        range: offset + range.start..offset + range.end,
+                        target: offset + target.start..offset + target.end,
+                    });
+                }
+                None => i += 2,
+            },
+            b'[' => match markdown_link(line, i) {
+                Some((range, target, angle)) => {
+                    // `![alt](image)` is linked from its `!`.
+                    let start = if i > 0 && bytes[i - 1] == b'!' {
+                        i - 1
+                    } else {
+                        i
+                    };
+                    i = range.end;
+                    links.push(Link {
+                        kind: LinkKind::Markdown { angle },
+                        range: offset + start..offset + range.end,
+                        target: offset + target.start..offset + target.end,
+                    });
+                }
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+}
+
+/// Where a code span opened by the `run` backticks at `start` ends, just past its closing
+/// backticks.
+fn code_span_end(bytes: &[u8], start: usize, run: usize) -> Option<usize> {
+    let mut i = start + run;
+    while i < bytes.len() {
+        if bytes[i] == b'`' {
+            let closing = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+            if closing == run {
+                return Some(i + run);
+            }
+            i += closing;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// The wiki link opening at `start`, and its target, which ends at the first `#`, `^` or `|`.
+fn wiki_link(line: &str, start: usize) -> Option<(Range<usize>, Range<usize>)> {
+    let inner_start = start + 2;
+    let inner_len = line[inner_start..].find("]]")?;
+    let inner = &line[inner_start..inner_start + inner_len];
+    if inner.trim().is_empty() || inner.contains(['[', ']', '\n']) {
+        return None;
+    }
+    let target_len = inner.find(['#', '^', '|']).unwrap_or(inner.len());
+    let target = trim_range(line, inner_start..inner_start + target_len);
+    Some((start..inner_start + inner_len + 2, target))
+}
+
+fn wiki_link_parts(text: &str, range: Range<usize>, target: Range<usize>) -> WikiLink {
+    let inner_end = range.end - 2;
+    let label_start = text[target.end..inner_end]
+        .find('|')
+        .map(|i| target.end + i);
+    let anchors_end = label_start.unwrap_or(inner_end);
+    let part = |start: usize, end: usize| {
+        let part = trim_range(text, start..end);
+        (!part.is_empty()).then_some(part)
+    };
+    let anchors = &text[target.end..anchors_end];
+    let block_start = anchors.find('^').map(|i| target.end + i);
+    // The target ends at the first `#`, `^` or `|`, so only whitespace comes before a `#`.
+    let heading = anchors
+        .find('#')
+        .filter(|&i| anchors[..i].trim().is_empty())
+        .and_then(|i| part(target.end + i + 1, block_start.unwrap_or(anchors_end)));
+    WikiLink {
+        target,
+        heading,
+        block: block_start.and_then(|start| part(start + 1, anchors_end)),
+        label: label_start.and_then(|start| part(start + 1, inner_end)),
+        range,
+    }
+}
+
+/// The Markdown link whose text opens at `start`, its destination's path and whether the
+/// destination is in angle brackets.
+fn markdown_link(line: &str, start: usize) -> Option<(Range<usize>, Range<usize>, bool)> {
+    let bytes = line.as_bytes();
+    // The link text may hold balanced brackets.
+    let mut depth = 0;
+    let mut i = start;
+    let text_end = loop {
+        match bytes.get(i)? {
+            b'\\' => i += 1,
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    break i;
+                }
+            }
+            b'\n' => return None,
+            _ => {}
+        }
+        i += 1;
+    };
+    if bytes.get(text_end + 1) != Some(&b'(') {
+        return None;
+    }
+
+    let mut i = text_end + 2;
+    while bytes.get(i) == Some(&b' ') {
+        i += 1;
+    }
+    let (destination, angle) = if bytes.get(i) == Some(&b'<') {
+        let len = line[i + 1..].find(['>', '\n'])?;
+        if bytes[i + 1 + len] != b'>' {
+            return None;
+        }
+        let destination = i + 1..i + 1 + len;
+        i += len + 2;
+        (destination, true)
+    } else {
+        let destination_start = i;
+        let mut parens = 0;
+        while let Some(&b) = bytes.get(i) {
+            match b {
+                b'\\' => i += 1,
+                b'(' => parens += 1,
+                b')' if parens == 0 => break,
+                b')' => parens -= 1,
+                b' ' | b'\t' | b'\n' => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        i = i.min(bytes.len());
+        (destination_start..i, false)
+    };
+
+    // Skip an optional title up to the closing parenthesis.
+    let close = i + line[i..].find(')')?;
+    if line[i..close].contains('\n') {
+        return None;
+    }
+    let path_len = line[destination.clone()]
+        .find('#')
+        .unwrap_or(destination.len());
+    Some((
+        start..close + 1,
+        destination.start..destination.start + path_len,
+        angle,
+    ))
+}
+
+fn trim_range(text: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    start..end.max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkKind, find_links, find_wiki_links, wiki_link_query};
+
+    fn targets(text: &str) -> Vec<(LinkKind, &str, &str)> {
+        find_links(text)
+            .into_iter()
+            .map(|link| (link.kind, &text[link.range], &text[link.target]))
+            .collect()
+    }
+
+    #[test]
+    fn finds_wiki_links_and_their_targets() {
+        assert_eq!(
+            targets("See [[Note]], [[ Other Note |Label]] and [[Guide#Setup]] or [[Log^abc]]."),
+            [
+                (LinkKind::Wiki, "[[Note]]", "Note"),
+                (LinkKind::Wiki, "[[ Other Note |Label]]", "Other Note"),
+                (LinkKind::Wiki, "[[Guide#Setup]]", "Guide"),
+                (LinkKind::Wiki, "[[Log^abc]]", "Log"),
+            ]
+        );
+        assert_eq!(targets("[[]] [[ ]] [[a\nb]]"), []);
+    }
+
+    #[test]
+    fn splits_wiki_links_into_parts() {
+        let text = "[[Note]] [[Guide # Setup | the guide]] [[Log^abc]] [[Log#^def|x]] [[A|]]";
+        let part = |range: Option<std::ops::Range<usize>>| range.map(|range| &text[range]);
+        let parts = find_wiki_links(text)
+            .into_iter()
+            .map(|link| {
+                (
+                    &text[link.target],
+                    part(link.heading),
+                    part(link.block),
+                    part(link.label),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                ("Note", None, None, None),
+                ("Guide", Some("Setup"), None, Some("the guide")),
+                ("Log", None, Some("abc"), None),
+                ("Log", None, Some("def"), Some("x")),
+                ("A", None, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_wiki_link_being_typed() {
+        let query = |line: &str| {
+            let cursor = line.find('‸').unwrap();
+            let line = line.replacen('‸', "", 1);
+            wiki_link_query(&line, cursor).map(|(range, query)| (range, query.to_string()))
+        };
+        assert_eq!(query("See [[‸"), Some((6..6, String::new())));
+        assert_eq!(query("[[a]] [[Pl‸"), Some((8..10, "Pl".into())));
+        assert_eq!(query("[[Pl‸an#x]] more"), Some((2..10, "Pl".into())));
+        assert_eq!(query("[[Pl‸ [[b]]"), Some((2..4, "Pl".into())));
+        assert_eq!(query("[[a]]‸"), None);
+        assert_eq!(query("[[a#b|c‸"), None);
+        assert_eq!(query("[x‸"), None);
+    }
+
+    #[test]
+    fn finds_markdown_links_and_their_paths() {
+        let angle = LinkKind::Markdown { angle: true };
+        let plain = LinkKind::Markdown { angle: false };
+        assert_eq!(
+            targets(
+                "[a](notes/a.md) ![img](pics/x.png \"title\") [b [c]](<my notes/b.md#top>) [d](x.md#h)"
+            ),
+            [
+                (plain, "[a](notes/a.md)", "notes/a.md"),
+                (plain, "![img](pics/x.png \"title\")", "pics/x.png"),
+                (angle, "[b [c]](<my notes/b.md#top>)", "my notes/b.md"),
+                (plain, "[d](x.md#h)", "x.md"),
+            ]
+        );
+        assert_eq!(targets("[not a link] (x.md) [x]"), []);
+    }
+
+    #[test]
+    fn skips_links_in_code() {
+        let text = "`[[a]]` [[b]]\n```md\n[[c]] [d](d.md)\n```\n``x ` [[e]]``\n~~~\n[[f]]\n";
+        let found: Vec<_> = targets(text).into_iter().map(|(_, _, t)| t).collect();
+        assert_eq!(found, ["b"]);
+    }
+}
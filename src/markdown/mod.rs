@@ -1 +1,7 @@
+pub mod decoration;
 pub mod highlight;
+mod injection;
+pub mod links;
+pub mod note;
+pub mod paste;
+pub mod syntax;
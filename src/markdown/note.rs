@@ -0,0 +1,144 @@
+use super::links::lines_outside_fences;
+
+/// An ATX heading of a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    /// The heading's text without its markers, as `[[Note#Heading]]` links name it.
+    pub text: String,
+}
+
+/// The ATX headings of `text` outside front matter and fenced code blocks, in order. Setext
+/// headings are left out.
+pub fn headings(text: &str) -> Vec<Heading> {
+    let body_start = front_matter(text).map_or(0, |front_matter| front_matter.end);
+    let mut headings = Vec::new();
+    for (offset, line) in lines_outside_fences(text) {
+        if offset < body_start {
+            continue;
+        }
+        let trimmed = line.trim_start_matches(' ');
+        if line.len() - trimmed.len() > 3 {
+            continue;
+        }
+        let level = trimmed.bytes().take_while(|&b| b == b'#').count();
+        let rest = &trimmed[level..];
+        if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t', '\n'])) {
+            continue;
+        }
+        // A closing run of `#` is not part of the text.
+        let rest = rest.trim();
+        let without_closing = rest.trim_end_matches('#');
+        let title = if without_closing.is_empty() || without_closing.ends_with([' ', '\t']) {
+            without_closing.trim_end()
+        } else {
+            rest
+        };
+        if !title.is_empty() {
+            headings.push(Heading {
+                level: level as u8,
+                text: title.to_string(),
+            });
+        }
+    }
+    headings
+}
+
+/// The other names a note answers to, listed under `aliases` or `alias` in its front matter,
+/// either inline (`aliases: [One, "Two"]`, `alias: One, Two`) or as a block list.
+pub fn aliases(text: &str) -> Vec<String> {
+    let Some(front_matter) = front_matter(text) else {
+        return Vec::new();
+    };
+    let mut aliases = Vec::new();
+    let mut in_list = false;
+    for line in text[front_matter].lines().skip(1) {
+        if in_list {
+            if let Some(item) = line.trim_start().strip_prefix('-') {
+                aliases.push(unquote(item));
+                continue;
+            }
+            if line.starts_with([' ', '\t']) {
+                continue;
+            }
+            in_list = false;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !matches!(key.trim_end().to_lowercase().as_str(), "aliases" | "alias") {
+            continue;
+        }
+        let value = value.trim();
+        if value.is_empty() {
+            in_list = true;
+            continue;
+        }
+        let value = value
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+            .unwrap_or(value);
+        aliases.extend(value.split(',').map(unquote));
+    }
+    aliases.retain(|alias| !alias.is_empty());
+    aliases
+}
+
+/// The YAML front matter at the top of `text`, from its opening `---` to the end of its closing
+/// `---` or `...` line.
+fn front_matter(text: &str) -> Option<std::ops::Range<usize>> {
+    let mut lines = text.split_inclusive('\n');
+    let opening = lines.next()?;
+    if opening.trim_end() != "---" {
+        return None;
+    }
+    let mut end = opening.len();
+    for line in lines {
+        end += line.len();
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some(0..end);
+        }
+    }
+    None
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote));
+    unquoted.unwrap_or(value).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aliases, headings};
+
+    #[test]
+    fn lists_atx_headings_outside_code_and_front_matter() {
+        let text = "---\n# not a heading\n---\n# Title\n\ntext #tag\n```\n# code\n```\n  ## Goals ##\n#nope\n###### Deep # down\n#\n";
+        let found = headings(text)
+            .into_iter()
+            .map(|heading| (heading.level, heading.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (1, "Title".to_string()),
+                (2, "Goals".to_string()),
+                (6, "Deep # down".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_aliases_from_front_matter() {
+        let text = "---\ntitle: Plan\naliases: [Roadmap, \"The plan\"]\nalias: Next\ntags:\n  - x\n---\naliases: [Body]\n";
+        assert_eq!(aliases(text), ["Roadmap", "The plan", "Next"]);
+
+        let text = "---\naliases:\n  - One\n  - 'Two'\ntags: [a]\n---\n";
+        assert_eq!(aliases(text), ["One", "Two"]);
+        assert!(aliases("aliases: [x]\n").is_empty());
+        assert!(aliases("---\naliases: [x]\n").is_empty());
+    }
+}
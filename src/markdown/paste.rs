@@ -0,0 +1,125 @@
+/// Rewrites clipboard text before it is inserted into a Markdown buffer.
+///
+/// - Pasting a URL over a single-line selection produces `[selection](url)`.
+/// - Pasting multiple lines while the cursor sits in a list item re-indents the following lines so
+///   they stay inside the list: nested list items become siblings of the current item and plain
+///   lines become continuation paragraphs aligned with the item's content.
+///
+/// `line_prefix` is the text between the start of the cursor's line and the start of the selection.
+pub fn prepare_paste(pasted: &str, selected: &str, line_prefix: &str) -> String {
+    let pasted = pasted.replace("\r\n", "\n");
+
+    if !selected.is_empty()
+        && !selected.contains('\n')
+        && let Some(url) = as_url(&pasted)
+    {
+        return format!("[{selected}]({url})");
+    }
+
+    let Some(item) = ListItemPrefix::parse(line_prefix) else {
+        return pasted;
+    };
+    if !pasted.contains('\n') {
+        return pasted;
+    }
+
+    let mut out = String::with_capacity(pasted.len());
+    for (i, line) in pasted.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+            if !line.trim().is_empty() {
+                if ListItemPrefix::parse(line).is_some() {
+                    out.push_str(&item.indent);
+                } else {
+                    out.push_str(&" ".repeat(item.content_column));
+                }
+            }
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+fn as_url(text: &str) -> Option<&str> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().any(char::is_whitespace) {
+        return None;
+    }
+    const SCHEMES: [&str; 4] = ["http://", "https://", "mailto:", "ftp://"];
+    SCHEMES
+        .iter()
+        .any(|scheme| {
+            text.len() > scheme.len()
+                && text
+                    .get(..scheme.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+        })
+        .then_some(text)
+}
+
+/// The leading part of a list item line, e.g. `  - ` or `1. `.
+struct ListItemPrefix {
+    indent: String,
+    content_column: usize,
+}
+
+impl ListItemPrefix {
+    fn parse(line: &str) -> Option<Self> {
+        let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let rest = &line[indent_len..];
+
+        let marker_len = if rest.starts_with(['-', '*', '+']) {
+            1
+        } else {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            if digits == 0 || digits > 9 || !rest[digits..].starts_with(['.', ')']) {
+                return None;
+            }
+            digits + 1
+        };
+
+        let after_marker = &rest[marker_len..];
+        let spacing = after_marker.len() - after_marker.trim_start_matches(' ').len();
+        if spacing == 0 {
+            return None;
+        }
+
+        Some(Self {
+            indent: line[..indent_len].to_string(),
+            content_column: indent_len + marker_len + spacing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prepare_paste;
+
+    #[test]
+    fn wraps_selection_when_pasting_url() {
+        assert_eq!(
+            prepare_paste("https://zeno.dev/docs\n", "the docs", "See "),
+            "[the docs](https://zeno.dev/docs)"
+        );
+        assert_eq!(
+            prepare_paste("https://zeno.dev", "", ""),
+            "https://zeno.dev"
+        );
+        assert_eq!(prepare_paste("not a url", "text", ""), "not a url");
+    }
+
+    #[test]
+    fn keeps_multiline_paste_inside_list_items() {
+        assert_eq!(
+            prepare_paste("first\r\nsecond\n\n- sibling", "", "  - item "),
+            "first\n    second\n\n  - sibling"
+        );
+        assert_eq!(prepare_paste("a\nb", "", "10. "), "a\n    b");
+    }
+
+    #[test]
+    fn leaves_paste_untouched_outside_lists() {
+        assert_eq!(prepare_paste("a\nb", "", "plain "), "a\nb");
+        assert_eq!(prepare_paste("a\nb", "", "-not a list"), "a\nb");
+    }
+}
@@ -0,0 +1,776 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use tree_sitter::{InputEdit, Node, Parser, Point, Tree};
+
+use super::decoration::{Decoration, markdown_decorations};
+use super::highlight::{HighlightSpan, markdown_highlights};
+use crate::editor::{BufferEdit, TextBuffer};
+
+/// Lines a chunk grows to before the next safe split point ends it. A keystroke reparses and
+/// re-queries the whole chunk around it, so chunks are kept small.
+const MIN_CHUNK_LINES: usize = 8;
+
+/// Persistent Markdown parse of one buffer.
+///
+/// tree-sitter-md reuses little of the old tree when reparsing, so a single tree costs about as
+/// much to update as to build. Instead the document is split into chunks at blank lines that no
+/// block can continue past, and each chunk keeps its own block and inline trees. An edit only
+/// marks the chunks it touches; `reparse` re-splits from there until the boundaries line up with
+/// the old ones again, reparses those chunks and re-queries only them. Documents without blank
+/// lines outside code blocks stay a single chunk and get no speedup.
+pub struct SyntaxLayer {
+    /// The block and inline grammars get a parser each, so neither switches languages per chunk.
+    block_parser: Parser,
+    inline_parser: Parser,
+    /// Contiguous, covering the whole buffer. Empty until the first `reparse`.
+    chunks: Vec<Chunk>,
+}
+
+struct Chunk {
+    range: Range<usize>,
+    start_row: usize,
+    /// Parse of `range` on its own; node offsets are relative to `range.start`.
+    tree: Option<ChunkTree>,
+    /// Relative to `range.start`, sorted by `(start, end)`.
+    highlights: Vec<HighlightSpan>,
+    /// Relative to `range.start`, sorted by start.
+    decorations: Vec<Decoration>,
+    /// Bytes edited since the last `reparse`, relative to `range.start`. `None` once reparsed.
+    edited: Option<Range<usize>>,
+}
+
+/// The block tree of a chunk and one inline tree per `inline` node, split the way
+/// `tree_sitter_md::MarkdownParser` splits a document.
+struct ChunkTree {
+    block: Tree,
+    /// In document order.
+    inlines: Vec<Tree>,
+}
+
+impl Default for SyntaxLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyntaxLayer {
+    pub fn new() -> Self {
+        let parser = |language: tree_sitter::Language| {
+            let mut parser = Parser::new();
+            parser
+                .set_language(&language)
+                .expect("bundled Markdown grammar is compatible");
+            parser
+        };
+        Self {
+            block_parser: parser(tree_sitter_md::LANGUAGE.into()),
+            inline_parser: parser(tree_sitter_md::INLINE_LANGUAGE.into()),
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Highlight spans intersecting `range`, sorted by position.
+    pub fn highlights_in(&self, range: Range<usize>) -> Vec<HighlightSpan> {
+        let first = self
+            .chunks
+            .partition_point(|chunk| chunk.range.end <= range.start);
+        self.chunks[first..]
+            .iter()
+            .take_while(|chunk| chunk.range.start < range.end)
+            .flat_map(|chunk| {
+                chunk.highlights.iter().map(|span| HighlightSpan {
+                    range: chunk.range.start + span.range.start..chunk.range.start + span.range.end,
+                    kind: span.kind,
+                })
+            })
+            .filter(|span| span.range.start < range.end && span.range.end > range.start)
+            .collect()
+    }
+
+    /// Decorations whose construct intersects `range`, sorted by position.
+    pub fn decorations_in(&self, range: Range<usize>) -> Vec<Decoration> {
+        let first = self
+            .chunks
+            .partition_point(|chunk| chunk.range.end <= range.start);
+        self.chunks[first..]
+            .iter()
+            .take_while(|chunk| chunk.range.start < range.end)
+            .flat_map(|chunk| {
+                chunk
+                    .decorations
+                    .iter()
+                    .map(|decoration| decoration.shifted(chunk.range.start))
+            })
+            .filter(|decoration| {
+                decoration.full_range.start < range.end && decoration.full_range.end > range.start
+            })
+            .collect()
+    }
+
+    /// Records an edit already applied to the buffer. Call `reparse` once all pending edits have
+    /// been recorded.
+    pub fn edit(&mut self, edit: &BufferEdit) {
+        if self.chunks.is_empty() {
+            return;
+        }
+
+        // Chunks that merely touch the edit are included: their boundary with a neighbour depends
+        // on the lines on either side of it.
+        let first = self
+            .chunks
+            .partition_point(|chunk| chunk.range.end < edit.start);
+        let last = self
+            .chunks
+            .partition_point(|chunk| chunk.range.start <= edit.old_end);
+        let delta = edit.new_end as isize - edit.old_end as isize;
+        let row_delta = edit.new_end_point.0 as isize - edit.old_end_point.0 as isize;
+
+        if last - first == 1 {
+            let chunk = &mut self.chunks[first];
+            let edit = relative_input_edit(edit, chunk.range.start, chunk.start_row);
+            if let Some(tree) = &mut chunk.tree {
+                tree.block.edit(&edit);
+                for inline in &mut tree.inlines {
+                    inline.edit(&edit);
+                }
+            }
+            chunk.range.end = shift(chunk.range.end, delta);
+            chunk.edited = Some(match chunk.edited.take() {
+                Some(edited) => {
+                    let end = if edited.end > edit.old_end_byte {
+                        shift(edited.end, delta)
+                    } else {
+                        edit.new_end_byte
+                    };
+                    edited.start.min(edit.start_byte)..end.max(edit.new_end_byte)
+                }
+                None => edit.start_byte..edit.new_end_byte,
+            });
+        } else {
+            let range =
+                self.chunks[first].range.start..shift(self.chunks[last - 1].range.end, delta);
+            let merged = Chunk {
+                start_row: self.chunks[first].start_row,
+                tree: None,
+                highlights: Vec::new(),
+                decorations: Vec::new(),
+                edited: Some(0..range.len()),
+                range,
+            };
+            self.chunks.splice(first..last, [merged]);
+        }
+
+        for chunk in &mut self.chunks[first + 1..] {
+            chunk.range = shift(chunk.range.start, delta)..shift(chunk.range.end, delta);
+            chunk.start_row = chunk.start_row.saturating_add_signed(row_delta);
+        }
+    }
+
+    /// Brings every chunk up to date with `source`, which must reflect every edit recorded since
+    /// the last call.
+    pub fn reparse(&mut self, source: &TextBuffer) {
+        if self.chunks.is_empty() {
+            self.chunks.push(Chunk {
+                range: 0..source.len(),
+                start_row: 0,
+                tree: None,
+                highlights: Vec::new(),
+                decorations: Vec::new(),
+                edited: Some(0..source.len()),
+            });
+        }
+
+        // Only the runs of chunks around edited ones are replaced; the rest stay where they are.
+        let mut index = 0;
+        while index < self.chunks.len() {
+            if self.chunks[index].edited.is_none() {
+                index += 1;
+                continue;
+            }
+
+            // Re-split from the stale chunk until a split point coincides with the start of a
+            // chunk that has not been edited; everything in between is parsed again.
+            let start = self.chunks[index].range.start;
+            let start_row = self.chunks[index].start_row;
+            let chunks = &self.chunks;
+            let mut ranges: VecDeque<_> = split_chunks(source, start, start_row, |offset| {
+                let next = chunks.partition_point(|chunk| chunk.range.start < offset);
+                chunks
+                    .get(next)
+                    .is_some_and(|chunk| chunk.range.start == offset && chunk.edited.is_none())
+            })
+            .into();
+            let end = ranges.back().map_or(start, |(range, _)| range.end);
+            let mut replaced_end = index + 1;
+            while self
+                .chunks
+                .get(replaced_end)
+                .is_some_and(|next| next.range.start < end)
+            {
+                replaced_end += 1;
+            }
+            let mut old_trees = self.chunks[index..replaced_end]
+                .iter_mut()
+                .filter_map(|old| Some((old.range.clone(), old.tree.take()?, old.edited.clone())))
+                .collect::<Vec<_>>();
+
+            let mut parsed = Vec::with_capacity(ranges.len());
+            while let Some((range, start_row)) = ranges.pop_front() {
+                let old = old_trees
+                    .iter()
+                    .position(|(old, ..)| *old == range)
+                    .map(|position| {
+                        let (_, tree, edited) = old_trees.swap_remove(position);
+                        (tree, edited)
+                    });
+                let mut chunk = self.parse_chunk(source, range, start_row, old);
+                // The split heuristics can be fooled; the parse cannot. Join chunks until none
+                // ends inside a block that would have continued into the next one.
+                while chunk.ends_open() {
+                    let end = match ranges.pop_front() {
+                        Some((next, _)) => next.end,
+                        None => match self.chunks.get(replaced_end) {
+                            Some(next) => {
+                                replaced_end += 1;
+                                next.range.end
+                            }
+                            None => break,
+                        },
+                    };
+                    chunk = self.parse_chunk(source, chunk.range.start..end, start_row, None);
+                }
+                parsed.push(chunk);
+            }
+            let parsed_len = parsed.len();
+            self.chunks.splice(index..replaced_end, parsed);
+            index += parsed_len;
+        }
+    }
+
+    /// `old` is the chunk's previous tree, already edited, with the bytes edited since it was
+    /// built, if any.
+    fn parse_chunk(
+        &mut self,
+        source: &TextBuffer,
+        range: Range<usize>,
+        start_row: usize,
+        old: Option<(ChunkTree, Option<Range<usize>>)>,
+    ) -> Chunk {
+        let tree = self.parse_tree(source, range.clone(), old);
+        let text = source.slice_cow(range.clone());
+        let highlights = tree
+            .as_ref()
+            .map(|tree| markdown_highlights(&tree.block, &tree.inlines, &text))
+            .unwrap_or_default();
+        let decorations = tree
+            .as_ref()
+            .map(|tree| markdown_decorations(&tree.block, &tree.inlines, &text))
+            .unwrap_or_default();
+        Chunk {
+            range,
+            start_row,
+            tree,
+            highlights,
+            decorations,
+            edited: None,
+        }
+    }
+
+    /// The block tree is reparsed incrementally. Inline trees whose text was not edited are
+    /// kept; the others are parsed from scratch, as the inline grammar is no faster with an old
+    /// tree and tree-sitter-md would pair old and new inline trees by index.
+    fn parse_tree(
+        &mut self,
+        source: &TextBuffer,
+        range: Range<usize>,
+        old: Option<(ChunkTree, Option<Range<usize>>)>,
+    ) -> Option<ChunkTree> {
+        // The parsers read the rope in place; only the queries need the chunk as one `&str`.
+        let mut read = |offset, _| {
+            let bytes = source.chunk_bytes_at(range.start + offset);
+            &bytes[..bytes.len().min(range.len().saturating_sub(offset))]
+        };
+        let block = self.block_parser.parse_with_options(
+            &mut read,
+            old.as_ref().map(|(tree, _)| &tree.block),
+            None,
+        )?;
+
+        let mut kept = old
+            .map(|(tree, edited)| {
+                tree.inlines
+                    .into_iter()
+                    .map(|inline| (inline.included_ranges(), inline))
+                    .filter(|(ranges, _)| {
+                        edited.as_ref().is_none_or(|edited| {
+                            !ranges.iter().any(|range| {
+                                range.start_byte <= edited.end && edited.start <= range.end_byte
+                            })
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .peekable();
+        let mut inline_trees = Vec::new();
+        inline_ranges(block.root_node(), &mut inline_trees);
+        let mut inlines = Vec::with_capacity(inline_trees.len());
+        for ranges in inline_trees {
+            while kept
+                .next_if(|(old, _)| old[0].start_byte < ranges[0].start_byte)
+                .is_some()
+            {}
+            let inline = match kept.next_if(|(old, _)| *old == ranges) {
+                Some((_, inline)) => inline,
+                None => {
+                    self.inline_parser.set_included_ranges(&ranges).ok()?;
+                    self.inline_parser
+                        .parse_with_options(&mut read, None, None)?
+                }
+            };
+            inlines.push(inline);
+        }
+        Some(ChunkTree { block, inlines })
+    }
+}
+
+impl Chunk {
+    /// Whether the chunk ends inside a fenced code block, HTML block or front matter, which the
+    /// following lines would have continued.
+    fn ends_open(&self) -> bool {
+        let Some(tree) = &self.tree else {
+            return false;
+        };
+        let len = self.range.len();
+        let mut node = tree.block.root_node();
+        while node.end_byte() == len {
+            match node.kind() {
+                "fenced_code_block" => {
+                    let mut cursor = node.walk();
+                    return node
+                        .children(&mut cursor)
+                        .filter(|child| child.kind() == "fenced_code_block_delimiter")
+                        .count()
+                        < 2;
+                }
+                "html_block" | "minus_metadata" | "plus_metadata" => return true,
+                _ => {}
+            }
+            let Some(last) = node.child(node.child_count().saturating_sub(1) as _) else {
+                return false;
+            };
+            node = last;
+        }
+        false
+    }
+}
+
+/// Splits `source` from `start`, which must begin line `start_row`, into `(range, start_row)`
+/// chunks of at least `MIN_CHUNK_LINES` lines. Splits only happen before an unindented line that
+/// follows a blank line outside any code fence, HTML block or front matter, or that follows the
+/// closing line of a fence opened at the start of a line. Every Markdown block has ended there.
+/// Stops early at the first split point for which `resync` returns true.
+fn split_chunks(
+    source: &TextBuffer,
+    start: usize,
+    start_row: usize,
+    mut resync: impl FnMut(usize) -> bool,
+) -> Vec<(Range<usize>, usize)> {
+    let mut chunks = Vec::new();
+    let mut chunk_start = start;
+    let mut chunk_row = start_row;
+    let mut row = start_row;
+    let mut lines = 0;
+    let mut blocks_ended = false;
+    let mut open_block: Option<OpenBlock> = None;
+
+    let mut pos = start;
+    while pos < source.len() {
+        let line = source.line(row);
+        let line = line.as_ref();
+
+        if pos > chunk_start
+            && blocks_ended
+            && open_block.is_none()
+            && line.starts_with(|c: char| !c.is_whitespace())
+        {
+            if resync(pos) {
+                chunks.push((chunk_start..pos, chunk_row));
+                return chunks;
+            }
+            if lines >= MIN_CHUNK_LINES {
+                chunks.push((chunk_start..pos, chunk_row));
+                chunk_start = pos;
+                chunk_row = row;
+                lines = 0;
+            }
+        }
+
+        (open_block, blocks_ended) = match open_block {
+            Some(block) if block.closed_by(line) => (None, block.is_top_level_fence()),
+            Some(block) => (Some(block), false),
+            None => (OpenBlock::opened_by(line, pos == 0), line.trim().is_empty()),
+        };
+        lines += 1;
+        row += 1;
+        pos = source.line_start(row);
+    }
+
+    chunks.push((chunk_start..source.len(), chunk_row));
+    chunks
+}
+
+/// Pushes the included ranges of each inline tree under `node` onto `inlines`, in document
+/// order: every `inline` and `pipe_table_cell` node minus its named children after the first, as
+/// `tree_sitter_md::MarkdownParser` computes them.
+fn inline_ranges(node: Node, inlines: &mut Vec<Vec<tree_sitter::Range>>) {
+    let mut cursor = node.walk();
+    if !matches!(node.kind(), "inline" | "pipe_table_cell") {
+        for child in node.children(&mut cursor) {
+            inline_ranges(child, inlines);
+        }
+        return;
+    }
+    let mut range = node.range();
+    let mut ranges = Vec::new();
+    for child in node.children(&mut cursor).skip(1) {
+        if child.is_named() {
+            ranges.push(tree_sitter::Range {
+                end_byte: child.start_byte(),
+                end_point: child.start_position(),
+                ..range
+            });
+            range.start_byte = child.end_byte();
+            range.start_point = child.end_position();
+        }
+    }
+    ranges.push(range);
+    inlines.push(ranges);
+}
+
+/// A block that may contain blank lines and must not be split.
+#[derive(Clone, Copy)]
+enum OpenBlock {
+    /// `quotes` is the block quote depth of the opening line, which the closing line must repeat.
+    /// `top_level` is whether the opening line starts with the fence, outside any container.
+    Fence {
+        marker: char,
+        len: usize,
+        quotes: usize,
+        top_level: bool,
+    },
+    Html {
+        close: &'static str,
+    },
+    FrontMatter,
+}
+
+impl OpenBlock {
+    fn opened_by(line: &str, at_document_start: bool) -> Option<Self> {
+        if at_document_start && line.trim_end() == "---" {
+            return Some(Self::FrontMatter);
+        }
+
+        let trimmed = strip_container_markers(line);
+        let quotes = strip_quote_markers(line).1;
+        if let Some(marker) = trimmed.chars().next().filter(|c| matches!(c, '`' | '~')) {
+            let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+            let info = &trimmed[len..];
+            if len >= 3 && !(marker == '`' && info.contains('`')) {
+                return Some(Self::Fence {
+                    marker,
+                    len,
+                    quotes,
+                    top_level: line.starts_with(marker),
+                });
+            }
+            return None;
+        }
+
+        const HTML_BLOCKS: [(&str, &str); 5] = [
+            ("<!--", "-->"),
+            ("<pre", "</pre>"),
+            ("<script", "</script>"),
+            ("<style", "</style>"),
+            ("<textarea", "</textarea>"),
+        ];
+        let lower = trimmed.to_ascii_lowercase();
+        HTML_BLOCKS
+            .iter()
+            .find(|(open, _)| lower.starts_with(open))
+            .filter(|(open, close)| !lower[open.len()..].contains(close))
+            .map(|&(_, close)| Self::Html { close })
+    }
+
+    fn closed_by(&self, line: &str) -> bool {
+        match *self {
+            Self::Fence {
+                marker,
+                len,
+                quotes,
+                ..
+            } => {
+                let (trimmed, depth) = strip_quote_markers(line);
+                let run = trimmed.len() - trimmed.trim_start_matches(marker).len();
+                depth == quotes && run >= len && trimmed[run..].trim().is_empty()
+            }
+            Self::Html { close } => line.to_ascii_lowercase().contains(close),
+            Self::FrontMatter => matches!(line.trim_end(), "---" | "..."),
+        }
+    }
+
+    fn is_top_level_fence(&self) -> bool {
+        matches!(
+            self,
+            Self::Fence {
+                top_level: true,
+                ..
+            }
+        )
+    }
+}
+
+/// Strips indentation and leading block quote and list markers, so fences opened inside
+/// containers are tracked too. Treating a line as a fence when it is not only costs split points.
+fn strip_container_markers(mut line: &str) -> &str {
+    loop {
+        let trimmed = line.trim_start_matches([' ', '\t']);
+        let marker_len = if trimmed.starts_with(['>', '-', '*', '+']) {
+            1
+        } else {
+            let digits = trimmed.bytes().take_while(u8::is_ascii_digit).count();
+            if digits > 0 && trimmed[digits..].starts_with(['.', ')']) {
+                digits + 1
+            } else {
+                0
+            }
+        };
+        let rest = &trimmed[marker_len..];
+        if marker_len == 0
+            || !(rest.is_empty() || rest.starts_with([' ', '\t']) || trimmed.starts_with('>'))
+        {
+            return trimmed;
+        }
+        line = rest;
+    }
+}
+
+/// Strips indentation and block quote markers, returning the rest and the quote depth.
+fn strip_quote_markers(mut line: &str) -> (&str, usize) {
+    let mut depth = 0;
+    loop {
+        line = line.trim_start_matches([' ', '\t']);
+        match line.strip_prefix('>') {
+            Some(rest) => {
+                line = rest;
+                depth += 1;
+            }
+            None => return (line, depth),
+        }
+    }
+}
+
+fn shift(offset: usize, delta: isize) -> usize {
+    offset.saturating_add_signed(delta)
+}
+
+fn relative_input_edit(edit: &BufferEdit, start: usize, start_row: usize) -> InputEdit {
+    let point = |(row, column): (usize, usize)| Point {
+        row: row.saturating_sub(start_row),
+        column,
+    };
+    InputEdit {
+        start_byte: edit.start - start,
+        old_end_byte: edit.old_end - start,
+        new_end_byte: edit.new_end - start,
+        start_position: point(edit.start_point),
+        old_end_position: point(edit.old_end_point),
+        new_end_position: point(edit.new_end_point),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tree_sitter_md::MarkdownParser;
+
+    use super::SyntaxLayer;
+    use crate::editor::{EditorState, TextBuffer};
+    use crate::markdown::decoration::{Decoration, markdown_decorations};
+    use crate::markdown::highlight::{HighlightKind, HighlightSpan, markdown_highlights};
+
+    /// Highlights and decorations of `source` parsed as one tree, without chunking.
+    fn full_parse(source: &str) -> (Vec<HighlightSpan>, Vec<Decoration>) {
+        let tree = MarkdownParser::default()
+            .parse(source.as_bytes(), None)
+            .unwrap();
+        (
+            markdown_highlights(tree.block_tree(), tree.inline_trees(), source),
+            markdown_decorations(tree.block_tree(), tree.inline_trees(), source),
+        )
+    }
+
+    fn sync(layer: &mut SyntaxLayer, editor: &mut EditorState) {
+        for edit in editor.take_buffer_edits() {
+            layer.edit(&edit);
+        }
+        layer.reparse(editor.buffer());
+    }
+
+    fn layer_parse(
+        layer: &SyntaxLayer,
+        editor: &EditorState,
+    ) -> (Vec<HighlightSpan>, Vec<Decoration>) {
+        (
+            layer.highlights_in(0..editor.len() + 1),
+            layer.decorations_in(0..editor.len() + 1),
+        )
+    }
+
+    #[test]
+    fn highlight_spans_are_within_bounds() {
+        let source = "# Title\n\nHello **world**.\n";
+        let mut layer = SyntaxLayer::new();
+        layer.reparse(&TextBuffer::from(source));
+        let spans = layer.highlights_in(0..source.len());
+        assert!(!spans.is_empty());
+        for span in spans {
+            assert!(span.range.start < span.range.end);
+            assert!(span.range.end <= source.len());
+        }
+    }
+
+    #[test]
+    fn classifies_block_and_inline_constructs() {
+        let source = "# Title\n\n## Section\n\n\
+            Some **strong**, *emphasis*, `code` and [a link](https://zeno.dev) \\*\n\
+            See [[Other note|it]].\n";
+        let mut layer = SyntaxLayer::new();
+        layer.reparse(&TextBuffer::from(source));
+        let spans = layer.highlights_in(0..source.len());
+        let kinds_of = |needle: &str| {
+            let start = source.find(needle).unwrap();
+            spans
+                .iter()
+                .filter(|span| span.range == (start..start + needle.len()))
+                .map(|span| span.kind)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(kinds_of("#"), [HighlightKind::Punctuation]);
+        assert_eq!(kinds_of("Title"), [HighlightKind::Heading { level: 1 }]);
+        assert_eq!(kinds_of("Section"), [HighlightKind::Heading { level: 2 }]);
+        assert_eq!(kinds_of("**strong**"), [HighlightKind::Strong]);
+        assert_eq!(kinds_of("*emphasis*"), [HighlightKind::Emphasis]);
+        assert_eq!(kinds_of("`code`"), [HighlightKind::Code]);
+        assert_eq!(kinds_of("a link"), [HighlightKind::Link]);
+        assert_eq!(kinds_of("https://zeno.dev"), [HighlightKind::Link]);
+        assert_eq!(kinds_of("\\*"), [HighlightKind::Other]);
+        assert_eq!(kinds_of("[[Other note|it]]"), [HighlightKind::WikiLink]);
+        assert_eq!(kinds_of("[["), [HighlightKind::Punctuation]);
+        assert_eq!(kinds_of("Other note"), []);
+    }
+
+    #[test]
+    fn incremental_updates_match_a_full_parse() {
+        let section =
+            "# Title\n\n- item **b**\n  more\n\n```\ncode\n\nmore code\n```\n\n> quote\n\npara\n";
+        let mut editor = EditorState::new();
+        let mut layer = SyntaxLayer::new();
+        editor.insert_str(&section.repeat(12));
+        sync(&mut layer, &mut editor);
+        assert!(layer.chunks.len() > 1);
+        assert_eq!(layer_parse(&layer, &editor), full_parse(editor.text()));
+
+        let inserts = [
+            "#", "## ", "\n", "`", "```", "- ", "x", "> ", "\n\n", "  ", "<!--",
+        ];
+        let mut seed = 7usize;
+        for step in 0..300 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+            let offset = seed % (editor.len() + 1);
+            if step % 3 == 0 && editor.len() > 4 {
+                editor.replace_range(offset..offset + 3, "");
+            } else {
+                editor.replace_range(offset..offset, inserts[seed % inserts.len()]);
+            }
+            if step % 5 == 0 {
+                editor.undo();
+            }
+            sync(&mut layer, &mut editor);
+            assert_eq!(
+                layer_parse(&layer, &editor),
+                full_parse(editor.text()),
+                "after step {step}: {:?}",
+                editor.text()
+            );
+        }
+    }
+
+    #[test]
+    fn splits_after_fences_without_blank_lines() {
+        let section = "```\ncode\n\nmore code\n```\n# Title\n- item\n";
+        let mut editor = EditorState::new();
+        let mut layer = SyntaxLayer::new();
+        editor.insert_str(&section.repeat(12));
+        sync(&mut layer, &mut editor);
+        assert!(layer.chunks.len() > 1);
+        assert_eq!(layer_parse(&layer, &editor), full_parse(editor.text()));
+    }
+
+    #[test]
+    fn keeps_inline_trees_outside_the_edit() {
+        let mut editor = EditorState::new();
+        let mut layer = SyntaxLayer::new();
+        editor.insert_str("First *para*.\n\nSecond *para*.\n");
+        sync(&mut layer, &mut editor);
+        let root_ids = |layer: &SyntaxLayer| {
+            let tree = layer.chunks[0].tree.as_ref().unwrap();
+            tree.inlines
+                .iter()
+                .map(|inline| inline.root_node().id())
+                .collect::<Vec<_>>()
+        };
+        let before = root_ids(&layer);
+
+        editor.replace_range(22..22, "x");
+        sync(&mut layer, &mut editor);
+        let after = root_ids(&layer);
+        assert_eq!(after[0], before[0]);
+        assert_ne!(after[1], before[1]);
+        assert_eq!(layer_parse(&layer, &editor), full_parse(editor.text()));
+    }
+
+    /// Only meaningful with optimizations, so it runs under `cargo test --release`.
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "timing needs a release build")]
+    fn incremental_update_of_large_file_is_sub_millisecond() {
+        let section = "## Section\n\nSome *text* with `code` and a [link](https://zeno.dev).\n\n\
+            - item\n- item\n\n```\nfn main() {}\n```\n";
+        let source = section.repeat(10_000 / section.lines().count() + 1);
+        assert!(source.lines().count() >= 10_000);
+
+        let mut editor = EditorState::new();
+        let mut layer = SyntaxLayer::new();
+        editor.insert_str(&source);
+        sync(&mut layer, &mut editor);
+
+        let mut timings = Vec::new();
+        for i in 0..200 {
+            let offset = editor.len() / 2 + i;
+            editor.replace_range(offset..offset, "a");
+            let started = Instant::now();
+            sync(&mut layer, &mut editor);
+            timings.push(started.elapsed());
+        }
+        timings.sort();
+        let median = timings[timings.len() / 2];
+        assert!(
+            median < Duration::from_millis(1),
+            "median update took {median:?}"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use gpui::{App, Global};
+
+/// When buffers backed by a file are written to it without an explicit save. Buffers are always
+/// journalled for crash recovery, whatever these say.
+///
+/// Read from the environment at startup: `ZENO_AUTOSAVE_DELAY_MS` sets the idle delay, with `0`
+/// turning idle autosave off, and `ZENO_AUTOSAVE_ON_FOCUS_LOSS=0` turns off saving when the editor
+/// or window loses focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutosaveSettings {
+    /// How long after the last edit to save. `None` disables saving on idle.
+    pub idle_delay: Option<Duration>,
+    pub on_focus_loss: bool,
+}
+
+impl Global for AutosaveSettings {}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self {
+            idle_delay: Some(Duration::from_secs(2)),
+            on_focus_loss: true,
+        }
+    }
+}
+
+impl AutosaveSettings {
+    pub fn from_env() -> Self {
+        let mut settings = Self::default();
+        if let Some(millis) = std::env::var("ZENO_AUTOSAVE_DELAY_MS")
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+        {
+            settings.idle_delay = (millis > 0).then(|| Duration::from_millis(millis));
+        }
+        if let Ok(value) = std::env::var("ZENO_AUTOSAVE_ON_FOCUS_LOSS") {
+            settings.on_focus_loss = !matches!(value.trim(), "0" | "false" | "off");
+        }
+        settings
+    }
+
+    pub fn get(cx: &App) -> Self {
+        cx.try_global::<Self>().copied().unwrap_or_default()
+    }
+}
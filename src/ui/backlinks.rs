@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+
+use gpui::{
+    ClickEvent, Context, Div, Entity, EventEmitter, IntoElement, Render, SharedString,
+    Subscription, Task, Window, div, prelude::*, px, rgb,
+};
+
+use super::theme;
+use super::vault_index::VaultIndex;
+use crate::vault::index::{Backlink, Backlinks};
+use crate::vault::links::is_note;
+
+/// Emitted when a backlink is clicked, to open its note at its line.
+pub struct OpenBacklink {
+    pub path: PathBuf,
+    /// Zero-based.
+    pub line: usize,
+}
+
+/// The sidebar section listing the notes that link to the active note, and those that mention it
+/// without a link, from the vault's [`VaultIndex`].
+pub struct BacklinksView {
+    vault_index: Entity<VaultIndex>,
+    /// The note whose backlinks are shown.
+    note: Option<PathBuf>,
+    backlinks: Backlinks,
+    show_linked: bool,
+    show_unlinked: bool,
+    /// Finds the backlinks of `note` in the background. Replaced, and so cancelled, when the note
+    /// or the index changes.
+    update: Task<()>,
+    _index_changed: Subscription,
+}
+
+impl EventEmitter<OpenBacklink> for BacklinksView {}
+
+impl BacklinksView {
+    pub fn new(vault_index: Entity<VaultIndex>, cx: &mut Context<Self>) -> Self {
+        let index_changed = cx.observe(&vault_index, |view, _, cx| view.refresh(cx));
+        Self {
+            vault_index,
+            note: None,
+            backlinks: Backlinks::default(),
+            show_linked: true,
+            show_unlinked: false,
+            update: Task::ready(()),
+            _index_changed: index_changed,
+        }
+    }
+
+    /// Shows the backlinks of the note at `note`, typically the active one.
+    pub fn set_note(&mut self, note: Option<PathBuf>, cx: &mut Context<Self>) {
+        if self.note != note {
+            self.note = note;
+            self.backlinks = Backlinks::default();
+            self.refresh(cx);
+            cx.notify();
+        }
+    }
+
+    fn refresh(&mut self, cx: &mut Context<Self>) {
+        let Some(note) = self.note.clone() else {
+            self.update = Task::ready(());
+            return;
+        };
+        let index = self.vault_index.read(cx).index().clone();
+        self.update = cx.spawn(async move |this, cx| {
+            let backlinks = cx
+                .background_executor()
+                .spawn(async move { index.backlinks(&note) })
+                .await;
+            this.update(cx, |view, cx| {
+                view.backlinks = backlinks;
+                cx.notify();
+            })
+            .ok();
+        });
+    }
+
+    fn render_section(
+        &self,
+        id: &'static str,
+        title: &str,
+        backlinks: &[Backlink],
+        expanded: bool,
+        toggle: fn(&mut Self),
+        cx: &mut Context<Self>,
+    ) -> Div {
+        let icon = if expanded { "▼" } else { "▶" };
+        let entries = backlinks.iter().enumerate().map(|(index, backlink)| {
+            let path = backlink.source.clone();
+            let line = backlink.line;
+            let name = backlink
+                .source
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            div()
+                .id((id, index))
+                .flex()
+                .flex_col()
+                .pl(px(22.))
+                .pr_2()
+                .py_0p5()
+                .rounded_sm()
+                .cursor_pointer()
+                .hover(|style| style.bg(rgb(theme::BORDER)))
+                .on_click(cx.listener(move |_, _: &ClickEvent, _, cx| {
+                    cx.emit(OpenBacklink {
+                        path: path.clone(),
+                        line,
+                    })
+                }))
+                .child(div().text_sm().truncate().child(name))
+                .child(
+                    div()
+                        .text_xs()
+                        .text_color(rgb(theme::TEXT_MUTED))
+                        .truncate()
+                        .child(SharedString::from(backlink.snippet.clone())),
+                )
+        });
+        div()
+            .flex()
+            .flex_col()
+            .child(
+                div()
+                    .id(id)
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .gap_1()
+                    .px_2()
+                    .py_0p5()
+                    .rounded_sm()
+                    .text_sm()
+                    .text_color(rgb(theme::TEXT_MUTED))
+                    .cursor_pointer()
+                    .hover(|style| style.bg(rgb(theme::BORDER)))
+                    .on_click(cx.listener(move |view, _, _, cx| {
+                        toggle(view);
+                        cx.notify();
+                    }))
+                    .child(div().w(px(12.)).text_xs().child(icon))
+                    .child(format!("{title} ({})", backlinks.len())),
+            )
+            .when(expanded, |this| this.children(entries))
+    }
+}
+
+impl Render for BacklinksView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let linked = self.render_section(
+            "backlinks",
+            "Backlinks",
+            &self.backlinks.linked,
+            self.show_linked,
+            |view| view.show_linked = !view.show_linked,
+            cx,
+        );
+        let unlinked = self.render_section(
+            "unlinked-mentions",
+            "Unlinked mentions",
+            &self.backlinks.unlinked,
+            self.show_unlinked,
+            |view| view.show_unlinked = !view.show_unlinked,
+            cx,
+        );
+        // Only notes are indexed, so other files have no backlinks to show.
+        let shows_note = self.note.as_deref().is_some_and(is_note);
+        div()
+            .id("backlinks-panel")
+            .flex()
+            .flex_col()
+            .flex_shrink_0()
+            .max_h(px(280.))
+            .overflow_y_scroll()
+            .when(shows_note, |this| {
+                this.border_t_1()
+                    .border_color(rgb(theme::BORDER))
+                    .pt_1()
+                    .child(linked)
+                    .child(unlinked)
+            })
+    }
+}
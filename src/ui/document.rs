@@ -0,0 +1,576 @@
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use futures::StreamExt;
+use futures::channel::mpsc;
+use gpui::{Context, EntityId, EventEmitter, PromptLevel, Task, Window};
+
+use crate::editor::{
+    BufferEdit, EditorState, FileFormat, FileWatcher, Journal, RecoveryEntry, SelectionSet,
+    has_conflict_markers, read_file, three_way_merge, write_file_atomic,
+};
+use crate::markdown::syntax::SyntaxLayer;
+use crate::settings::AutosaveSettings;
+
+/// How long after an edit the buffer is journalled, so a burst of typing is written once.
+const JOURNAL_DELAY: Duration = Duration::from_millis(500);
+
+const FILE_CHANGE_DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub enum DocumentEvent {
+    /// The buffer was edited through the view with the given id, or by the document itself.
+    /// Other views move their selections past `edits`.
+    Edited {
+        edits: Vec<BufferEdit>,
+        view: Option<EntityId>,
+    },
+    /// The whole buffer was replaced, e.g. by loading or reloading its file.
+    Reloaded,
+}
+
+/// A buffer and the file behind it, shared by every editor view showing it. Views keep their own
+/// selections and swap them in to edit.
+pub struct Document {
+    editor: EditorState,
+    syntax: SyntaxLayer,
+    /// The view whose selections edits last applied to. Edits through another view start a new
+    /// undo group.
+    last_view: Option<EntityId>,
+    /// File the buffer was opened from or last saved to. `None` for a new note.
+    path: Option<PathBuf>,
+    /// Line endings and trailing newline to write back on save.
+    file_format: FileFormat,
+    /// Buffer edits applied so far, and how many of them had been applied when the buffer last
+    /// matched the file on disk. `None` when it never has, e.g. after restoring from the journal.
+    edit_count: usize,
+    saved_edit_count: Option<usize>,
+    journal: Option<Journal>,
+    /// Names this buffer's journal entry. Unique across runs so a new session never overwrites an
+    /// entry left by a crashed one.
+    journal_id: String,
+    /// Journals the buffer shortly after an edit, then autosaves it once idle. Replaced, and so
+    /// cancelled, by every edit.
+    pending_autosave: Option<Task<()>>,
+    /// Buffer text as last read from or written to the file: the base for merging in changes
+    /// other programs make to it.
+    saved_text: String,
+    /// The file's contents after another program changed it while the buffer had unsaved edits.
+    disk_conflict: Option<DiskConflict>,
+    /// Whether merging in the file's changes left conflicts marked in the buffer. Autosave waits
+    /// until the markers are gone, so they are not written to disk unasked.
+    unresolved_merge: bool,
+    file_watch: Option<(FileWatcher, Task<()>)>,
+}
+
+struct DiskConflict {
+    text: String,
+    format: FileFormat,
+}
+
+impl EventEmitter<DocumentEvent> for Document {}
+
+impl Document {
+    pub fn new() -> Self {
+        static NEXT_JOURNAL_ID: AtomicUsize = AtomicUsize::new(0);
+        let session = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let journal_id = format!(
+            "{session}-{}-{}",
+            std::process::id(),
+            NEXT_JOURNAL_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        Self {
+            editor: EditorState::new(),
+            syntax: SyntaxLayer::new(),
+            last_view: None,
+            path: None,
+            file_format: FileFormat::default(),
+            edit_count: 0,
+            saved_edit_count: Some(0),
+            journal: Journal::in_app_data(),
+            journal_id,
+            pending_autosave: None,
+            saved_text: String::new(),
+            disk_conflict: None,
+            unresolved_merge: false,
+            file_watch: None,
+        }
+    }
+
+    /// The buffer, with the selections of whichever view last edited it.
+    pub fn state(&self) -> &EditorState {
+        &self.editor
+    }
+
+    pub fn syntax(&self) -> &SyntaxLayer {
+        &self.syntax
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether the buffer has been edited since it was opened or saved.
+    pub fn is_dirty(&self) -> bool {
+        self.saved_edit_count != Some(self.edit_count)
+    }
+
+    /// Whether this is an empty new note the user has not touched, which opening a file can
+    /// replace rather than open beside.
+    pub fn is_blank(&self) -> bool {
+        self.path.is_none() && !self.is_dirty() && self.editor.is_empty()
+    }
+
+    pub fn has_disk_conflict(&self) -> bool {
+        self.disk_conflict.is_some()
+    }
+
+    pub fn has_unresolved_merge(&self) -> bool {
+        self.unresolved_merge
+    }
+
+    /// Runs `f` on the buffer with `selections`, those of the view with id `view`, swapped in.
+    pub fn with_selections<R>(
+        &mut self,
+        view: EntityId,
+        selections: &mut SelectionSet,
+        f: impl FnOnce(&mut EditorState) -> R,
+    ) -> R {
+        if self.last_view != Some(view) {
+            self.editor.break_undo_group();
+            self.last_view = Some(view);
+        }
+        self.editor.swap_selections(selections);
+        let result = f(&mut self.editor);
+        self.editor.swap_selections(selections);
+        result
+    }
+
+    /// Brings the syntax layer up to date with the edits just applied to the buffer through
+    /// `view`, tells the other views about them and schedules journalling and autosave.
+    pub fn buffer_edited(
+        &mut self,
+        view: Option<EntityId>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.apply_buffer_edits(view, cx) {
+            self.schedule_autosave(window, cx);
+        }
+    }
+
+    /// `buffer_edited` without scheduling autosave. Returns whether there were any edits.
+    fn apply_buffer_edits(&mut self, view: Option<EntityId>, cx: &mut Context<Self>) -> bool {
+        let edits = self.editor.take_buffer_edits();
+        if edits.is_empty() {
+            return false;
+        }
+        for edit in &edits {
+            self.syntax.edit(edit);
+            self.edit_count += 1;
+        }
+        self.syntax.reparse(self.editor.buffer());
+        if self.unresolved_merge && !has_conflict_markers(self.editor.text()) {
+            self.unresolved_merge = false;
+        }
+        cx.emit(DocumentEvent::Edited { edits, view });
+        cx.notify();
+        true
+    }
+
+    /// Replaces `edits`' ranges, sorted and not overlapping, as one undoable step made by none of
+    /// the views, e.g. to rewrite links after the notes they point to moved.
+    pub fn edit_ranges(
+        &mut self,
+        edits: Vec<(Range<usize>, String)>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.edit_ranges(edits);
+        self.last_view = None;
+        self.buffer_edited(None, window, cx);
+    }
+
+    /// Replaces the buffer with the contents of the file at `path`, as read from disk.
+    pub fn load(
+        &mut self,
+        path: PathBuf,
+        text: String,
+        format: FileFormat,
+        cx: &mut Context<Self>,
+    ) {
+        self.set_path(Some(path), cx);
+        self.replace_buffer(text.clone(), format, cx);
+        self.mark_saved(text, cx);
+    }
+
+    /// Replaces the buffer with unsaved contents recovered from the journal. The buffer stays
+    /// dirty until saved.
+    pub fn restore(&mut self, entry: &RecoveryEntry, cx: &mut Context<Self>) {
+        let (text, format) = match &entry.path {
+            Some(_) => FileFormat::decode(&entry.contents),
+            None => (entry.contents.clone(), FileFormat::default()),
+        };
+        self.set_path(entry.path.clone(), cx);
+        self.replace_buffer(text, format, cx);
+        self.saved_edit_count = None;
+        self.saved_text.clear();
+        // Journalled under this buffer's own id, as the restored entry is discarded.
+        self.write_journal(cx).detach();
+
+        // The file as it is now is the base for merging in later changes to it.
+        if let Some(path) = entry.path.clone() {
+            cx.spawn(async move |this, cx| {
+                let read_path = path.clone();
+                let read = cx
+                    .background_executor()
+                    .spawn(async move { read_file(&read_path) })
+                    .await;
+                if let Ok((text, _)) = read {
+                    this.update(cx, |document, _| {
+                        if document.path.as_deref() == Some(path.as_path()) {
+                            document.saved_text = text;
+                        }
+                    })
+                    .ok();
+                }
+            })
+            .detach();
+        }
+    }
+
+    /// Replaces the buffer with the file's new contents after another program changed it, as one
+    /// edit that undo takes back like any other. Views keep their cursors and scroll positions
+    /// where they were.
+    fn reload(&mut self, text: String, format: FileFormat, cx: &mut Context<Self>) {
+        self.editor.replace_all(&text);
+        self.last_view = None;
+        self.apply_buffer_edits(None, cx);
+        self.file_format = format;
+        self.pending_autosave = None;
+        self.mark_saved(text, cx);
+    }
+
+    fn replace_buffer(&mut self, text: String, format: FileFormat, cx: &mut Context<Self>) {
+        self.editor = EditorState::new();
+        self.editor.set_text(text);
+        self.editor.set_cursor(0);
+        self.editor.take_buffer_edits();
+        self.syntax = SyntaxLayer::new();
+        self.syntax.reparse(self.editor.buffer());
+        self.file_format = format;
+        self.edit_count = 0;
+        self.pending_autosave = None;
+        self.disk_conflict = None;
+        self.unresolved_merge = false;
+        cx.emit(DocumentEvent::Reloaded);
+        cx.notify();
+    }
+
+    /// Records that the buffer matches `text`, the file's contents as of the last read or write.
+    fn mark_saved(&mut self, text: String, cx: &mut Context<Self>) {
+        self.saved_text = text;
+        self.saved_edit_count = Some(self.edit_count);
+        self.disk_conflict = None;
+        self.discard_journal(cx);
+        cx.notify();
+    }
+
+    fn set_path(&mut self, path: Option<PathBuf>, cx: &mut Context<Self>) {
+        if self.path != path {
+            self.path = path;
+            self.watch_file(cx);
+        }
+    }
+
+    /// Points the buffer at its file's new location after the file was renamed or moved, or at
+    /// no file once it was trashed, leaving the text as an unsaved note.
+    pub fn follow_file(&mut self, path: Option<PathBuf>, cx: &mut Context<Self>) {
+        if path.is_none() {
+            self.saved_edit_count = None;
+        }
+        self.set_path(path, cx);
+        if self.is_dirty() {
+            self.write_journal(cx).detach();
+        }
+        cx.notify();
+    }
+
+    /// Starts watching the buffer's file for changes made by other programs, such as agents
+    /// editing the note from the terminal.
+    fn watch_file(&mut self, cx: &mut Context<Self>) {
+        self.file_watch = None;
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let (changes_tx, mut changes) = mpsc::unbounded();
+        let Ok(watcher) = FileWatcher::new(&path, changes_tx) else {
+            return;
+        };
+        let task = cx.spawn(async move |this, cx| {
+            while changes.next().await.is_some() {
+                // Writers often touch a file several times in a row; check once they are done.
+                cx.background_executor().timer(FILE_CHANGE_DEBOUNCE).await;
+                while let Ok(Some(())) = changes.try_next() {}
+                if this
+                    .update(cx, |document, cx| document.check_file(cx))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        self.file_watch = Some((watcher, task));
+    }
+
+    fn check_file(&mut self, cx: &mut Context<Self>) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        cx.spawn(async move |this, cx| {
+            let read_path = path.clone();
+            let read = cx
+                .background_executor()
+                .spawn(async move { read_file(&read_path) })
+                .await;
+            // A file that was deleted or cannot be read is left alone; the buffer keeps its text
+            // and saving writes it back.
+            let Ok((text, format)) = read else {
+                return;
+            };
+            this.update(cx, |document, cx| {
+                if document.path.as_deref() == Some(path.as_path()) {
+                    document.file_changed(text, format, cx);
+                }
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn file_changed(&mut self, text: String, format: FileFormat, cx: &mut Context<Self>) {
+        if text == self.editor.text() {
+            // Already in sync, e.g. after our own save or the same edit made elsewhere.
+            self.file_format = format;
+            self.mark_saved(text, cx);
+        } else if text == self.saved_text {
+            // Touched without changing the text.
+        } else if self.is_dirty() {
+            self.disk_conflict = Some(DiskConflict { text, format });
+            cx.notify();
+        } else {
+            self.reload(text, format, cx);
+        }
+    }
+
+    /// Drops the buffer's edits in favour of the file's new contents.
+    pub fn take_theirs(&mut self, cx: &mut Context<Self>) {
+        if let Some(conflict) = self.disk_conflict.take() {
+            self.reload(conflict.text, conflict.format, cx);
+        }
+    }
+
+    /// Keeps the buffer as it is; saving overwrites the file's new contents.
+    pub fn keep_mine(&mut self, cx: &mut Context<Self>) {
+        if let Some(conflict) = self.disk_conflict.take() {
+            self.saved_text = conflict.text;
+            self.saved_edit_count = None;
+            cx.notify();
+        }
+    }
+
+    /// Merges the file's new contents into the buffer as a single undoable edit made through
+    /// `view`, taking the file as last read or written as the common base. Changes that overlap
+    /// are left between conflict markers, and autosave waits until they are resolved.
+    pub fn merge_theirs(
+        &mut self,
+        view: EntityId,
+        selections: &mut SelectionSet,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(conflict) = self.disk_conflict.take() else {
+            return;
+        };
+        let merged = three_way_merge(&self.saved_text, self.editor.text(), &conflict.text);
+        self.with_selections(view, selections, |editor| editor.replace_all(&merged.text));
+        self.saved_text = conflict.text;
+        self.saved_edit_count = None;
+        self.unresolved_merge = merged.has_conflicts;
+        self.buffer_edited(Some(view), window, cx);
+        cx.notify();
+    }
+
+    /// Saves the buffer to its file, asking for a path if it has none. Resolves to whether it was
+    /// saved.
+    pub fn save_file(&mut self, window: &mut Window, cx: &mut Context<Self>) -> Task<bool> {
+        match self.path.clone() {
+            Some(path) => self.save_to(path, window, cx),
+            None => self.save_file_as(window, cx),
+        }
+    }
+
+    pub fn save_file_as(&mut self, window: &mut Window, cx: &mut Context<Self>) -> Task<bool> {
+        let directory = self
+            .path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let suggested_name = self
+            .path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled.md".to_string());
+        let path = cx.prompt_for_new_path(&directory, Some(&suggested_name));
+        cx.spawn_in(window, async move |this, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return false;
+            };
+            let Ok(saved) = this.update_in(cx, |document, window, cx| {
+                document.save_to(path, window, cx)
+            }) else {
+                return false;
+            };
+            saved.await
+        })
+    }
+
+    /// Writes the buffer to `path` off the main thread. The buffer counts as saved at the edit it
+    /// had reached when the write started, so typing during the write leaves it dirty.
+    fn save_to(
+        &mut self,
+        path: PathBuf,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Task<bool> {
+        let text = self.editor.text().to_string();
+        let contents = self.file_format.encode(&text);
+        let edit_count = self.edit_count;
+        cx.spawn_in(window, async move |this, cx| {
+            let write_path = path.clone();
+            let written = cx
+                .background_executor()
+                .spawn(async move { write_file_atomic(&write_path, contents.as_bytes()) })
+                .await;
+            this.update_in(cx, |document, window, cx| match written {
+                Ok(()) => {
+                    document.set_path(Some(path), cx);
+                    document.saved_text = text;
+                    document.saved_edit_count = Some(edit_count);
+                    document.disk_conflict = None;
+                    if !document.is_dirty() {
+                        document.discard_journal(cx);
+                    }
+                    cx.notify();
+                    true
+                }
+                Err(error) => {
+                    document.report_file_error("save", &path, error, window, cx);
+                    false
+                }
+            })
+            .unwrap_or(false)
+        })
+    }
+
+    fn schedule_autosave(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let idle_delay = AutosaveSettings::get(cx).idle_delay;
+        self.pending_autosave = Some(cx.spawn_in(window, async move |this, cx| {
+            cx.background_executor().timer(JOURNAL_DELAY).await;
+            let Ok(journalled) = this.update(cx, |document, cx| document.write_journal(cx)) else {
+                return;
+            };
+            journalled.await;
+            let Some(idle_delay) = idle_delay else {
+                return;
+            };
+            cx.background_executor()
+                .timer(idle_delay.saturating_sub(JOURNAL_DELAY))
+                .await;
+            this.update_in(cx, |document, window, cx| document.autosave(window, cx))
+                .ok();
+        }));
+    }
+
+    /// Journals the buffer and, if configured, saves it straight away rather than waiting for the
+    /// idle delay. Otherwise the idle timer keeps running and autosaves as usual.
+    pub fn focus_lost(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.pending_autosave.is_none() {
+            return;
+        }
+        self.write_journal(cx).detach();
+        if AutosaveSettings::get(cx).on_focus_loss {
+            // Saving now replaces the pending autosave.
+            self.autosave(window, cx);
+        }
+    }
+
+    fn autosave(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.pending_autosave = None;
+        // Notes never saved stay in the journal until the user picks a name for them, and a file
+        // changed elsewhere is not overwritten until the user decides what to keep and has
+        // resolved any conflicts merging left.
+        if let Some(path) = self.path.clone()
+            && self.is_dirty()
+            && self.disk_conflict.is_none()
+            && !self.unresolved_merge
+        {
+            self.save_to(path, window, cx).detach();
+        }
+    }
+
+    /// Records the buffer's unsaved contents in the journal, or removes its entry if there are
+    /// none. Journalling is best effort: failing to write it must not get in the way of editing.
+    fn write_journal(&mut self, cx: &mut Context<Self>) -> Task<()> {
+        let Some(journal) = self.journal.clone() else {
+            return Task::ready(());
+        };
+        let id = self.journal_id.clone();
+        if !self.is_dirty() {
+            return cx.background_executor().spawn(async move {
+                journal.remove(&id).ok();
+            });
+        }
+        let path = self.path.clone();
+        let contents = match &path {
+            Some(_) => self.file_format.encode(self.editor.text()),
+            None => self.editor.text().to_string(),
+        };
+        cx.background_executor().spawn(async move {
+            journal.write(&id, path.as_deref(), &contents).ok();
+        })
+    }
+
+    pub fn discard_journal(&mut self, cx: &mut Context<Self>) {
+        if let Some(journal) = self.journal.clone() {
+            let id = self.journal_id.clone();
+            cx.background_executor()
+                .spawn(async move {
+                    journal.remove(&id).ok();
+                })
+                .detach();
+        }
+    }
+
+    fn report_file_error(
+        &mut self,
+        verb: &str,
+        path: &Path,
+        error: io::Error,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let message = format!("Could not {verb} {}", path.display());
+        let detail = error.to_string();
+        // The prompt has a single answer, so there is nothing to wait for.
+        drop(window.prompt(PromptLevel::Critical, &message, Some(&detail), &["OK"], cx));
+    }
+}
@@ -1,171 +1,1360 @@
-use std::ops::Range;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::time::Duration;
 
 use gpui::{
-    App, Bounds, Context, CursorStyle, Element, ElementId, ElementInputHandler, Entity,
-    EntityInputHandler, FocusHandle, Focusable, GlobalElementId, LayoutId, MouseButton,
-    MouseDownEvent, MouseMoveEvent, MouseUpEvent, PaintQuad, Pixels, Point, ShapedLine,
-    SharedString, Style, TextRun, UTF16Selection, UnderlineStyle, Window, actions, div, fill, hsla,
-    point, prelude::*, px, relative, rgb, rgba,
+    AnyElement, App, Bounds, ClipboardItem, ContentMask, Context, CursorStyle, Div, Element,
+    ElementId, ElementInputHandler, Entity, EntityInputHandler, EventEmitter, FocusHandle,
+    Focusable, Font, FontStyle, FontWeight, GlobalElementId, Hsla, LayoutId, MouseButton,
+    MouseDownEvent, MouseMoveEvent, MouseUpEvent, PaintQuad, Pixels, Point, ScrollWheelEvent,
+    SharedString, Stateful, Style, Subscription, TextAlign, TextRun, UTF16Selection,
+    UnderlineStyle, Window, WrappedLine, actions, anchored, deferred, div, fill, hsla, point,
+    prelude::*, px, relative, rgb, rgba, size,
 };
 
-use crate::editor::EditorState;
-use crate::markdown::highlight::{HighlightKind, HighlightSpan, highlight_markdown};
+use super::document::{Document, DocumentEvent};
+use super::theme;
+use super::vault_index::VaultIndex;
+use crate::editor::{
+    EditorState, LineLayout, MonospaceLayout, SelectionGranularity, SelectionSet, granular_range_at,
+};
+use crate::markdown::decoration::{ConcealedLine, Decoration, DecorationStyle};
+use crate::markdown::highlight::{HighlightKind, HighlightSpan};
+use crate::markdown::links::{find_wiki_links, wiki_link_query};
+use crate::markdown::paste::prepare_paste;
+use crate::vault::index::Completion;
 
 actions!(
     zeno_editor,
     [
         Backspace,
         Delete,
+        DeleteToPreviousWordStart,
+        DeleteToNextWordEnd,
         Left,
         Right,
+        Up,
+        Down,
+        Home,
+        End,
+        MoveToBeginning,
+        MoveToEnd,
+        MoveToPreviousWordStart,
+        MoveToNextWordEnd,
+        MoveToPreviousParagraph,
+        MoveToNextParagraph,
         SelectLeft,
         SelectRight,
+        SelectUp,
+        SelectDown,
+        SelectHome,
+        SelectEnd,
+        SelectToBeginning,
+        SelectToEnd,
+        SelectToPreviousWordStart,
+        SelectToNextWordEnd,
+        SelectToPreviousParagraph,
+        SelectToNextParagraph,
         SelectAll,
-        Newline
+        Newline,
+        Undo,
+        Redo,
+        Cut,
+        Copy,
+        Paste,
+        AddCursorAbove,
+        AddCursorBelow,
+        SelectNextOccurrence,
+        Cancel,
+        ToggleReadableWidth,
+        Save,
+        SaveAs,
+        ConfirmCompletion,
+        PreviousCompletion,
+        NextCompletion
     ]
 );
 
-fn color_for_highlight_kind(kind: HighlightKind) -> Option<gpui::Hsla> {
-    match kind {
-        HighlightKind::Heading => Some(rgb(0x82aaff).into()),
-        HighlightKind::Strong => Some(rgb(0xffcb6b).into()),
-        HighlightKind::Emphasis => Some(rgb(0xffcb6b).into()),
-        HighlightKind::Code => Some(rgb(0xc3e88d).into()),
-        HighlightKind::Link => Some(rgb(0x89ddff).into()),
-        HighlightKind::Punctuation => Some(rgb(0x7a7a7a).into()),
-        HighlightKind::Other => None,
+/// Rows of the wiki link completion list shown at once; the rest scroll into view with the
+/// selection.
+const VISIBLE_COMPLETIONS: usize = 8;
+
+fn color_for_highlight_kind(kind: HighlightKind) -> Option<gpui::Hsla> {
+    match kind {
+        HighlightKind::Heading { .. } => Some(rgb(0x82aaff).into()),
+        HighlightKind::Strong => Some(rgb(0xffcb6b).into()),
+        HighlightKind::Emphasis => Some(rgb(0xffcb6b).into()),
+        HighlightKind::Code => Some(rgb(0xc3e88d).into()),
+        HighlightKind::Link | HighlightKind::WikiLink => Some(rgb(0x89ddff).into()),
+        HighlightKind::Punctuation => Some(rgb(0x7a7a7a).into()),
+        HighlightKind::Keyword => Some(rgb(0xc792ea).into()),
+        HighlightKind::String => Some(rgb(0xecc48d).into()),
+        HighlightKind::Comment => Some(rgb(0x697098).into()),
+        HighlightKind::Function => Some(rgb(0x82aaff).into()),
+        HighlightKind::Other => None,
+    }
+}
+
+fn runs_for_line(
+    base_run: &TextRun,
+    line_start: usize,
+    line_end: usize,
+    highlights: &[HighlightSpan],
+) -> Vec<TextRun> {
+    let local_spans: Vec<(usize, usize, HighlightKind)> = highlights
+        .iter()
+        .filter_map(|span| {
+            if span.range.end <= line_start || span.range.start >= line_end {
+                return None;
+            }
+            let start = span.range.start.max(line_start) - line_start;
+            let end = span.range.end.min(line_end) - line_start;
+            (start < end).then_some((start, end, span.kind))
+        })
+        .collect();
+
+    // Spans nest (a keyword inside a fenced code block, a delimiter inside strong text), so each
+    // piece of the line takes the colour of the innermost coloured span covering it.
+    let mut boundaries = local_spans
+        .iter()
+        .flat_map(|&(start, end, _)| [start, end])
+        .chain([0, base_run.len])
+        .collect::<Vec<_>>();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut runs: Vec<TextRun> = Vec::new();
+    for piece in boundaries.windows(2) {
+        let (start, end) = (piece[0], piece[1]);
+        let covering = local_spans
+            .iter()
+            .filter(|&&(s, e, _)| s <= start && e >= end);
+        let color = covering
+            .clone()
+            .filter_map(|&(s, e, kind)| {
+                Some(((s, std::cmp::Reverse(e)), color_for_highlight_kind(kind)?))
+            })
+            .max_by_key(|&(key, _)| key)
+            .map_or(base_run.color, |(_, color)| color);
+        // Font changes accumulate instead: strong text inside emphasis is bold and italic.
+        let mut font = base_run.font.clone();
+        for &(_, _, kind) in covering {
+            match kind {
+                HighlightKind::Heading { .. } | HighlightKind::Strong => {
+                    font.weight = FontWeight::BOLD;
+                }
+                HighlightKind::Emphasis => font.style = FontStyle::Italic,
+                _ => {}
+            }
+        }
+        match runs.last_mut() {
+            Some(last) if last.color == color && last.font == font => last.len += end - start,
+            _ => runs.push(TextRun {
+                len: end - start,
+                color,
+                font,
+                ..base_run.clone()
+            }),
+        }
+    }
+
+    runs.into_iter().filter(|r| r.len > 0).collect()
+}
+
+/// How much larger than body text a line is shaped: each heading level is smaller than the one
+/// above it, down to body size for level 6.
+fn heading_scale(highlights: &[HighlightSpan]) -> f32 {
+    let level = highlights.iter().find_map(|span| match span.kind {
+        HighlightKind::Heading { level } => Some(level),
+        _ => None,
+    });
+    match level {
+        Some(1) => 1.6,
+        Some(2) => 1.4,
+        Some(3) => 1.25,
+        Some(4) => 1.1,
+        Some(5) => 1.05,
+        _ => 1.0,
+    }
+}
+
+/// Adds the background of inline code and the colour and underline of links to runs covering a
+/// line whose markers are concealed.
+fn style_decorated_runs(
+    runs: Vec<TextRun>,
+    line_start: usize,
+    decorations: &[Decoration],
+) -> Vec<TextRun> {
+    let mut styled = Vec::with_capacity(runs.len());
+    let mut start = line_start;
+    for run in runs {
+        let end = start + run.len;
+        let mut boundaries = decorations
+            .iter()
+            .flat_map(|decoration| [decoration.full_range.start, decoration.full_range.end])
+            .filter(|&offset| offset > start && offset < end)
+            .collect::<Vec<_>>();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut piece_start = start;
+        for piece_end in boundaries.into_iter().chain([end]) {
+            let mut piece = TextRun {
+                len: piece_end - piece_start,
+                ..run.clone()
+            };
+            let covering = decorations.iter().filter(|decoration| {
+                decoration.full_range.start <= piece_start && decoration.full_range.end >= piece_end
+            });
+            for decoration in covering {
+                match decoration.style {
+                    DecorationStyle::InlineCode => {
+                        piece.background_color = Some(rgba(0xffffff14).into());
+                    }
+                    DecorationStyle::Link { .. } => {
+                        piece.color = rgb(0x89ddff).into();
+                        piece.underline = Some(UnderlineStyle {
+                            color: Some(piece.color),
+                            thickness: px(1.0),
+                            wavy: false,
+                        });
+                    }
+                    // Already styled by the highlight runs, which revealed lines share.
+                    DecorationStyle::Heading { .. }
+                    | DecorationStyle::Bold
+                    | DecorationStyle::Italic => {}
+                }
+            }
+            styled.push(piece);
+            piece_start = piece_end;
+        }
+        start = end;
+    }
+    styled
+}
+
+/// Shortens runs built over a raw line so they cover its concealed display text instead.
+fn conceal_runs(runs: Vec<TextRun>, line: &ConcealedLine) -> Vec<TextRun> {
+    let mut start = 0;
+    runs.into_iter()
+        .filter_map(|run| {
+            let end = start + run.len;
+            let len = line.display_offset(end) - line.display_offset(start);
+            start = end;
+            (len > 0).then_some(TextRun { len, ..run })
+        })
+        .collect()
+}
+
+/// The byte range of `text` that `range_utf16`, in UTF-16 code units, covers.
+fn utf16_range_in(text: &str, range_utf16: &Range<usize>) -> Range<usize> {
+    let offset = |utf16_offset: usize| {
+        let mut count = 0;
+        text.char_indices()
+            .find(|(_, ch)| {
+                let reached = count >= utf16_offset;
+                count += ch.len_utf16();
+                reached
+            })
+            .map_or(text.len(), |(offset, _)| offset)
+    };
+    offset(range_utf16.start)..offset(range_utf16.end)
+}
+
+/// Width lines wrap at in readable-width mode, roughly 80 characters of body text.
+const READABLE_WIDTH: Pixels = px(700.);
+
+/// A line as shaped by the last prepaint, soft wrapped into one or more visual rows. Columns are
+/// byte offsets into the raw line; when its markers are concealed they are mapped to the shaped
+/// display text.
+struct DisplayLine {
+    shaped: WrappedLine,
+    concealed: Option<ConcealedLine>,
+    /// Display index at which each visual row starts, beginning with 0.
+    row_starts: Vec<usize>,
+    /// Offset of the line's top from the top of the text area; negative when scrolled above it.
+    top: Pixels,
+    row_height: Pixels,
+}
+
+impl DisplayLine {
+    fn new(shaped: WrappedLine, concealed: Option<ConcealedLine>, row_height: Pixels) -> Self {
+        let wrap_indices = shaped.wrap_boundaries().iter().map(|boundary| {
+            shaped.unwrapped_layout.runs[boundary.run_ix].glyphs[boundary.glyph_ix].index
+        });
+        let row_starts = std::iter::once(0).chain(wrap_indices).collect();
+        Self {
+            shaped,
+            concealed,
+            row_starts,
+            top: px(0.),
+            row_height,
+        }
+    }
+
+    fn height(&self) -> Pixels {
+        self.row_height * self.row_starts.len() as f32
+    }
+
+    /// Length of the raw line.
+    fn len(&self) -> usize {
+        let display_len = self.shaped.text.len();
+        self.concealed
+            .as_ref()
+            .map_or(display_len, |line| line.raw_offset(display_len))
+    }
+
+    fn display_index(&self, column: usize) -> usize {
+        self.concealed
+            .as_ref()
+            .map_or(column, |line| line.display_offset(column))
+    }
+
+    fn column(&self, display_index: usize) -> usize {
+        self.concealed
+            .as_ref()
+            .map_or(display_index, |line| line.raw_offset(display_index))
+    }
+
+    fn row_count(&self) -> usize {
+        self.row_starts.len()
+    }
+
+    fn row_for_display_index(&self, index: usize) -> usize {
+        self.row_starts
+            .partition_point(|&start| start <= index)
+            .saturating_sub(1)
+    }
+
+    fn row_for_column(&self, column: usize) -> usize {
+        self.row_for_display_index(self.display_index(column))
+    }
+
+    /// Display indices a position on `row` can take. Rows other than the last end before the
+    /// first character of the next row, which would otherwise be drawn there.
+    fn row_range(&self, row: usize) -> Range<usize> {
+        let start = self.row_starts[row];
+        let end = match self.row_starts.get(row + 1) {
+            Some(&next) => self.shaped.text[..next]
+                .char_indices()
+                .next_back()
+                .map_or(start, |(index, _)| index.max(start)),
+            None => self.shaped.text.len(),
+        };
+        start..end
+    }
+
+    /// Unwrapped x at which `row` starts.
+    fn row_x(&self, row: usize) -> Pixels {
+        self.shaped
+            .unwrapped_layout
+            .x_for_index(self.row_starts[row])
+    }
+
+    /// Position of `column` relative to the top-left corner of the line.
+    fn position_for_column(&self, column: usize) -> Point<Pixels> {
+        let index = self.display_index(column);
+        let row = self.row_for_display_index(index);
+        point(
+            self.shaped.unwrapped_layout.x_for_index(index) - self.row_x(row),
+            self.row_height * row as f32,
+        )
+    }
+
+    fn column_for_x(&self, row: usize, x: Pixels) -> usize {
+        let row = row.min(self.row_count() - 1);
+        let range = self.row_range(row);
+        let index = self
+            .shaped
+            .unwrapped_layout
+            .closest_index_for_x(x + self.row_x(row));
+        self.column(index.clamp(range.start, range.end))
+    }
+
+    /// Column of the character under `position`, relative to the top-left corner of the line, or
+    /// the end of its row when `position` is past it.
+    fn column_for_position(&self, position: Point<Pixels>) -> usize {
+        let row = (position.y / self.row_height).floor().max(0.) as usize;
+        let row = row.min(self.row_count() - 1);
+        let range = self.row_range(row);
+        let index = self
+            .shaped
+            .unwrapped_layout
+            .index_for_x(position.x + self.row_x(row))
+            .map_or(range.end, |index| index.clamp(range.start, range.end));
+        self.column(index)
+    }
+
+    /// One rectangle per visual row covered by `columns`, relative to the top-left corner of the
+    /// line.
+    fn bounds_for_columns(&self, columns: Range<usize>) -> Vec<Bounds<Pixels>> {
+        let start = self.display_index(columns.start);
+        let end = self.display_index(columns.end);
+        let layout = &self.shaped.unwrapped_layout;
+        (self.row_for_display_index(start)..=self.row_for_display_index(end))
+            .filter_map(|row| {
+                let row_end = self
+                    .row_starts
+                    .get(row + 1)
+                    .copied()
+                    .unwrap_or(self.shaped.text.len());
+                let (from, to) = (start.max(self.row_starts[row]), end.min(row_end));
+                let top = self.row_height * row as f32;
+                (from < to).then(|| {
+                    Bounds::from_corners(
+                        point(layout.x_for_index(from) - self.row_x(row), top),
+                        point(
+                            layout.x_for_index(to) - self.row_x(row),
+                            top + self.row_height,
+                        ),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// Measures goal columns against the lines shaped during the last prepaint.
+struct ShapedLineLayout<'a> {
+    layout: &'a LastLayout,
+}
+
+impl LineLayout for ShapedLineLayout<'_> {
+    fn x_for_column(&self, line: usize, column: usize) -> f32 {
+        self.layout
+            .line(line)
+            .map(|line| line.position_for_column(column).x.into())
+            .unwrap_or(0.)
+    }
+
+    fn column_for_x(&self, line: usize, row: usize, x: f32) -> usize {
+        self.layout
+            .line(line)
+            .map(|line| line.column_for_x(row, px(x)))
+            .unwrap_or(0)
+    }
+
+    fn row_count(&self, line: usize) -> usize {
+        self.layout.line(line).map_or(1, DisplayLine::row_count)
+    }
+
+    fn row_for_column(&self, line: usize, column: usize) -> usize {
+        self.layout
+            .line(line)
+            .map_or(0, |line| line.row_for_column(column))
+    }
+}
+
+/// The first line at least partly visible and how far its top is scrolled above the text area.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ScrollPosition {
+    line: usize,
+    offset: Pixels,
+}
+
+/// Lines left visible above the one `go_to_line` scrolls to.
+const GO_TO_LINE_CONTEXT: usize = 3;
+
+/// Lines shaped above and below the visible ones, so drags and vertical motion near the edges
+/// still find laid-out lines.
+const OVERSCAN_LINES: usize = 4;
+
+/// The lines laid out by the last paint: only those in view plus `OVERSCAN_LINES` either side.
+struct LastLayout {
+    /// The text area: the element's bounds, narrowed to the readable width if set.
+    bounds: Bounds<Pixels>,
+    first_line: usize,
+    /// `top`s are relative to the top of `bounds`.
+    lines: Vec<DisplayLine>,
+    /// Lines in the buffer when it was laid out.
+    line_count: usize,
+    /// Advance of a digit in the editor's font, for estimating the lines not laid out.
+    char_width: Pixels,
+}
+
+impl LastLayout {
+    fn end_line(&self) -> usize {
+        self.first_line + self.lines.len()
+    }
+
+    fn line(&self, index: usize) -> Option<&DisplayLine> {
+        self.lines.get(index.checked_sub(self.first_line)?)
+    }
+
+    /// Index of the laid-out line at `y`, relative to the top of `bounds`, clamped to the lines
+    /// laid out.
+    fn line_at_y(&self, y: Pixels) -> usize {
+        let offset = self
+            .lines
+            .partition_point(|line| line.top + line.height() <= y);
+        self.first_line + offset.min(self.lines.len().saturating_sub(1))
+    }
+}
+
+/// Shapes lines on demand during one prepaint, keeping each for reuse.
+struct LineShaper<'a> {
+    view: &'a EditorView,
+    document: &'a Document,
+    font: Font,
+    color: Hsla,
+    font_size: Pixels,
+    line_height: Pixels,
+    wrap_width: Pixels,
+    /// Lines that show their raw Markdown.
+    revealed: Vec<RangeInclusive<usize>>,
+    shaped: HashMap<usize, DisplayLine>,
+}
+
+impl LineShaper<'_> {
+    fn line_count(&self) -> usize {
+        self.document.state().buffer().line_count()
+    }
+
+    fn line(&mut self, index: usize, window: &mut Window) -> &DisplayLine {
+        if !self.shaped.contains_key(&index) {
+            let line = self.shape(index, window);
+            self.shaped.insert(index, line);
+        }
+        &self.shaped[&index]
+    }
+
+    fn take(&mut self, index: usize, window: &mut Window) -> DisplayLine {
+        match self.shaped.remove(&index) {
+            Some(line) => line,
+            None => self.shape(index, window),
+        }
+    }
+
+    /// Distance from the top of the text area down to the top of line `to`, given up on once it
+    /// exceeds `limit`.
+    fn distance(
+        &mut self,
+        scroll: ScrollPosition,
+        to: usize,
+        limit: Pixels,
+        window: &mut Window,
+    ) -> Pixels {
+        let mut distance = -scroll.offset;
+        for index in scroll.line..to {
+            if distance > limit {
+                break;
+            }
+            distance += self.line(index, window).height();
+        }
+        distance
+    }
+
+    /// Moves `scroll` to the line its offset lands in, keeping at least the last row of the
+    /// document on screen.
+    fn normalize(&mut self, mut scroll: ScrollPosition, window: &mut Window) -> ScrollPosition {
+        let last = self.line_count() - 1;
+        scroll.line = scroll.line.min(last);
+        while scroll.offset < px(0.) && scroll.line > 0 {
+            scroll.line -= 1;
+            scroll.offset += self.line(scroll.line, window).height();
+        }
+        loop {
+            let line = self.line(scroll.line, window);
+            let height = line.height();
+            if scroll.offset >= height && scroll.line < last {
+                scroll.offset -= height;
+                scroll.line += 1;
+            } else {
+                let max_offset = height - line.row_height;
+                scroll.offset = scroll.offset.min(max_offset).max(px(0.));
+                return scroll;
+            }
+        }
+    }
+
+    fn shape(&self, index: usize, window: &mut Window) -> DisplayLine {
+        let editor = self.view;
+        let document = self.document;
+        let buffer = document.state().buffer();
+        let is_empty = buffer.is_empty();
+        let (line, start, end, color) = if is_empty {
+            let placeholder = Cow::Borrowed(editor.placeholder.as_ref());
+            (placeholder, 0, 0, hsla(0., 0., 1., 0.5))
+        } else {
+            let Range { start, end } = buffer.line_range(index);
+            (buffer.line(index), start, end, self.color)
+        };
+        let slice = line.as_ref();
+        let highlights = if is_empty {
+            Vec::new()
+        } else {
+            document.syntax().highlights_in(start..end)
+        };
+
+        let base_run = TextRun {
+            len: slice.len(),
+            font: self.font.clone(),
+            color,
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+        };
+
+        let marked = editor
+            .marked_ranges
+            .iter()
+            .filter(|marked| marked.start >= start && marked.end <= end)
+            .collect::<Vec<_>>();
+        let runs = if is_empty {
+            vec![base_run]
+        } else if !marked.is_empty() {
+            let mut runs = Vec::new();
+            let mut run_start = start;
+            for marked in marked {
+                runs.push(TextRun {
+                    len: marked.start - run_start,
+                    ..base_run.clone()
+                });
+                runs.push(TextRun {
+                    len: marked.end - marked.start,
+                    underline: Some(UnderlineStyle {
+                        color: Some(base_run.color),
+                        thickness: px(1.0),
+                        wavy: false,
+                    }),
+                    ..base_run.clone()
+                });
+                run_start = marked.end;
+            }
+            runs.push(TextRun {
+                len: end - run_start,
+                ..base_run
+            });
+            runs.retain(|run| run.len > 0);
+            runs
+        } else {
+            runs_for_line(&base_run, start, end, &highlights)
+        };
+
+        let revealed = is_empty || self.revealed.iter().any(|lines| lines.contains(&index));
+        let decorations = if revealed {
+            Vec::new()
+        } else {
+            document.syntax().decorations_in(start..end)
+        };
+        let concealed = (!decorations.is_empty())
+            .then(|| ConcealedLine::new(slice, start, &decorations))
+            .filter(ConcealedLine::is_concealing);
+        let (line_text, runs) = match &concealed {
+            Some(concealed) => (
+                concealed.text.clone(),
+                conceal_runs(style_decorated_runs(runs, start, &decorations), concealed),
+            ),
+            None => (slice.to_string(), runs),
+        };
+
+        let line_font_size = self.font_size * heading_scale(&highlights);
+        let row_height = self.line_height * (line_font_size / self.font_size);
+        let shaped = window
+            .text_system()
+            .shape_text(
+                line_text.into(),
+                line_font_size,
+                &runs,
+                Some(self.wrap_width),
+                None,
+            )
+            .ok()
+            .and_then(|shaped| shaped.into_iter().next())
+            .unwrap_or_default();
+        DisplayLine::new(shaped, concealed, row_height)
+    }
+}
+
+/// Emitted when a wiki link is cmd-clicked (ctrl-clicked off macOS), naming the note to open.
+pub struct FollowWikiLink {
+    pub target: String,
+}
+
+/// Notes offered for the wiki link being typed at the cursor.
+struct LinkCompletion {
+    /// What choosing a completion replaces: from just after the link's `[[` to the cursor, or
+    /// past the link's `]]`.
+    range: Range<usize>,
+    query: String,
+    items: Vec<Completion>,
+    selected: usize,
+}
+
+pub struct EditorView {
+    focus_handle: FocusHandle,
+    document: Entity<Document>,
+    /// This view's selections, swapped into the document's `EditorState` while it edits.
+    selections: SelectionSet,
+    placeholder: SharedString,
+    /// The text an input method is composing, one range per cursor.
+    marked_ranges: Vec<Range<usize>>,
+    is_selecting: bool,
+    /// Range selected by the initial click of a drag and the unit further dragging extends by.
+    drag_origin: Option<(Range<usize>, SelectionGranularity)>,
+    /// `(line, x)` where an alt-drag column selection started.
+    column_select_origin: Option<(usize, f32)>,
+    /// Caps the wrap width, centring the text in the editor. `None` wraps at the editor's width.
+    readable_width: Option<Pixels>,
+    scroll_position: ScrollPosition,
+    /// Cursor offset last scrolled into view. Autoscroll happens when the cursor moves, so
+    /// scrolling away from a cursor that stays put is left alone.
+    autoscrolled_cursor: Option<usize>,
+    /// Whether a task is scrolling towards a drag selection held above or below the text area.
+    drag_autoscroll: bool,
+    last_drag_position: Option<Point<Pixels>>,
+    last_layout: Option<LastLayout>,
+    /// The open vault's notes, offered when typing a wiki link.
+    vault_index: Option<Entity<VaultIndex>>,
+    completion: Option<LinkCompletion>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl EventEmitter<FollowWikiLink> for EditorView {}
+
+impl EditorView {
+    /// A view of a new, empty note.
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
+        let document = cx.new(|_| Document::new());
+        Self::for_document(document, window, cx)
+    }
+
+    /// Another view of `document`, with its own cursors and scroll position.
+    pub fn for_document(
+        document: Entity<Document>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Self {
+        let focus_handle = cx.focus_handle();
+        let subscriptions = vec![
+            cx.on_blur(&focus_handle, window, Self::focus_lost),
+            cx.observe_window_activation(window, |view, window, cx| {
+                if !window.is_window_active() {
+                    view.focus_lost(window, cx);
+                }
+            }),
+            cx.subscribe(&document, Self::on_document_event),
+            cx.observe(&document, |_, _, cx| cx.notify()),
+        ];
+        Self {
+            focus_handle,
+            document,
+            selections: SelectionSet::default(),
+            placeholder: "Type here…".into(),
+            marked_ranges: Vec::new(),
+            is_selecting: false,
+            drag_origin: None,
+            column_select_origin: None,
+            readable_width: None,
+            scroll_position: ScrollPosition::default(),
+            autoscrolled_cursor: None,
+            drag_autoscroll: false,
+            last_drag_position: None,
+            last_layout: None,
+            vault_index: None,
+            completion: None,
+            _subscriptions: subscriptions,
+        }
+    }
+
+    /// Another view of this view's document, starting out with the same cursors, scroll position
+    /// and width.
+    pub fn duplicate(&self, window: &mut Window, cx: &mut Context<Self>) -> Entity<Self> {
+        let document = self.document.clone();
+        let selections = self.selections.clone();
+        let scroll_position = self.scroll_position;
+        let readable_width = self.readable_width;
+        let vault_index = self.vault_index.clone();
+        cx.new(|cx| {
+            let mut view = Self::for_document(document, window, cx);
+            view.vault_index = vault_index;
+            view.autoscrolled_cursor = Some(selections.cursor());
+            view.selections = selections;
+            view.scroll_position = scroll_position;
+            view.readable_width = readable_width;
+            view
+        })
+    }
+
+    pub fn document(&self) -> &Entity<Document> {
+        &self.document
+    }
+
+    /// Puts the cursor at the start of `line`, zero-based, and scrolls it near the top.
+    pub fn go_to_line(&mut self, line: usize, cx: &mut Context<Self>) {
+        let cursor = self.with_state(cx, |editor| {
+            editor.set_cursor(editor.buffer().line_start(line));
+            editor.cursor()
+        });
+        let line = self
+            .document
+            .read(cx)
+            .state()
+            .buffer()
+            .line_for_offset(cursor);
+        self.scroll_position = ScrollPosition {
+            line: line.saturating_sub(GO_TO_LINE_CONTEXT),
+            offset: px(0.),
+        };
+        self.autoscrolled_cursor = Some(cursor);
+        cx.notify();
+    }
+
+    /// Offers the notes in `vault_index` when a wiki link is typed.
+    pub fn set_vault_index(&mut self, vault_index: Entity<VaultIndex>) {
+        self.vault_index = Some(vault_index);
+    }
+
+    fn on_document_event(
+        &mut self,
+        _: Entity<Document>,
+        event: &DocumentEvent,
+        cx: &mut Context<Self>,
+    ) {
+        match event {
+            DocumentEvent::Edited { edits, view } => {
+                if *view == Some(cx.entity_id()) {
+                    return;
+                }
+                for edit in edits {
+                    self.selections.apply_buffer_edit(edit);
+                }
+                self.marked_ranges.clear();
+                // Keep the view where it was rather than following the moved cursor.
+                self.autoscrolled_cursor = Some(self.selections.cursor());
+            }
+            DocumentEvent::Reloaded => {
+                let state = self.document.read(cx).state();
+                let cursor = self.selections.cursor().min(state.len());
+                let last_line = state.buffer().line_count().saturating_sub(1);
+                self.selections = SelectionSet::cursor_at(cursor);
+                self.scroll_position.line = self.scroll_position.line.min(last_line);
+                self.autoscrolled_cursor = Some(self.selections.cursor());
+                self.marked_ranges.clear();
+                self.last_layout = None;
+            }
+        }
+        cx.notify();
+    }
+
+    /// Runs `f` on the document's buffer with this view's selections.
+    fn with_state<R>(
+        &mut self,
+        cx: &mut Context<Self>,
+        f: impl FnOnce(&mut EditorState) -> R,
+    ) -> R {
+        let view = cx.entity_id();
+        let selections = &mut self.selections;
+        self.document.update(cx, |document, _| {
+            document.with_selections(view, selections, f)
+        })
+    }
+
+    /// Updates the document after the edits just applied through this view.
+    fn buffer_edited(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view = cx.entity_id();
+        self.document.update(cx, |document, cx| {
+            document.buffer_edited(Some(view), window, cx)
+        });
+    }
+
+    fn backspace(&mut self, _: &Backspace, window: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.backspace());
+        self.buffer_edited(window, cx);
+        // Deleting refines completions already shown but does not bring them up.
+        if self.completion.is_some() {
+            self.update_completion(cx);
+        }
+        cx.notify();
+    }
+
+    fn delete(&mut self, _: &Delete, window: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.delete_forward());
+        self.buffer_edited(window, cx);
+        cx.notify();
+    }
+
+    fn delete_to_previous_word_start(
+        &mut self,
+        _: &DeleteToPreviousWordStart,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.delete_to_prev_word_start());
+        self.buffer_edited(window, cx);
+        cx.notify();
+    }
+
+    fn delete_to_next_word_end(
+        &mut self,
+        _: &DeleteToNextWordEnd,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.delete_to_next_word_end());
+        self.buffer_edited(window, cx);
+        cx.notify();
+    }
+
+    fn left(&mut self, _: &Left, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_left(false));
+        cx.notify();
+    }
+
+    fn right(&mut self, _: &Right, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_right(false));
+        cx.notify();
+    }
+
+    /// Runs `f` with the geometry of the lines shaped during the last prepaint, falling back to a
+    /// monospace approximation before the first paint, while the layout is stale or when a cursor
+    /// is out of view. The approximation measures in pixels and wraps like the shaped lines, so
+    /// goal columns carry over between the two.
+    fn with_line_layout(
+        &mut self,
+        cx: &mut Context<Self>,
+        f: impl FnOnce(&mut EditorState, &dyn LineLayout),
+    ) {
+        let view = cx.entity_id();
+        let selections = &mut self.selections;
+        let last_layout = self.last_layout.as_ref();
+        self.document.update(cx, |document, _| {
+            document.with_selections(view, selections, |editor| {
+                let buffer = editor.buffer();
+                let shaped_layout = last_layout.filter(|layout| {
+                    // Every cursor and the lines either side of it must have been laid out.
+                    layout.line_count == buffer.line_count()
+                        && editor.selections().iter().all(|selection| {
+                            let line = buffer.line_for_offset(selection.cursor);
+                            line.saturating_sub(1) >= layout.first_line
+                                && (line + 1).min(layout.line_count - 1) < layout.end_line()
+                        })
+                });
+                if let Some(layout) = shaped_layout {
+                    f(editor, &ShapedLineLayout { layout });
+                } else {
+                    let buffer = buffer.clone();
+                    let estimate = match last_layout {
+                        Some(layout) => MonospaceLayout::new(&buffer)
+                            .with_char_width(layout.char_width.into())
+                            .wrapped((layout.bounds.size.width / layout.char_width) as usize),
+                        None => MonospaceLayout::new(&buffer),
+                    };
+                    f(editor, &estimate);
+                }
+            })
+        });
+    }
+
+    fn move_vertically(&mut self, down: bool, extend_selection: bool, cx: &mut Context<Self>) {
+        self.with_line_layout(cx, |editor, layout| {
+            if down {
+                editor.move_down(extend_selection, layout);
+            } else {
+                editor.move_up(extend_selection, layout);
+            }
+        });
+    }
+
+    fn up(&mut self, _: &Up, _: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(false, false, cx);
+        cx.notify();
+    }
+
+    fn down(&mut self, _: &Down, _: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(true, false, cx);
+        cx.notify();
+    }
+
+    fn home(&mut self, _: &Home, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_line_start(false));
+        cx.notify();
+    }
+
+    fn end(&mut self, _: &End, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_line_end(false));
+        cx.notify();
+    }
+
+    fn move_to_beginning(&mut self, _: &MoveToBeginning, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_beginning(false));
+        cx.notify();
+    }
+
+    fn move_to_end(&mut self, _: &MoveToEnd, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_end(false));
+        cx.notify();
+    }
+
+    fn move_to_previous_word_start(
+        &mut self,
+        _: &MoveToPreviousWordStart,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_prev_word_start(false));
+        cx.notify();
+    }
+
+    fn move_to_next_word_end(
+        &mut self,
+        _: &MoveToNextWordEnd,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_next_word_end(false));
+        cx.notify();
+    }
+
+    fn move_to_previous_paragraph(
+        &mut self,
+        _: &MoveToPreviousParagraph,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_prev_paragraph(false));
+        cx.notify();
+    }
+
+    fn move_to_next_paragraph(
+        &mut self,
+        _: &MoveToNextParagraph,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_next_paragraph(false));
+        cx.notify();
+    }
+
+    fn select_left(&mut self, _: &SelectLeft, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_left(true));
+        cx.notify();
+    }
+
+    fn select_right(&mut self, _: &SelectRight, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_right(true));
+        cx.notify();
+    }
+
+    fn select_up(&mut self, _: &SelectUp, _: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(false, true, cx);
+        cx.notify();
+    }
+
+    fn select_down(&mut self, _: &SelectDown, _: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(true, true, cx);
+        cx.notify();
+    }
+
+    fn select_home(&mut self, _: &SelectHome, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_line_start(true));
+        cx.notify();
+    }
+
+    fn select_end(&mut self, _: &SelectEnd, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_line_end(true));
+        cx.notify();
+    }
+
+    fn select_to_beginning(
+        &mut self,
+        _: &SelectToBeginning,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_beginning(true));
+        cx.notify();
+    }
+
+    fn select_to_end(&mut self, _: &SelectToEnd, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.move_to_end(true));
+        cx.notify();
+    }
+
+    fn select_to_previous_word_start(
+        &mut self,
+        _: &SelectToPreviousWordStart,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_prev_word_start(true));
+        cx.notify();
+    }
+
+    fn select_to_next_word_end(
+        &mut self,
+        _: &SelectToNextWordEnd,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_next_word_end(true));
+        cx.notify();
+    }
+
+    fn select_to_previous_paragraph(
+        &mut self,
+        _: &SelectToPreviousParagraph,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_prev_paragraph(true));
+        cx.notify();
+    }
+
+    fn select_to_next_paragraph(
+        &mut self,
+        _: &SelectToNextParagraph,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.move_to_next_paragraph(true));
+        cx.notify();
+    }
+
+    fn select_all(&mut self, _: &SelectAll, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.select_all());
+        cx.notify();
+    }
+
+    fn newline(&mut self, _: &Newline, window: &mut Window, cx: &mut Context<Self>) {
+        self.with_state(cx, |editor| editor.insert_str("\n"));
+        self.buffer_edited(window, cx);
+        cx.notify();
+    }
+
+    fn undo(&mut self, _: &Undo, window: &mut Window, cx: &mut Context<Self>) {
+        if self.with_state(cx, |editor| editor.undo()) {
+            self.marked_ranges.clear();
+            self.buffer_edited(window, cx);
+            cx.notify();
+        }
+    }
+
+    fn redo(&mut self, _: &Redo, window: &mut Window, cx: &mut Context<Self>) {
+        if self.with_state(cx, |editor| editor.redo()) {
+            self.marked_ranges.clear();
+            self.buffer_edited(window, cx);
+            cx.notify();
+        }
+    }
+
+    fn cut(&mut self, _: &Cut, window: &mut Window, cx: &mut Context<Self>) {
+        let selected = self.with_state(cx, |editor| editor.selected_text());
+        if selected.is_empty() {
+            return;
+        }
+        cx.write_to_clipboard(ClipboardItem::new_string(selected));
+        self.with_state(cx, |editor| {
+            editor.replace_selections_with(|_, _| String::new())
+        });
+        self.buffer_edited(window, cx);
+        cx.notify();
+    }
+
+    fn copy(&mut self, _: &Copy, _: &mut Window, cx: &mut Context<Self>) {
+        let selected = self.with_state(cx, |editor| editor.selected_text());
+        if selected.is_empty() {
+            return;
+        }
+        cx.write_to_clipboard(ClipboardItem::new_string(selected));
+    }
+
+    fn paste(&mut self, _: &Paste, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(pasted) = cx.read_from_clipboard().and_then(|item| item.text()) else {
+            return;
+        };
+        self.with_state(cx, |editor| {
+            editor.replace_selections_with(|selected, line_prefix| {
+                prepare_paste(&pasted, selected, line_prefix)
+            })
+        });
+        self.marked_ranges.clear();
+        self.buffer_edited(window, cx);
+        cx.notify();
+    }
+
+    fn add_cursor_above(&mut self, _: &AddCursorAbove, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_line_layout(cx, |editor, layout| editor.add_cursor_above(layout));
+        cx.notify();
     }
-}
 
-fn runs_for_line(
-    base_run: &TextRun,
-    line_start: usize,
-    line_end: usize,
-    highlights: &[HighlightSpan],
-) -> Vec<TextRun> {
-    let mut local_spans: Vec<(usize, usize, HighlightKind)> = highlights
-        .iter()
-        .filter_map(|span| {
-            if span.range.end <= line_start || span.range.start >= line_end {
-                return None;
-            }
-            let start = span.range.start.max(line_start) - line_start;
-            let end = span.range.end.min(line_end) - line_start;
-            (start < end).then_some((start, end, span.kind))
-        })
-        .collect();
+    fn add_cursor_below(&mut self, _: &AddCursorBelow, _: &mut Window, cx: &mut Context<Self>) {
+        self.with_line_layout(cx, |editor, layout| editor.add_cursor_below(layout));
+        cx.notify();
+    }
+
+    fn select_next_occurrence(
+        &mut self,
+        _: &SelectNextOccurrence,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.with_state(cx, |editor| editor.select_next_occurrence());
+        cx.notify();
+    }
 
-    local_spans.sort_by_key(|(start, end, _)| (*start, *end));
+    fn cancel(&mut self, _: &Cancel, _: &mut Window, cx: &mut Context<Self>) {
+        if self.completion.take().is_none() {
+            self.with_state(cx, |editor| editor.clear_extra_selections());
+        }
+        cx.notify();
+    }
 
-    let mut runs = Vec::new();
-    let mut pos = 0usize;
-    for (start, end, kind) in local_spans {
-        if start > pos {
-            runs.push(TextRun {
-                len: start - pos,
-                ..base_run.clone()
-            });
+    /// The wiki link being typed at the cursor, if there is a single cursor: the range a
+    /// completion replaces and the text typed after `[[`.
+    fn link_query_at_cursor(&self, cx: &App) -> Option<(Range<usize>, String)> {
+        if self.selections.selections.len() > 1 || !self.selections.primary().is_empty() {
+            return None;
         }
+        let buffer = self.document.read(cx).state().buffer();
+        let cursor = self.selections.cursor();
+        let line_range = buffer.line_range(buffer.line_for_offset(cursor));
+        let line = buffer.slice(line_range.clone());
+        let (range, query) = wiki_link_query(&line, cursor - line_range.start)?;
+        Some((
+            line_range.start + range.start..line_range.start + range.end,
+            query.to_string(),
+        ))
+    }
 
-        let Some(color) = color_for_highlight_kind(kind) else {
-            continue;
+    /// Shows completions for the wiki link being typed at the cursor, or hides them when there
+    /// is none or nothing matches.
+    fn update_completion(&mut self, cx: &mut Context<Self>) {
+        self.completion = None;
+        let (Some((range, query)), Some(vault_index)) =
+            (self.link_query_at_cursor(cx), &self.vault_index)
+        else {
+            return;
         };
-
-        let start = start.max(pos);
-        if end > start {
-            runs.push(TextRun {
-                len: end - start,
-                color,
-                ..base_run.clone()
+        let source = self.document.read(cx).path();
+        let items = vault_index.read(cx).index().complete(&query, source);
+        if !items.is_empty() {
+            self.completion = Some(LinkCompletion {
+                range,
+                query,
+                items,
+                selected: 0,
             });
-            pos = end;
         }
     }
 
-    if base_run.len > pos {
-        runs.push(TextRun {
-            len: base_run.len - pos,
-            ..base_run.clone()
-        });
+    /// Hides the completions once the cursor has left the link they were for, and refreshes
+    /// them if the text typed in it changed some other way, e.g. by undo.
+    fn sync_completion(&mut self, cx: &mut Context<Self>) {
+        let Some(completion) = &self.completion else {
+            return;
+        };
+        match self.link_query_at_cursor(cx) {
+            Some((range, query)) if range.start == completion.range.start => {
+                if query != completion.query {
+                    self.update_completion(cx);
+                } else if let Some(completion) = &mut self.completion {
+                    completion.range = range;
+                }
+            }
+            _ => self.completion = None,
+        }
     }
 
-    runs.into_iter().filter(|r| r.len > 0).collect()
-}
-
-pub struct EditorView {
-    focus_handle: FocusHandle,
-    editor: EditorState,
-    placeholder: SharedString,
-    marked_range: Option<Range<usize>>,
-    is_selecting: bool,
-    highlights: Vec<HighlightSpan>,
-    last_bounds: Option<Bounds<Pixels>>,
-    last_line_height: Option<Pixels>,
-    last_line_starts: Option<Vec<usize>>,
-    last_lines: Option<Vec<ShapedLine>>,
-}
+    fn previous_completion(
+        &mut self,
+        _: &PreviousCompletion,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(completion) = &mut self.completion {
+            let count = completion.items.len();
+            completion.selected = (completion.selected + count - 1) % count;
+            cx.notify();
+        }
+    }
 
-impl EditorView {
-    pub fn new(cx: &mut Context<Self>) -> Self {
-        Self {
-            focus_handle: cx.focus_handle(),
-            editor: EditorState::new(),
-            placeholder: "Type here…".into(),
-            marked_range: None,
-            is_selecting: false,
-            highlights: vec![],
-            last_bounds: None,
-            last_line_height: None,
-            last_line_starts: None,
-            last_lines: None,
+    fn next_completion(&mut self, _: &NextCompletion, _: &mut Window, cx: &mut Context<Self>) {
+        if let Some(completion) = &mut self.completion {
+            completion.selected = (completion.selected + 1) % completion.items.len();
+            cx.notify();
         }
     }
 
-    fn update_highlights(&mut self) {
-        self.highlights = highlight_markdown(self.editor.text());
+    fn confirm_completion(
+        &mut self,
+        _: &ConfirmCompletion,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(selected) = self
+            .completion
+            .as_ref()
+            .map(|completion| completion.selected)
+        {
+            self.accept_completion(selected, window, cx);
+        }
     }
 
-    fn backspace(&mut self, _: &Backspace, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.backspace();
-        self.update_highlights();
+    /// Completes the link with the completion at `index`, leaving the cursor after its `]]`.
+    fn accept_completion(&mut self, index: usize, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(completion) = self.completion.take() else {
+            return;
+        };
+        let Some(item) = completion.items.get(index) else {
+            return;
+        };
+        let text = format!("{}]]", item.text);
+        self.with_state(cx, |editor| {
+            editor.replace_range(completion.range.clone(), &text)
+        });
+        self.buffer_edited(window, cx);
         cx.notify();
     }
 
-    fn delete(&mut self, _: &Delete, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.delete_forward();
-        self.update_highlights();
+    fn toggle_readable_width(
+        &mut self,
+        _: &ToggleReadableWidth,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.readable_width = match self.readable_width {
+            Some(_) => None,
+            None => Some(READABLE_WIDTH),
+        };
         cx.notify();
     }
 
-    fn left(&mut self, _: &Left, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_left(false);
-        cx.notify();
+    fn take_theirs(&mut self, cx: &mut Context<Self>) {
+        self.document
+            .update(cx, |document, cx| document.take_theirs(cx));
     }
 
-    fn right(&mut self, _: &Right, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_right(false);
-        cx.notify();
+    fn keep_mine(&mut self, cx: &mut Context<Self>) {
+        self.document
+            .update(cx, |document, cx| document.keep_mine(cx));
     }
 
-    fn select_left(&mut self, _: &SelectLeft, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_left(true);
-        cx.notify();
+    fn merge_theirs(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let view = cx.entity_id();
+        let selections = &mut self.selections;
+        self.document.update(cx, |document, cx| {
+            document.merge_theirs(view, selections, window, cx)
+        });
     }
 
-    fn select_right(&mut self, _: &SelectRight, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_right(true);
-        cx.notify();
+    fn save(&mut self, _: &Save, window: &mut Window, cx: &mut Context<Self>) {
+        self.document
+            .update(cx, |document, cx| document.save_file(window, cx))
+            .detach();
     }
 
-    fn select_all(&mut self, _: &SelectAll, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.select_all();
-        cx.notify();
+    fn save_as(&mut self, _: &SaveAs, window: &mut Window, cx: &mut Context<Self>) {
+        self.document
+            .update(cx, |document, cx| document.save_file_as(window, cx))
+            .detach();
     }
 
-    fn newline(&mut self, _: &Newline, _: &mut Window, cx: &mut Context<Self>) {
-        self.editor.insert_str("\n");
-        self.update_highlights();
-        cx.notify();
+    fn focus_lost(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.completion = None;
+        self.document
+            .update(cx, |document, cx| document.focus_lost(window, cx));
     }
 
     fn on_mouse_down(
@@ -175,16 +1364,54 @@ impl EditorView {
         cx: &mut Context<Self>,
     ) {
         window.focus(&self.focus_handle(cx));
+        if event.modifiers.secondary()
+            && let Some(target) =
+                self.wiki_link_target_at(self.index_for_mouse_position(event.position, cx), cx)
+        {
+            cx.emit(FollowWikiLink { target });
+            return;
+        }
         self.is_selecting = true;
-        let offset = self.index_for_mouse_position(event.position);
-        if event.modifiers.shift {
-            self.editor.set_selection(self.editor.anchor(), offset);
-        } else {
-            self.editor.set_cursor(offset);
+        if event.modifiers.alt {
+            self.column_select_origin = self.line_and_x_for_mouse_position(event.position);
+            if let Some(origin) = self.column_select_origin {
+                self.with_line_layout(cx, |editor, layout| {
+                    editor.select_columns(origin, origin, layout);
+                });
+            }
+            cx.notify();
+            return;
         }
+
+        self.column_select_origin = None;
+        let offset = self.index_for_mouse_position(event.position, cx);
+        let granularity = SelectionGranularity::from_click_count(event.click_count);
+        let origin = if event.modifiers.shift {
+            let anchor = self.selections.primary().anchor;
+            anchor..anchor
+        } else {
+            granular_range_at(self.document.read(cx).state().buffer(), offset, granularity)
+        };
+        self.with_state(cx, |editor| {
+            editor.select_granular(origin.clone(), offset, granularity)
+        });
+        self.drag_origin = Some((origin, granularity));
         cx.notify();
     }
 
+    /// The target of the wiki link at `offset`, if there is one with a target.
+    fn wiki_link_target_at(&self, offset: usize, cx: &App) -> Option<String> {
+        let document = self.document.read(cx);
+        let span = document
+            .syntax()
+            .highlights_in(offset..offset + 1)
+            .into_iter()
+            .find(|span| span.kind == HighlightKind::WikiLink && span.range.contains(&offset))?;
+        let text = document.state().buffer().slice(span.range);
+        let link = find_wiki_links(&text).into_iter().next()?;
+        Some(text[link.target].to_string())
+    }
+
     fn on_mouse_move(
         &mut self,
         event: &MouseMoveEvent,
@@ -194,73 +1421,151 @@ impl EditorView {
         if !self.is_selecting {
             return;
         }
-        let offset = self.index_for_mouse_position(event.position);
-        self.editor.set_selection(self.editor.anchor(), offset);
+        self.last_drag_position = Some(event.position);
+        self.extend_drag_selection(event.position, cx);
+        if self.drag_overshoot(event.position) != px(0.) && !self.drag_autoscroll {
+            self.drag_autoscroll = true;
+            cx.spawn(async move |this, cx| {
+                loop {
+                    cx.background_executor()
+                        .timer(Duration::from_millis(16))
+                        .await;
+                    let scrolling = this.update(cx, |view, cx| view.autoscroll_drag(cx));
+                    if !scrolling.unwrap_or(false) {
+                        break;
+                    }
+                }
+            })
+            .detach();
+        }
         cx.notify();
     }
 
     fn on_mouse_up(&mut self, _: &MouseUpEvent, _window: &mut Window, _cx: &mut Context<Self>) {
         self.is_selecting = false;
+        self.drag_origin = None;
+        self.column_select_origin = None;
+        self.last_drag_position = None;
     }
 
-    fn offset_to_utf16(&self, utf8_offset: usize) -> usize {
-        let offset = utf8_offset.min(self.editor.text().len());
-        self.editor.text()[..offset].encode_utf16().count()
+    fn on_scroll_wheel(
+        &mut self,
+        event: &ScrollWheelEvent,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let delta = event.delta.pixel_delta(window.line_height());
+        // Clamped to the document by the next prepaint.
+        self.scroll_position.offset -= delta.y;
+        cx.notify();
     }
 
-    fn offset_from_utf16(&self, utf16_offset: usize) -> usize {
-        let text = self.editor.text();
-        let mut count = 0usize;
-        for (byte_idx, ch) in text.char_indices() {
-            if count >= utf16_offset {
-                return byte_idx;
+    /// Extends the drag selection to `position`, held inside the text area vertically so that
+    /// dragging past an edge selects up to the edge while `autoscroll_drag` scrolls.
+    fn extend_drag_selection(&mut self, mut position: Point<Pixels>, cx: &mut Context<Self>) {
+        if let Some(layout) = &self.last_layout {
+            let bounds = layout.bounds;
+            position.y = position
+                .y
+                .clamp(bounds.top(), (bounds.bottom() - px(1.)).max(bounds.top()));
+        }
+        if let Some(origin) = self.column_select_origin {
+            if let Some(head) = self.line_and_x_for_mouse_position(position) {
+                self.with_line_layout(cx, |editor, layout| {
+                    editor.select_columns(origin, head, layout);
+                });
             }
-            count += ch.len_utf16();
+            return;
+        }
+        let Some((origin, granularity)) = self.drag_origin.clone() else {
+            return;
+        };
+        let offset = self.index_for_mouse_position(position, cx);
+        self.with_state(cx, |editor| {
+            editor.select_granular(origin, offset, granularity)
+        });
+    }
+
+    /// How far `position` is above (negative) or below the text area.
+    fn drag_overshoot(&self, position: Point<Pixels>) -> Pixels {
+        let Some(layout) = &self.last_layout else {
+            return px(0.);
+        };
+        if position.y < layout.bounds.top() {
+            position.y - layout.bounds.top()
+        } else if position.y > layout.bounds.bottom() {
+            position.y - layout.bounds.bottom()
+        } else {
+            px(0.)
         }
-        text.len()
     }
 
-    fn range_to_utf16(&self, range: &Range<usize>) -> Range<usize> {
-        self.offset_to_utf16(range.start)..self.offset_to_utf16(range.end)
+    /// One frame of scrolling towards a drag held outside the text area, faster the further out
+    /// it is. Returns whether to keep going.
+    fn autoscroll_drag(&mut self, cx: &mut Context<Self>) -> bool {
+        let overshoot = self
+            .last_drag_position
+            .filter(|_| self.is_selecting)
+            .map_or(px(0.), |position| self.drag_overshoot(position));
+        if overshoot == px(0.) {
+            self.drag_autoscroll = false;
+            return false;
+        }
+        self.scroll_position.offset += overshoot / 4.;
+        if let Some(position) = self.last_drag_position {
+            self.extend_drag_selection(position, cx);
+        }
+        cx.notify();
+        true
     }
 
-    fn range_from_utf16(&self, range_utf16: &Range<usize>) -> Range<usize> {
-        self.offset_from_utf16(range_utf16.start)..self.offset_from_utf16(range_utf16.end)
+    fn offset_to_utf16(&self, utf8_offset: usize, cx: &App) -> usize {
+        let buffer = self.document.read(cx).state().buffer();
+        buffer.offset_to_utf16(utf8_offset)
     }
 
-    fn index_for_mouse_position(&self, position: Point<Pixels>) -> usize {
-        if self.editor.text().is_empty() {
-            return 0;
+    fn offset_from_utf16(&self, utf16_offset: usize, cx: &App) -> usize {
+        let buffer = self.document.read(cx).state().buffer();
+        buffer.offset_from_utf16(utf16_offset)
+    }
+
+    fn range_to_utf16(&self, range: &Range<usize>, cx: &App) -> Range<usize> {
+        self.offset_to_utf16(range.start, cx)..self.offset_to_utf16(range.end, cx)
+    }
+
+    fn range_from_utf16(&self, range_utf16: &Range<usize>, cx: &App) -> Range<usize> {
+        self.offset_from_utf16(range_utf16.start, cx)..self.offset_from_utf16(range_utf16.end, cx)
+    }
+
+    /// Line index and x offset within the text for a window position, clamped to the laid-out
+    /// lines so drags that leave the editor still resolve to the nearest line.
+    fn line_and_x_for_mouse_position(&self, position: Point<Pixels>) -> Option<(usize, f32)> {
+        let layout = self.last_layout.as_ref()?;
+        if layout.lines.is_empty() {
+            return None;
         }
 
-        let Some(bounds) = self.last_bounds else {
-            return 0;
-        };
-        let Some(line_height) = self.last_line_height else {
-            return 0;
-        };
-        let Some(line_starts) = self.last_line_starts.as_ref() else {
+        let local = position - layout.bounds.origin;
+        Some((layout.line_at_y(local.y), f32::from(local.x).max(0.)))
+    }
+
+    fn index_for_mouse_position(&self, position: Point<Pixels>, cx: &App) -> usize {
+        let buffer = self.document.read(cx).state().buffer();
+        if buffer.is_empty() {
             return 0;
-        };
-        let Some(lines) = self.last_lines.as_ref() else {
+        }
+        let (Some(layout), Some((line_index, x))) = (
+            self.last_layout.as_ref(),
+            self.line_and_x_for_mouse_position(position),
+        ) else {
             return 0;
         };
-        let Some(local) = bounds.localize(&position) else {
+        let Some(line) = layout.line(line_index) else {
             return 0;
         };
-        if lines.is_empty() {
-            return 0;
-        }
-
-        let mut line_index = (local.y / line_height).floor() as isize;
-        if line_index < 0 {
-            line_index = 0;
-        }
-        let line_index = (line_index as usize).min(lines.len() - 1);
-
-        let line = &lines[line_index];
-        let col = line.index_for_x(local.x).unwrap_or(line.text.len());
-        (line_starts[line_index] + col).min(self.editor.text().len())
+        let y = position.y - layout.bounds.top() - line.top;
+        let col = line.column_for_position(point(px(x), y));
+        buffer.point_to_offset(line_index, col)
     }
 }
 
@@ -276,54 +1581,58 @@ impl EntityInputHandler for EditorView {
         range_utf16: Range<usize>,
         actual_range: &mut Option<Range<usize>>,
         _window: &mut Window,
-        _cx: &mut Context<Self>,
+        cx: &mut Context<Self>,
     ) -> Option<String> {
-        let range = self.range_from_utf16(&range_utf16);
-        actual_range.replace(self.range_to_utf16(&range));
-        Some(self.editor.text()[range].to_string())
+        let range = self.range_from_utf16(&range_utf16, cx);
+        actual_range.replace(self.range_to_utf16(&range, cx));
+        Some(self.document.read(cx).state().buffer().slice(range))
     }
 
     fn selected_text_range(
         &mut self,
         _ignore_disabled_input: bool,
         _window: &mut Window,
-        _cx: &mut Context<Self>,
+        cx: &mut Context<Self>,
     ) -> Option<UTF16Selection> {
+        let (range, reversed) = self.with_state(cx, |editor| {
+            (editor.selection_range(), editor.selection_reversed())
+        });
         Some(UTF16Selection {
-            range: self.range_to_utf16(&self.editor.selection_range()),
-            reversed: self.editor.selection_reversed(),
+            range: self.range_to_utf16(&range, cx),
+            reversed,
         })
     }
 
     fn marked_text_range(
         &self,
         _window: &mut Window,
-        _cx: &mut Context<Self>,
+        cx: &mut Context<Self>,
     ) -> Option<Range<usize>> {
-        self.marked_range
-            .as_ref()
-            .map(|range| self.range_to_utf16(range))
+        // Input methods compose at the primary cursor; the other cursors follow along.
+        let cursor = self.selections.cursor();
+        self.marked_ranges
+            .iter()
+            .find(|range| range.start <= cursor && cursor <= range.end)
+            .or(self.marked_ranges.first())
+            .map(|range| self.range_to_utf16(range, cx))
     }
 
     fn unmark_text(&mut self, _window: &mut Window, _cx: &mut Context<Self>) {
-        self.marked_range = None;
+        self.marked_ranges.clear();
     }
 
     fn replace_text_in_range(
         &mut self,
         range_utf16: Option<Range<usize>>,
         new_text: &str,
-        _window: &mut Window,
+        window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        let range = range_utf16
-            .as_ref()
-            .map(|r| self.range_from_utf16(r))
-            .or(self.marked_range.clone())
-            .unwrap_or_else(|| self.editor.selection_range());
-        self.editor.replace_range(range, new_text);
-        self.marked_range = None;
-        self.update_highlights();
+        let range = range_utf16.map(|range| self.range_from_utf16(&range, cx));
+        let marked = std::mem::take(&mut self.marked_ranges);
+        self.with_state(cx, |editor| editor.replace_input(range, &marked, new_text));
+        self.buffer_edited(window, cx);
+        self.update_completion(cx);
         cx.notify();
     }
 
@@ -332,71 +1641,56 @@ impl EntityInputHandler for EditorView {
         range_utf16: Option<Range<usize>>,
         new_text: &str,
         new_selected_range_utf16: Option<Range<usize>>,
-        _window: &mut Window,
+        window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        let range = range_utf16
-            .as_ref()
-            .map(|r| self.range_from_utf16(r))
-            .or(self.marked_range.clone())
-            .unwrap_or_else(|| self.editor.selection_range());
-
-        self.editor.replace_range(range.clone(), new_text);
+        let range = range_utf16.map(|range| self.range_from_utf16(&range, cx));
+        let marked = std::mem::take(&mut self.marked_ranges);
+        let inserted = self.with_state(cx, |editor| editor.replace_input(range, &marked, new_text));
         if !new_text.is_empty() {
-            self.marked_range = Some(range.start..range.start + new_text.len());
-        } else {
-            self.marked_range = None;
+            self.marked_ranges = inserted;
         }
 
+        // The selection within the composed text is relative to it, not to the buffer.
         if let Some(new_selected_range_utf16) = new_selected_range_utf16.as_ref() {
-            let new_selected_range = self.range_from_utf16(new_selected_range_utf16);
-            let anchor = (range.start + new_selected_range.start).min(self.editor.text().len());
-            let cursor = (range.start + new_selected_range.end).min(self.editor.text().len());
-            self.editor.set_selection(anchor, cursor);
+            let new_selected_range = utf16_range_in(new_text, new_selected_range_utf16);
+            self.with_state(cx, |editor| {
+                editor.select_before_cursors(new_text.len(), new_selected_range)
+            });
         }
 
-        self.update_highlights();
+        self.buffer_edited(window, cx);
         cx.notify();
     }
 
     fn bounds_for_range(
         &mut self,
         range_utf16: Range<usize>,
-        bounds: Bounds<Pixels>,
+        _element_bounds: Bounds<Pixels>,
         _window: &mut Window,
-        _cx: &mut Context<Self>,
+        cx: &mut Context<Self>,
     ) -> Option<Bounds<Pixels>> {
-        let Some(line_starts) = self.last_line_starts.as_ref() else {
-            return None;
-        };
-        let Some(lines) = self.last_lines.as_ref() else {
-            return None;
-        };
-        let Some(line_height) = self.last_line_height else {
-            return None;
-        };
-        if lines.is_empty() {
-            return None;
-        }
-
-        let range = self.range_from_utf16(&range_utf16);
-        let (line_index, &line_start) = line_starts
-            .iter()
-            .enumerate()
-            .rev()
-            .find(|&(_, &start)| start <= range.start)?;
-        let line = lines.get(line_index)?;
-
-        let local_start = (range.start - line_start).min(line.text.len());
-        let local_end = (range.end - line_start).min(line.text.len());
-        let x0 = line.x_for_index(local_start);
-        let x1 = line.x_for_index(local_end);
-        let top = bounds.top() + line_height * line_index as f32;
-        let bottom = top + line_height;
-
-        Some(Bounds::from_corners(
-            point(bounds.left() + x0, top),
-            point(bounds.left() + x1, bottom),
+        let layout = self.last_layout.as_ref()?;
+        let range = self.range_from_utf16(&range_utf16, cx);
+        let buffer = self.document.read(cx).state().buffer();
+        let line_index = buffer.line_for_offset(range.start);
+        let line_start = buffer.line_start(line_index);
+        let line = layout.line(line_index)?;
+
+        let local_start = (range.start - line_start).min(line.len());
+        let local_end = (range.end - line_start).min(line.len());
+        // A range wrapping onto later rows is reported by its first row.
+        let row_bounds = line
+            .bounds_for_columns(local_start..local_end)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| {
+                let position = line.position_for_column(local_start);
+                Bounds::new(position, size(px(0.), line.row_height))
+            });
+        Some(Bounds::new(
+            layout.bounds.origin + point(px(0.), line.top) + row_bounds.origin,
+            row_bounds.size,
         ))
     }
 
@@ -404,9 +1698,9 @@ impl EntityInputHandler for EditorView {
         &mut self,
         point: Point<Pixels>,
         _window: &mut Window,
-        _cx: &mut Context<Self>,
+        cx: &mut Context<Self>,
     ) -> Option<usize> {
-        Some(self.offset_to_utf16(self.index_for_mouse_position(point)))
+        Some(self.offset_to_utf16(self.index_for_mouse_position(point, cx), cx))
     }
 }
 
@@ -415,11 +1709,11 @@ struct EditorElement {
 }
 
 struct PrepaintState {
-    lines: Vec<ShapedLine>,
-    line_starts: Vec<usize>,
+    layout: Option<LastLayout>,
+    scroll: ScrollPosition,
+    autoscrolled_cursor: usize,
     selection_quads: Vec<PaintQuad>,
-    cursor: Option<PaintQuad>,
-    line_height: Pixels,
+    cursors: Vec<PaintQuad>,
 }
 
 impl IntoElement for EditorElement {
@@ -465,159 +1759,158 @@ impl Element for EditorElement {
         cx: &mut App,
     ) -> Self::PrepaintState {
         let editor = self.editor.read(cx);
-        let text = editor.editor.text();
-        let selection = editor.editor.selection_range();
-        let cursor = editor.editor.cursor();
+        let document = editor.document.read(cx);
+        let buffer = document.state().buffer();
         let style = window.text_style();
-        let font_size = style.font_size.to_pixels(window.rem_size());
-        let line_height = window.line_height();
 
-        let (display_text, text_color) = if text.is_empty() {
-            (editor.placeholder.clone(), hsla(0., 0., 1., 0.5))
-        } else {
-            (text.to_string().into(), style.color)
-        };
+        let wrap_width = editor
+            .readable_width
+            .map_or(bounds.size.width, |width| width.min(bounds.size.width));
+        let text_bounds = Bounds::new(
+            point(
+                bounds.left() + (bounds.size.width - wrap_width) / 2.,
+                bounds.top(),
+            ),
+            size(wrap_width, bounds.size.height),
+        );
 
-        let mut line_starts = vec![0usize];
-        for (idx, b) in display_text.as_bytes().iter().enumerate() {
-            if *b == b'\n' {
-                line_starts.push(idx + 1);
+        // Lines holding a cursor, a selection or IME composition show their raw Markdown; every
+        // other line conceals its syntax markers.
+        let revealed = editor
+            .selections
+            .selections
+            .iter()
+            .map(|selection| selection.range())
+            .chain(editor.marked_ranges.iter().cloned())
+            .map(|range| buffer.line_for_offset(range.start)..=buffer.line_for_offset(range.end))
+            .collect();
+        let font_size = style.font_size.to_pixels(window.rem_size());
+        let text_system = window.text_system();
+        let char_width = text_system
+            .ch_advance(text_system.resolve_font(&style.font()), font_size)
+            .unwrap_or(font_size / 2.)
+            .max(px(1.));
+        let mut shaper = LineShaper {
+            view: editor,
+            document,
+            font: style.font(),
+            color: style.color,
+            font_size,
+            line_height: window.line_height(),
+            wrap_width,
+            revealed,
+            shaped: HashMap::new(),
+        };
+        let line_count = shaper.line_count();
+        let viewport_height = text_bounds.size.height;
+
+        let mut scroll = editor.scroll_position;
+        let cursor = editor.selections.cursor();
+        if editor.autoscrolled_cursor != Some(cursor) && !buffer.is_empty() {
+            let (cursor_line, column) = buffer.offset_to_point(cursor);
+            let line = shaper.line(cursor_line, window);
+            let cursor_top = line.position_for_column(column.min(line.len())).y;
+            let cursor_bottom = cursor_top + line.row_height;
+            if (cursor_line, cursor_top) < (scroll.line, scroll.offset) {
+                scroll = ScrollPosition {
+                    line: cursor_line,
+                    offset: cursor_top,
+                };
+            } else if shaper.distance(scroll, cursor_line, viewport_height, window) + cursor_bottom
+                > viewport_height
+            {
+                scroll = ScrollPosition {
+                    line: cursor_line,
+                    offset: cursor_bottom - viewport_height,
+                };
             }
         }
-        if line_starts.is_empty() {
-            line_starts.push(0);
-        }
-
-        let mut lines = Vec::with_capacity(line_starts.len());
-        for (i, &start) in line_starts.iter().enumerate() {
-            let raw_end = line_starts
-                .get(i + 1)
-                .copied()
-                .unwrap_or(display_text.len());
-            let end = if start == raw_end {
-                raw_end
-            } else if raw_end > 0 && display_text.as_bytes()[raw_end - 1] == b'\n' {
-                raw_end - 1
-            } else {
-                raw_end
-            };
-            let slice = &display_text[start..end];
-
-            let base_run = TextRun {
-                len: slice.len(),
-                font: style.font(),
-                color: text_color,
-                background_color: None,
-                underline: None,
-                strikethrough: None,
-            };
+        let scroll = shaper.normalize(scroll, window);
 
-            let runs = if !text.is_empty() {
-                if let Some(marked_range) = editor.marked_range.as_ref() {
-                    if marked_range.start >= start && marked_range.end <= end {
-                        vec![
-                            TextRun {
-                                len: marked_range.start - start,
-                                ..base_run.clone()
-                            },
-                            TextRun {
-                                len: marked_range.end - marked_range.start,
-                                underline: Some(UnderlineStyle {
-                                    color: Some(base_run.color),
-                                    thickness: px(1.0),
-                                    wavy: false,
-                                }),
-                                ..base_run.clone()
-                            },
-                            TextRun {
-                                len: end - marked_range.end,
-                                ..base_run
-                            },
-                        ]
-                        .into_iter()
-                        .filter(|r| r.len > 0)
-                        .collect()
-                    } else {
-                        runs_for_line(&base_run, start, end, &editor.highlights)
-                    }
-                } else {
-                    runs_for_line(&base_run, start, end, &editor.highlights)
+        // Shape from the first visible line down past the bottom, plus a margin on either side.
+        let first_line = scroll.line.saturating_sub(OVERSCAN_LINES);
+        let mut top = -scroll.offset;
+        for index in (first_line..scroll.line).rev() {
+            top -= shaper.line(index, window).height();
+        }
+        let mut lines = Vec::new();
+        let mut below = 0;
+        for index in first_line..line_count {
+            let mut line = shaper.take(index, window);
+            line.top = top;
+            top += line.height();
+            lines.push(line);
+            if top >= viewport_height {
+                below += 1;
+                if below > OVERSCAN_LINES {
+                    break;
                 }
-            } else {
-                vec![base_run]
-            };
-
-            lines.push(window.text_system().shape_line(
-                slice.to_string().into(),
-                font_size,
-                &runs,
-                None,
-            ));
+            }
         }
+        let layout = LastLayout {
+            bounds: text_bounds,
+            first_line,
+            lines,
+            line_count,
+            char_width,
+        };
 
         let mut selection_quads = vec![];
-        if !text.is_empty() && !selection.is_empty() {
-            for (i, &start) in line_starts.iter().enumerate() {
-                let raw_end = line_starts.get(i + 1).copied().unwrap_or(text.len());
-                let end = if start == raw_end {
-                    raw_end
-                } else if raw_end > 0 && text.as_bytes()[raw_end - 1] == b'\n' {
-                    raw_end - 1
-                } else {
-                    raw_end
-                };
-
-                let overlap_start = selection.start.max(start);
-                let overlap_end = selection.end.min(end);
-                if overlap_start >= overlap_end {
+        let mut cursors = vec![];
+        if !buffer.is_empty() {
+            for selection in &editor.selections.selections {
+                let range = selection.range();
+                if range.is_empty() {
                     continue;
                 }
+                let first = buffer.line_for_offset(range.start).max(layout.first_line);
+                let last = buffer.line_for_offset(range.end).min(layout.end_line() - 1);
+                for index in first..=last {
+                    let line = &layout.lines[index - layout.first_line];
+                    let Range { start, end } = buffer.line_range(index);
+                    let overlap_start = range.start.max(start);
+                    let overlap_end = range.end.min(end);
+                    if overlap_start >= overlap_end {
+                        continue;
+                    }
 
-                let line = &lines[i];
-                let x0 = line.x_for_index(overlap_start - start);
-                let x1 = line.x_for_index(overlap_end - start);
-                let top = bounds.top() + line_height * i as f32;
-                let bottom = top + line_height;
-                selection_quads.push(fill(
-                    Bounds::from_corners(
-                        point(bounds.left() + x0, top),
-                        point(bounds.left() + x1, bottom),
-                    ),
-                    rgba(0x3355ff40),
-                ));
+                    let origin = text_bounds.origin + point(px(0.), line.top);
+                    for row_bounds in
+                        line.bounds_for_columns(overlap_start - start..overlap_end - start)
+                    {
+                        selection_quads.push(fill(
+                            Bounds::new(row_bounds.origin + origin, row_bounds.size),
+                            rgba(0x3355ff40),
+                        ));
+                    }
+                }
             }
         }
 
-        let cursor_quad = if selection.is_empty() {
-            let cursor = cursor.min(display_text.len());
-            let (line_index, &line_start) = line_starts
-                .iter()
-                .enumerate()
-                .rev()
-                .find(|&(_, &start)| start <= cursor)
-                .unwrap_or((0, &0));
-            let line_index = line_index.min(lines.len().saturating_sub(1));
-            let line = &lines[line_index];
-            let col = (cursor - line_start).min(line.text.len());
-            let x = line.x_for_index(col);
-            let top = bounds.top() + line_height * line_index as f32;
-            Some(fill(
+        for selection in &editor.selections.selections {
+            if !selection.is_empty() {
+                continue;
+            }
+            let (line_index, col) = buffer.offset_to_point(selection.cursor);
+            let Some(line) = layout.line(line_index) else {
+                continue;
+            };
+            let position = line.position_for_column(col.min(line.len()));
+            cursors.push(fill(
                 Bounds::new(
-                    point(bounds.left() + x, top),
-                    gpui::size(px(2.), line_height),
+                    text_bounds.origin + point(position.x, line.top + position.y),
+                    size(px(2.), line.row_height),
                 ),
                 gpui::blue(),
-            ))
-        } else {
-            None
-        };
+            ));
+        }
 
         PrepaintState {
-            lines,
-            line_starts,
+            layout: Some(layout),
+            scroll,
+            autoscrolled_cursor: cursor,
             selection_quads,
-            cursor: cursor_quad,
-            line_height,
+            cursors,
         }
     }
 
@@ -638,54 +1931,235 @@ impl Element for EditorElement {
             cx,
         );
 
-        for quad in prepaint.selection_quads.drain(..) {
-            window.paint_quad(quad);
-        }
+        let Some(layout) = prepaint.layout.take() else {
+            return;
+        };
+        window.with_content_mask(Some(ContentMask { bounds }), |window| {
+            for quad in prepaint.selection_quads.drain(..) {
+                window.paint_quad(quad);
+            }
 
-        let lines = std::mem::take(&mut prepaint.lines);
-        for (i, line) in lines.iter().enumerate() {
-            let origin = point(
-                bounds.left(),
-                bounds.top() + prepaint.line_height * i as f32,
-            );
-            line.paint(origin, prepaint.line_height, window, cx).ok();
-        }
+            for line in &layout.lines {
+                let origin = layout.bounds.origin + point(px(0.), line.top);
+                line.shaped
+                    .paint(origin, line.row_height, TextAlign::Left, None, window, cx)
+                    .ok();
+            }
 
-        if focus_handle.is_focused(window) {
-            if let Some(cursor) = prepaint.cursor.take() {
-                window.paint_quad(cursor);
+            if focus_handle.is_focused(window) {
+                for cursor in prepaint.cursors.drain(..) {
+                    window.paint_quad(cursor);
+                }
             }
-        }
+        });
 
-        let line_starts = std::mem::take(&mut prepaint.line_starts);
-        let line_height = prepaint.line_height;
+        let scroll = prepaint.scroll;
+        let autoscrolled_cursor = prepaint.autoscrolled_cursor;
         self.editor.update(cx, |editor, _cx| {
-            editor.last_bounds = Some(bounds);
-            editor.last_line_height = Some(line_height);
-            editor.last_line_starts = Some(line_starts);
-            editor.last_lines = Some(lines);
+            editor.scroll_position = scroll;
+            editor.autoscrolled_cursor = Some(autoscrolled_cursor);
+            editor.last_layout = Some(layout);
         });
     }
 }
 
+impl EditorView {
+    /// The completion list, below the start of the link being completed.
+    fn render_completion(
+        &mut self,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Option<AnyElement> {
+        let start = self.completion.as_ref()?.range.start;
+        let range_utf16 = self.range_to_utf16(&(start..start), cx);
+        let element_bounds = self.last_layout.as_ref()?.bounds;
+        let anchor = self.bounds_for_range(range_utf16, element_bounds, window, cx)?;
+        let completion = self.completion.as_ref()?;
+
+        let first = completion
+            .selected
+            .saturating_sub(VISIBLE_COMPLETIONS - 1)
+            .min(completion.items.len().saturating_sub(VISIBLE_COMPLETIONS));
+        let rows = completion
+            .items
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE_COMPLETIONS)
+            .map(|(index, item)| {
+                div()
+                    .id(index)
+                    .flex()
+                    .flex_row()
+                    .gap_2()
+                    .px_2()
+                    .py_0p5()
+                    .cursor_pointer()
+                    .when(index == completion.selected, |row| {
+                        row.bg(rgb(theme::BG_ACTIVE_ROW))
+                    })
+                    .child(div().flex_grow().truncate().child(item.label.clone()))
+                    .children(item.detail.clone().map(|detail| {
+                        div()
+                            .flex_shrink_0()
+                            .text_color(rgb(theme::TEXT_MUTED))
+                            .child(detail)
+                    }))
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |view, _, window, cx| {
+                            // Keep the click from moving the cursor in the text below.
+                            cx.stop_propagation();
+                            view.accept_completion(index, window, cx);
+                        }),
+                    )
+            });
+        let menu = div()
+            .min_w(px(220.))
+            .max_w(px(420.))
+            .py_1()
+            .rounded_md()
+            .border_1()
+            .border_color(rgb(theme::BORDER))
+            .bg(rgb(theme::BG_SIDEBAR))
+            .shadow_md()
+            .text_sm()
+            .text_color(rgb(theme::TEXT_PRIMARY))
+            .cursor(CursorStyle::Arrow)
+            .children(rows);
+        Some(
+            deferred(
+                anchored()
+                    .position(anchor.bottom_left())
+                    .snap_to_window_with_margin(px(8.))
+                    .child(menu),
+            )
+            .into_any_element(),
+        )
+    }
+
+    fn render_conflict_banner(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        banner("This note changed on disk while you were editing it.")
+            .child(
+                banner_button("reload-from-disk", "Reload")
+                    .on_click(cx.listener(|view, _, _, cx| view.take_theirs(cx))),
+            )
+            .child(
+                banner_button("keep-mine", "Keep Mine")
+                    .on_click(cx.listener(|view, _, _, cx| view.keep_mine(cx))),
+            )
+            .child(
+                banner_button("merge-with-disk", "Merge")
+                    .on_click(cx.listener(|view, _, window, cx| view.merge_theirs(window, cx))),
+            )
+    }
+
+    fn render_merge_banner(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        banner(
+            "Some changes conflicted and are marked with <<<<<<< and >>>>>>> in the note. \
+             Autosave is paused until they are resolved.",
+        )
+        .child(
+            banner_button("undo-merge", "Undo")
+                .on_click(cx.listener(|view, _, window, cx| view.undo(&Undo, window, cx))),
+        )
+    }
+}
+
+/// A bar above the text about the note's file, with room for buttons after `message`.
+fn banner(message: &'static str) -> Div {
+    div()
+        .flex()
+        .flex_row()
+        .flex_shrink_0()
+        .items_center()
+        .gap_2()
+        .mb_2()
+        .px_3()
+        .py_2()
+        .rounded_md()
+        .bg(rgb(0x3a3020))
+        .text_sm()
+        .cursor(CursorStyle::Arrow)
+        // Keep clicks on the banner from moving the cursor in the text below.
+        .on_mouse_down(MouseButton::Left, |_, _, cx| cx.stop_propagation())
+        .child(div().flex_grow().child(message))
+}
+
+fn banner_button(id: &'static str, label: &'static str) -> Stateful<Div> {
+    div()
+        .id(id)
+        .px_2()
+        .py_0p5()
+        .rounded_sm()
+        .bg(rgb(0x4a4033))
+        .hover(|style| style.bg(rgb(0x5c5040)))
+        .cursor_pointer()
+        .child(label)
+}
+
 impl Render for EditorView {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        self.sync_completion(cx);
+        let completion_menu = self.render_completion(window, cx);
         div()
-            .key_context("ZenoEditor")
+            .key_context(if completion_menu.is_some() {
+                "ZenoEditor completing"
+            } else {
+                "ZenoEditor"
+            })
             .track_focus(&self.focus_handle(cx))
             .cursor(CursorStyle::IBeam)
             .on_action(cx.listener(Self::backspace))
             .on_action(cx.listener(Self::delete))
+            .on_action(cx.listener(Self::delete_to_previous_word_start))
+            .on_action(cx.listener(Self::delete_to_next_word_end))
             .on_action(cx.listener(Self::left))
             .on_action(cx.listener(Self::right))
+            .on_action(cx.listener(Self::up))
+            .on_action(cx.listener(Self::down))
+            .on_action(cx.listener(Self::home))
+            .on_action(cx.listener(Self::end))
+            .on_action(cx.listener(Self::move_to_beginning))
+            .on_action(cx.listener(Self::move_to_end))
+            .on_action(cx.listener(Self::move_to_previous_word_start))
+            .on_action(cx.listener(Self::move_to_next_word_end))
+            .on_action(cx.listener(Self::move_to_previous_paragraph))
+            .on_action(cx.listener(Self::move_to_next_paragraph))
             .on_action(cx.listener(Self::select_left))
             .on_action(cx.listener(Self::select_right))
+            .on_action(cx.listener(Self::select_up))
+            .on_action(cx.listener(Self::select_down))
+            .on_action(cx.listener(Self::select_home))
+            .on_action(cx.listener(Self::select_end))
+            .on_action(cx.listener(Self::select_to_beginning))
+            .on_action(cx.listener(Self::select_to_end))
+            .on_action(cx.listener(Self::select_to_previous_word_start))
+            .on_action(cx.listener(Self::select_to_next_word_end))
+            .on_action(cx.listener(Self::select_to_previous_paragraph))
+            .on_action(cx.listener(Self::select_to_next_paragraph))
             .on_action(cx.listener(Self::select_all))
             .on_action(cx.listener(Self::newline))
+            .on_action(cx.listener(Self::undo))
+            .on_action(cx.listener(Self::redo))
+            .on_action(cx.listener(Self::cut))
+            .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::paste))
+            .on_action(cx.listener(Self::add_cursor_above))
+            .on_action(cx.listener(Self::add_cursor_below))
+            .on_action(cx.listener(Self::select_next_occurrence))
+            .on_action(cx.listener(Self::cancel))
+            .on_action(cx.listener(Self::toggle_readable_width))
+            .on_action(cx.listener(Self::save))
+            .on_action(cx.listener(Self::save_as))
+            .on_action(cx.listener(Self::previous_completion))
+            .on_action(cx.listener(Self::next_completion))
+            .on_action(cx.listener(Self::confirm_completion))
             .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
             .on_mouse_move(cx.listener(Self::on_mouse_move))
             .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
+            .on_scroll_wheel(cx.listener(Self::on_scroll_wheel))
             .flex()
             .flex_col()
             .w_full()
@@ -693,8 +2167,17 @@ impl Render for EditorView {
             .p_4()
             .bg(rgb(0x1e1e1e))
             .text_color(rgb(0xffffff))
+            .when(self.document.read(cx).has_disk_conflict(), |this| {
+                this.child(self.render_conflict_banner(cx))
+            })
+            .when(
+                !self.document.read(cx).has_disk_conflict()
+                    && self.document.read(cx).has_unresolved_merge(),
+                |this| this.child(self.render_merge_banner(cx)),
+            )
             .child(EditorElement {
                 editor: cx.entity(),
             })
+            .children(completion_menu)
     }
 }
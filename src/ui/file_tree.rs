@@ -0,0 +1,848 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gpui::{
+    App, ClickEvent, Context, Div, ElementId, EventEmitter, FocusHandle, Focusable, IntoElement,
+    KeyDownEvent, Render, SharedString, Stateful, Subscription, Window, actions, div, prelude::*,
+    px, rgb,
+};
+
+use super::theme;
+use crate::vault::ops::{self, Trash, TrashEntry};
+use crate::vault::tree::FileTree;
+
+actions!(
+    zeno_file_tree,
+    [NewNote, NewFolder, RenameEntry, DeleteEntry]
+);
+
+/// Emitted when a file in the tree is clicked.
+pub struct OpenFile(pub PathBuf);
+
+/// Emitted when a file or folder was renamed or moved, so buffers showing it, or files inside
+/// it, can follow.
+pub struct PathMoved {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Emitted when a file or folder was moved to the vault's trash.
+pub struct PathTrashed(pub PathBuf);
+
+/// An entry whose name is being edited in place of its row.
+struct Rename {
+    path: PathBuf,
+    name: String,
+    /// Whether the name is still as the rename started, in which case typing replaces it.
+    untouched: bool,
+    /// A note just created, opened once it has been named.
+    is_new_note: bool,
+}
+
+/// A row being dragged onto a folder.
+#[derive(Clone)]
+struct DraggedEntry {
+    path: PathBuf,
+    name: SharedString,
+}
+
+impl Render for DraggedEntry {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .px_2()
+            .py_0p5()
+            .rounded_sm()
+            .bg(rgb(theme::BG_ACTIVE_ROW))
+            .text_color(rgb(theme::TEXT_PRIMARY))
+            .text_sm()
+            .child(self.name.clone())
+    }
+}
+
+/// The sidebar's view of the open vault.
+pub struct FileTreeView {
+    focus_handle: FocusHandle,
+    root: Option<PathBuf>,
+    tree: Option<FileTree>,
+    /// Why the last walk of the vault failed.
+    error: Option<SharedString>,
+    /// Why the last file operation failed, shown until the next one succeeds.
+    operation_error: Option<SharedString>,
+    /// The file in the active tab, highlighted in the tree.
+    active_path: Option<PathBuf>,
+    /// The row last clicked: what renaming and deleting apply to, and where new entries go.
+    selected: Option<PathBuf>,
+    rename: Option<Rename>,
+    /// The vault's trash, most recently trashed first.
+    trash: Vec<TrashEntry>,
+    show_trash: bool,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl EventEmitter<OpenFile> for FileTreeView {}
+impl EventEmitter<PathMoved> for FileTreeView {}
+impl EventEmitter<PathTrashed> for FileTreeView {}
+
+const INDENT: f32 = 14.;
+
+impl FileTreeView {
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
+        let focus_handle = cx.focus_handle();
+        // Clicking away from a rename keeps the name typed so far.
+        let subscriptions = vec![cx.on_blur(&focus_handle, window, Self::commit_rename)];
+        Self {
+            focus_handle,
+            root: None,
+            tree: None,
+            error: None,
+            operation_error: None,
+            active_path: None,
+            selected: None,
+            rename: None,
+            trash: Vec::new(),
+            show_trash: false,
+            _subscriptions: subscriptions,
+        }
+    }
+
+    pub fn set_active_path(&mut self, path: Option<PathBuf>, cx: &mut Context<Self>) {
+        if self.active_path != path {
+            self.active_path = path;
+            cx.notify();
+        }
+    }
+
+    /// The open vault's folder, once it has been walked.
+    pub fn vault_root(&self) -> Option<&Path> {
+        self.tree.as_ref().map(|tree| tree.root.as_path())
+    }
+
+    /// Shows the vault at `root`, walking it on the background executor.
+    pub fn open(&mut self, root: PathBuf, cx: &mut Context<Self>) {
+        if self.root.as_ref() != Some(&root) {
+            self.tree = None;
+            self.trash.clear();
+            self.selected = None;
+            self.rename = None;
+            self.operation_error = None;
+        }
+        self.root = Some(root);
+        self.refresh(cx);
+    }
+
+    /// Walks the vault again, keeping expanded directories expanded, and lists its trash.
+    pub fn refresh(&mut self, cx: &mut Context<Self>) {
+        let Some(root) = self.root.clone() else {
+            return;
+        };
+        cx.spawn(async move |this, cx| {
+            let walk_root = root.clone();
+            let walked = cx
+                .background_executor()
+                .spawn(async move {
+                    let tree = FileTree::walk(&walk_root)?;
+                    let trash = Trash::new(&tree.root).entries().unwrap_or_default();
+                    io::Result::Ok((tree, trash))
+                })
+                .await;
+            this.update(cx, |view, cx| {
+                // Another vault was opened while this one was being walked.
+                if view.root.as_ref() != Some(&root) {
+                    return;
+                }
+                match walked {
+                    Ok((mut tree, trash)) => {
+                        if let Some(previous) = &view.tree {
+                            tree.keep_expanded(previous);
+                        }
+                        view.tree = Some(tree);
+                        view.trash = trash;
+                        view.error = None;
+                    }
+                    Err(error) => {
+                        view.error =
+                            Some(format!("Could not read {}: {error}", root.display()).into());
+                    }
+                }
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn click(&mut self, path: PathBuf, is_dir: bool, window: &mut Window, cx: &mut Context<Self>) {
+        if self
+            .rename
+            .as_ref()
+            .is_some_and(|rename| rename.path == path)
+        {
+            return;
+        }
+        self.commit_rename(window, cx);
+        window.focus(&self.focus_handle);
+        self.selected = Some(path.clone());
+        if is_dir {
+            if let Some(tree) = &mut self.tree {
+                tree.toggle_expanded(&path);
+            }
+        } else {
+            cx.emit(OpenFile(path));
+        }
+        cx.notify();
+    }
+
+    /// The folder new entries go in: the selected folder, the folder of the selected file, or
+    /// the top of the vault.
+    fn target_dir(&self) -> Option<PathBuf> {
+        let root = self.tree.as_ref()?.root.clone();
+        let dir = match &self.selected {
+            Some(path) if path.is_dir() => path.clone(),
+            Some(path) => path.parent().map(Path::to_path_buf).unwrap_or(root),
+            None => root,
+        };
+        Some(dir)
+    }
+
+    /// Runs the file operation `operation` on the background executor, then `done` with its
+    /// result and a fresh walk of the vault. A failure is shown in the sidebar as
+    /// "Could not `description`".
+    fn run<T: Send + 'static>(
+        &mut self,
+        description: String,
+        operation: impl FnOnce() -> io::Result<T> + Send + 'static,
+        done: impl FnOnce(&mut Self, T, &mut Window, &mut Context<Self>) + 'static,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        cx.spawn_in(window, async move |this, cx| {
+            let result = cx
+                .background_executor()
+                .spawn(async move { operation() })
+                .await;
+            this.update_in(cx, |view, window, cx| {
+                match result {
+                    Ok(value) => {
+                        view.operation_error = None;
+                        done(view, value, window, cx);
+                    }
+                    Err(error) => {
+                        view.operation_error =
+                            Some(format!("Could not {description}: {error}").into());
+                    }
+                }
+                view.refresh(cx);
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn new_note(&mut self, _: &NewNote, window: &mut Window, cx: &mut Context<Self>) {
+        self.create(false, window, cx);
+    }
+
+    fn new_folder(&mut self, _: &NewFolder, window: &mut Window, cx: &mut Context<Self>) {
+        self.create(true, window, cx);
+    }
+
+    /// Creates an untitled note or folder in the target folder and starts renaming it.
+    fn create(&mut self, is_dir: bool, window: &mut Window, cx: &mut Context<Self>) {
+        self.commit_rename(window, cx);
+        let Some(dir) = self.target_dir() else {
+            return;
+        };
+        if let Some(node) = self.tree.as_mut().and_then(|tree| tree.node_mut(&dir)) {
+            node.is_expanded = true;
+        }
+        let description = if is_dir {
+            "create a folder"
+        } else {
+            "create a note"
+        };
+        self.run(
+            description.to_string(),
+            move || {
+                if is_dir {
+                    ops::create_folder(&dir, &ops::unused_name(&dir, "Untitled", None))
+                } else {
+                    ops::create_note(&dir, &ops::unused_name(&dir, "Untitled", Some("md")))
+                }
+            },
+            move |view, path, window, cx| {
+                view.selected = Some(path.clone());
+                view.start_rename(path, !is_dir, window, cx);
+            },
+            window,
+            cx,
+        );
+    }
+
+    fn rename_entry(&mut self, _: &RenameEntry, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(path) = self.selected.clone() {
+            self.start_rename(path, false, window, cx);
+        }
+    }
+
+    /// Replaces the row of `path` with an editable name, the stem for files so the extension is
+    /// kept.
+    fn start_rename(
+        &mut self,
+        path: PathBuf,
+        is_new_note: bool,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let name = if path.is_dir() {
+            path.file_name()
+        } else {
+            path.file_stem()
+        };
+        let name = name
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.rename = Some(Rename {
+            path,
+            name,
+            untouched: true,
+            is_new_note,
+        });
+        window.focus(&self.focus_handle);
+        cx.notify();
+    }
+
+    fn commit_rename(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(rename) = self.rename.take() else {
+            return;
+        };
+        cx.notify();
+        if rename.untouched {
+            self.finish_rename(rename.path, rename.is_new_note, cx);
+            return;
+        }
+        let Rename {
+            path: from,
+            name,
+            is_new_note,
+            ..
+        } = rename;
+        let description = format!(
+            "rename {}",
+            from.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let path = from.clone();
+        self.run(
+            description,
+            move || ops::rename(&path, &name),
+            move |view, to, _, cx| {
+                if to != from {
+                    cx.emit(PathMoved {
+                        from: from.clone(),
+                        to: to.clone(),
+                    });
+                }
+                view.selected = Some(to.clone());
+                view.finish_rename(to, is_new_note, cx);
+            },
+            window,
+            cx,
+        );
+    }
+
+    fn cancel_rename(&mut self, cx: &mut Context<Self>) {
+        if let Some(rename) = self.rename.take() {
+            self.finish_rename(rename.path, rename.is_new_note, cx);
+            cx.notify();
+        }
+    }
+
+    fn finish_rename(&mut self, path: PathBuf, is_new_note: bool, cx: &mut Context<Self>) {
+        if is_new_note {
+            cx.emit(OpenFile(path));
+        }
+    }
+
+    /// Edits the name being renamed. Enter keeps it and escape goes back to the old one.
+    fn rename_key_down(
+        &mut self,
+        event: &KeyDownEvent,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(rename) = &mut self.rename else {
+            return;
+        };
+        let keystroke = &event.keystroke;
+        match keystroke.key.as_str() {
+            "enter" => self.commit_rename(window, cx),
+            "escape" => self.cancel_rename(cx),
+            "backspace" => {
+                if rename.untouched {
+                    rename.name.clear();
+                } else {
+                    rename.name.pop();
+                }
+                rename.untouched = false;
+            }
+            _ => {
+                // Leave shortcuts to their bindings.
+                if keystroke.modifiers.platform || keystroke.modifiers.control {
+                    return;
+                }
+                let Some(text) = keystroke.key_char.as_deref() else {
+                    return;
+                };
+                if rename.untouched {
+                    rename.name.clear();
+                }
+                rename.name.push_str(text);
+                rename.untouched = false;
+            }
+        }
+        cx.stop_propagation();
+        cx.notify();
+    }
+
+    fn delete_entry(&mut self, _: &DeleteEntry, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(path) = self.selected.clone() {
+            self.trash(path, window, cx);
+        }
+    }
+
+    /// Moves the entry at `path` to the vault's trash, from where it can be restored.
+    fn trash(&mut self, path: PathBuf, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(root) = self.tree.as_ref().map(|tree| tree.root.clone()) else {
+            return;
+        };
+        if self
+            .rename
+            .as_ref()
+            .is_some_and(|rename| rename.path == path)
+        {
+            self.rename = None;
+        }
+        let description = format!(
+            "delete {}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let trashed = path.clone();
+        self.run(
+            description,
+            move || Trash::new(&root).trash(&trashed),
+            move |view, _, _, cx| {
+                if view
+                    .selected
+                    .as_ref()
+                    .is_some_and(|selected| selected.starts_with(&path))
+                {
+                    view.selected = None;
+                }
+                cx.emit(PathTrashed(path));
+            },
+            window,
+            cx,
+        );
+    }
+
+    fn restore(&mut self, entry: TrashEntry, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(root) = self.tree.as_ref().map(|tree| tree.root.clone()) else {
+            return;
+        };
+        let description = format!("restore {}", entry.name());
+        self.run(
+            description,
+            move || Trash::new(&root).restore(&entry),
+            |view, path, _, _| view.selected = Some(path),
+            window,
+            cx,
+        );
+    }
+
+    /// Moves the entry at `path` into the folder `dir`.
+    fn move_entry(
+        &mut self,
+        path: PathBuf,
+        dir: PathBuf,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if path.parent() == Some(dir.as_path()) {
+            return;
+        }
+        let description = format!(
+            "move {}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let from = path.clone();
+        self.run(
+            description,
+            move || ops::move_into(&path, &dir),
+            move |view, to, _, cx| {
+                if view.selected.as_ref() == Some(&from) {
+                    view.selected = Some(to.clone());
+                }
+                cx.emit(PathMoved { from, to });
+            },
+            window,
+            cx,
+        );
+    }
+
+    fn render_rename(&self, rename: &Rename) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_row()
+            .flex_grow()
+            .min_w_0()
+            .px_1()
+            .rounded_sm()
+            .border_1()
+            .border_color(rgb(theme::TEXT_MUTED))
+            .bg(rgb(theme::BG_APP))
+            .child(
+                div()
+                    .truncate()
+                    .when(rename.untouched, |name| name.bg(rgb(theme::BG_ACTIVE_ROW)))
+                    .child(rename.name.clone()),
+            )
+            .child(div().w(px(1.)).h(px(14.)).bg(rgb(theme::TEXT_PRIMARY)))
+    }
+
+    fn render_trash(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let entries = self.trash.iter().enumerate().map(|(index, entry)| {
+            let restored = entry.clone();
+            div()
+                .flex()
+                .flex_row()
+                .items_center()
+                .gap_1()
+                .pl(px(8. + INDENT))
+                .pr_2()
+                .py_0p5()
+                .text_sm()
+                .child(
+                    div()
+                        .flex_grow()
+                        .min_w_0()
+                        .truncate()
+                        .text_color(rgb(theme::TEXT_MUTED))
+                        .child(entry.name()),
+                )
+                .child(
+                    div()
+                        .id(("restore-entry", index))
+                        .px_1()
+                        .rounded_sm()
+                        .text_xs()
+                        .cursor_pointer()
+                        .hover(|style| style.bg(rgb(theme::BORDER)))
+                        .child("Restore")
+                        .on_click(cx.listener(move |view, _: &ClickEvent, window, cx| {
+                            view.restore(restored.clone(), window, cx)
+                        })),
+                )
+        });
+        let icon = if self.show_trash { "▼" } else { "▶" };
+        div()
+            .flex()
+            .flex_col()
+            .flex_shrink_0()
+            .border_t_1()
+            .border_color(rgb(theme::BORDER))
+            .pt_1()
+            .child(
+                div()
+                    .id("trash-header")
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .gap_1()
+                    .px_2()
+                    .py_0p5()
+                    .rounded_sm()
+                    .text_sm()
+                    .text_color(rgb(theme::TEXT_MUTED))
+                    .cursor_pointer()
+                    .hover(|style| style.bg(rgb(theme::BORDER)))
+                    .on_click(cx.listener(|view, _, _, cx| {
+                        view.show_trash = !view.show_trash;
+                        cx.notify();
+                    }))
+                    .child(div().w(px(12.)).text_xs().child(icon))
+                    .child(format!("Trash ({})", self.trash.len())),
+            )
+            .when(self.show_trash, |this| this.children(entries))
+    }
+}
+
+impl Focusable for FileTreeView {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+fn header_button(id: &'static str, label: &'static str) -> Stateful<Div> {
+    div()
+        .id(id)
+        .px_1()
+        .rounded_sm()
+        .text_xs()
+        .text_color(rgb(theme::TEXT_MUTED))
+        .cursor_pointer()
+        .hover(|style| style.bg(rgb(theme::BORDER)))
+        .child(label)
+}
+
+impl Render for FileTreeView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let title = self
+            .root
+            .as_ref()
+            .and_then(|root| root.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "No folder open".to_string());
+
+        let mut rows = Vec::new();
+        if let Some(tree) = &self.tree {
+            for (index, row) in tree.visible_rows().into_iter().enumerate() {
+                let node = row.node;
+                let icon = match (node.is_dir, node.is_expanded) {
+                    (true, true) => "▼",
+                    (true, false) => "▶",
+                    (false, _) => "•",
+                };
+                let path = node.path.clone();
+                let is_dir = node.is_dir;
+                let is_active = self.active_path.as_ref() == Some(&node.path);
+                let is_selected = self.selected.as_ref() == Some(&node.path);
+                let rename = self
+                    .rename
+                    .as_ref()
+                    .filter(|rename| rename.path == node.path);
+                // Dropping onto a file moves into the folder it is in.
+                let drop_dir = if is_dir {
+                    Some(node.path.clone())
+                } else {
+                    node.path.parent().map(Path::to_path_buf)
+                };
+                let group = SharedString::from(format!("file-row-{index}"));
+                let name: SharedString = node.name().to_string().into();
+
+                let mut item = div()
+                    .id(ElementId::Name(
+                        node.path.to_string_lossy().into_owned().into(),
+                    ))
+                    .group(group.clone())
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .gap_1()
+                    .pl(px(8. + row.depth as f32 * INDENT))
+                    .pr_2()
+                    .py_0p5()
+                    .rounded_sm()
+                    .text_sm()
+                    .when(is_selected, |row| row.bg(rgb(theme::BORDER)))
+                    .when(is_active, |row| row.bg(rgb(theme::BG_ACTIVE_ROW)))
+                    .hover(|style| style.bg(rgb(theme::BORDER)))
+                    .cursor_pointer()
+                    .on_click(cx.listener({
+                        let path = path.clone();
+                        move |view, _, window, cx| view.click(path.clone(), is_dir, window, cx)
+                    }))
+                    .on_drag(
+                        DraggedEntry {
+                            path: path.clone(),
+                            name: name.clone(),
+                        },
+                        |entry, _, _, cx| cx.new(|_| entry.clone()),
+                    )
+                    .child(
+                        div()
+                            .w(px(12.))
+                            .text_xs()
+                            .text_color(rgb(theme::TEXT_MUTED))
+                            .child(icon),
+                    );
+                if let Some(dir) = drop_dir {
+                    item = item
+                        .drag_over::<DraggedEntry>(|style, _, _, _| {
+                            style.bg(rgb(theme::BG_ACTIVE_ROW))
+                        })
+                        .on_drop(cx.listener(move |view, entry: &DraggedEntry, window, cx| {
+                            view.move_entry(entry.path.clone(), dir.clone(), window, cx)
+                        }));
+                }
+                let item = match rename {
+                    Some(rename) => item.child(self.render_rename(rename)),
+                    None => item
+                        .child(div().flex_grow().min_w_0().truncate().child(name))
+                        .child(
+                            div()
+                                .id(("rename-entry", index))
+                                .px_1()
+                                .rounded_sm()
+                                .text_xs()
+                                .text_color(rgb(theme::TEXT_MUTED))
+                                .opacity(0.)
+                                .group_hover(group.clone(), |style| style.opacity(1.))
+                                .hover(|style| style.bg(rgb(theme::BORDER)))
+                                .child("✎")
+                                .on_click(cx.listener({
+                                    let path = path.clone();
+                                    move |view, _: &ClickEvent, window, cx| {
+                                        cx.stop_propagation();
+                                        view.selected = Some(path.clone());
+                                        view.start_rename(path.clone(), false, window, cx);
+                                    }
+                                })),
+                        )
+                        .child(
+                            div()
+                                .id(("delete-entry", index))
+                                .px_1()
+                                .rounded_sm()
+                                .text_xs()
+                                .text_color(rgb(theme::TEXT_MUTED))
+                                .opacity(0.)
+                                .group_hover(group, |style| style.opacity(1.))
+                                .hover(|style| style.bg(rgb(theme::BORDER)))
+                                .child("×")
+                                .on_click(cx.listener(move |view, _: &ClickEvent, window, cx| {
+                                    cx.stop_propagation();
+                                    view.trash(path.clone(), window, cx);
+                                })),
+                        ),
+                };
+                rows.push(item);
+            }
+        }
+
+        let key_context = if self.rename.is_some() {
+            "ZenoFileTree renaming"
+        } else {
+            "ZenoFileTree"
+        };
+        let vault_root = self.tree.as_ref().map(|tree| tree.root.clone());
+
+        div()
+            .key_context(key_context)
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::new_note))
+            .on_action(cx.listener(Self::new_folder))
+            .on_action(cx.listener(Self::rename_entry))
+            .on_action(cx.listener(Self::delete_entry))
+            .on_key_down(cx.listener(Self::rename_key_down))
+            .flex()
+            .flex_col()
+            .size_full()
+            .gap_2()
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .gap_1()
+                    .px_2()
+                    .child(
+                        div()
+                            .flex_grow()
+                            .min_w_0()
+                            .text_sm()
+                            .font_weight(gpui::FontWeight::BOLD)
+                            .truncate()
+                            .child(title),
+                    )
+                    .when(self.tree.is_some(), |this| {
+                        this.child(header_button("new-note", "+ Note").on_click(cx.listener(
+                            |view, _: &ClickEvent, window, cx| view.create(false, window, cx),
+                        )))
+                        .child(
+                            header_button("new-folder", "+ Folder").on_click(cx.listener(
+                                |view, _: &ClickEvent, window, cx| view.create(true, window, cx),
+                            )),
+                        )
+                    }),
+            )
+            .when_some(self.error.clone(), |this, error| {
+                this.child(
+                    div()
+                        .px_2()
+                        .text_xs()
+                        .text_color(rgb(theme::TEXT_ERROR))
+                        .child(error),
+                )
+            })
+            .when_some(self.operation_error.clone(), |this, error| {
+                this.child(
+                    div()
+                        .id("operation-error")
+                        .px_2()
+                        .text_xs()
+                        .text_color(rgb(theme::TEXT_ERROR))
+                        .cursor_pointer()
+                        .child(error)
+                        .on_click(cx.listener(|view, _, _, cx| {
+                            view.operation_error = None;
+                            cx.notify();
+                        })),
+                )
+            })
+            .when(self.root.is_none(), |this| {
+                this.child(
+                    div()
+                        .px_2()
+                        .text_xs()
+                        .text_color(rgb(theme::TEXT_MUTED))
+                        .child("Open a folder with ⌘⇧O."),
+                )
+            })
+            .when(
+                self.root.is_some() && self.tree.is_none() && self.error.is_none(),
+                |this| {
+                    this.child(
+                        div()
+                            .px_2()
+                            .text_xs()
+                            .text_color(rgb(theme::TEXT_MUTED))
+                            .child("Loading…"),
+                    )
+                },
+            )
+            .child(
+                div()
+                    .id("file-tree-rows")
+                    .flex()
+                    .flex_col()
+                    .flex_grow()
+                    .overflow_y_scroll()
+                    .children(rows)
+                    // Dropping below the rows moves to the top of the vault.
+                    .when_some(vault_root, |this, root| {
+                        this.child(
+                            div()
+                                .flex_grow()
+                                .min_h(px(24.))
+                                .rounded_sm()
+                                .drag_over::<DraggedEntry>(|style, _, _, _| {
+                                    style.bg(rgb(theme::BG_ACTIVE_ROW))
+                                })
+                                .on_drop(cx.listener(
+                                    move |view, entry: &DraggedEntry, window, cx| {
+                                        view.move_entry(
+                                            entry.path.clone(),
+                                            root.clone(),
+                                            window,
+                                            cx,
+                                        )
+                                    },
+                                )),
+                        )
+                    }),
+            )
+            .when(!self.trash.is_empty(), |this| {
+                this.child(self.render_trash(cx))
+            })
+    }
+}
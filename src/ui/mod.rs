@@ -1,5 +1,10 @@
+pub mod backlinks;
+pub mod document;
 pub mod editor;
+pub mod file_tree;
+pub mod pane_group;
 pub mod theme;
+pub mod vault_index;
 pub mod workspace;
 
 pub use editor::EditorView;
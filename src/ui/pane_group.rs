@@ -0,0 +1,282 @@
+use gpui::Axis;
+
+pub type PaneId = usize;
+
+/// Where to move focus from a pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    fn axis(self) -> Axis {
+        match self {
+            Direction::Left | Direction::Right => Axis::Horizontal,
+            Direction::Up | Direction::Down => Axis::Vertical,
+        }
+    }
+
+    fn is_forward(self) -> bool {
+        matches!(self, Direction::Right | Direction::Down)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaneNode {
+    Pane(PaneId),
+    /// Children laid out side by side along `axis`. Never fewer than two.
+    Split {
+        axis: Axis,
+        children: Vec<PaneNode>,
+        /// Share of the split's length each child takes, summing to 1.
+        sizes: Vec<f32>,
+    },
+}
+
+/// How the editor area is divided into panes: a tree of splits with a pane at each leaf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaneGroup {
+    root: PaneNode,
+}
+
+impl PaneGroup {
+    pub fn new(pane: PaneId) -> Self {
+        Self {
+            root: PaneNode::Pane(pane),
+        }
+    }
+
+    pub fn root(&self) -> &PaneNode {
+        &self.root
+    }
+
+    /// Every pane, left to right and top to bottom.
+    #[cfg(test)]
+    pub fn panes(&self) -> Vec<PaneId> {
+        fn collect(node: &PaneNode, panes: &mut Vec<PaneId>) {
+            match node {
+                PaneNode::Pane(pane) => panes.push(*pane),
+                PaneNode::Split { children, .. } => {
+                    for child in children {
+                        collect(child, panes);
+                    }
+                }
+            }
+        }
+        let mut panes = Vec::new();
+        collect(&self.root, &mut panes);
+        panes
+    }
+
+    /// Puts `new_pane` after `pane` along `axis`, halving the space `pane` had.
+    pub fn split(&mut self, pane: PaneId, new_pane: PaneId, axis: Axis) {
+        let Some(path) = self.path_to(pane) else {
+            return;
+        };
+        if let Some((&index, parent_path)) = path.split_last()
+            && let PaneNode::Split {
+                axis: parent_axis,
+                children,
+                sizes,
+            } = self.node_mut(parent_path)
+            && *parent_axis == axis
+        {
+            let half = sizes[index] / 2.;
+            sizes[index] = half;
+            sizes.insert(index + 1, half);
+            children.insert(index + 1, PaneNode::Pane(new_pane));
+            return;
+        }
+        let node = self.node_mut(&path);
+        *node = PaneNode::Split {
+            axis,
+            children: vec![PaneNode::Pane(pane), PaneNode::Pane(new_pane)],
+            sizes: vec![0.5, 0.5],
+        };
+    }
+
+    /// Takes `pane` out of the layout, giving its space to a neighbour. The last pane stays.
+    pub fn remove(&mut self, pane: PaneId) {
+        let Some(path) = self.path_to(pane) else {
+            return;
+        };
+        let Some((&index, parent_path)) = path.split_last() else {
+            return;
+        };
+        let parent = self.node_mut(parent_path);
+        let PaneNode::Split {
+            children, sizes, ..
+        } = parent
+        else {
+            return;
+        };
+        children.remove(index);
+        let size = sizes.remove(index);
+        sizes[index.saturating_sub(1)] += size;
+        if children.len() == 1 {
+            *parent = children.remove(0);
+        }
+    }
+
+    /// The pane next to `pane` in `direction`, if any.
+    pub fn neighbour(&self, pane: PaneId, direction: Direction) -> Option<PaneId> {
+        let mut path = self.path_to(pane)?;
+        while let Some(index) = path.pop() {
+            let PaneNode::Split { axis, children, .. } = self.node(&path) else {
+                continue;
+            };
+            if *axis != direction.axis() {
+                continue;
+            }
+            let next = if direction.is_forward() {
+                Some(index + 1)
+            } else {
+                index.checked_sub(1)
+            };
+            let Some(mut node) = next.and_then(|next| children.get(next)) else {
+                continue;
+            };
+            // Enter the sibling on the side facing the pane moved from.
+            while let PaneNode::Split { axis, children, .. } = node {
+                node = if *axis == direction.axis() && !direction.is_forward() {
+                    children.last()?
+                } else {
+                    children.first()?
+                };
+            }
+            if let PaneNode::Pane(neighbour) = node {
+                return Some(*neighbour);
+            }
+        }
+        None
+    }
+
+    /// Moves the divider after child `divider` of the split at `path` to `fraction` of the
+    /// split's length, keeping every child at least `min_size` long.
+    pub fn resize(&mut self, path: &[usize], divider: usize, fraction: f32, min_size: f32) {
+        let PaneNode::Split { sizes, .. } = self.node_mut(path) else {
+            return;
+        };
+        if divider + 1 >= sizes.len() {
+            return;
+        }
+        let before: f32 = sizes[..divider].iter().sum();
+        let pair = sizes[divider] + sizes[divider + 1];
+        let first =
+            (fraction - before).clamp(min_size.min(pair / 2.), (pair - min_size).max(pair / 2.));
+        sizes[divider] = first;
+        sizes[divider + 1] = pair - first;
+    }
+
+    /// Child indices leading from the root to `pane`.
+    fn path_to(&self, pane: PaneId) -> Option<Vec<usize>> {
+        fn find(node: &PaneNode, pane: PaneId, path: &mut Vec<usize>) -> bool {
+            match node {
+                PaneNode::Pane(id) => *id == pane,
+                PaneNode::Split { children, .. } => {
+                    for (index, child) in children.iter().enumerate() {
+                        path.push(index);
+                        if find(child, pane, path) {
+                            return true;
+                        }
+                        path.pop();
+                    }
+                    false
+                }
+            }
+        }
+        let mut path = Vec::new();
+        find(&self.root, pane, &mut path).then_some(path)
+    }
+
+    fn node(&self, path: &[usize]) -> &PaneNode {
+        let mut node = &self.root;
+        for &index in path {
+            if let PaneNode::Split { children, .. } = node {
+                node = &children[index];
+            }
+        }
+        node
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> &mut PaneNode {
+        let mut node = &mut self.root;
+        for &index in path {
+            if let PaneNode::Split { children, .. } = node {
+                node = &mut children[index];
+            }
+        }
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gpui::Axis;
+
+    use super::{Direction, PaneGroup, PaneNode};
+
+    #[test]
+    fn splits_along_the_same_axis_share_a_parent() {
+        let mut group = PaneGroup::new(0);
+        group.split(0, 1, Axis::Horizontal);
+        group.split(0, 2, Axis::Horizontal);
+        assert_eq!(group.panes(), vec![0, 2, 1]);
+        let PaneNode::Split {
+            children, sizes, ..
+        } = group.root()
+        else {
+            panic!("expected a split");
+        };
+        assert_eq!(children.len(), 3);
+        assert_eq!(sizes, &vec![0.25, 0.25, 0.5]);
+
+        group.split(1, 3, Axis::Vertical);
+        assert_eq!(group.panes(), vec![0, 2, 1, 3]);
+    }
+
+    #[test]
+    fn removing_a_pane_collapses_single_child_splits() {
+        let mut group = PaneGroup::new(0);
+        group.split(0, 1, Axis::Horizontal);
+        group.split(1, 2, Axis::Vertical);
+        group.remove(2);
+        group.remove(0);
+        assert_eq!(group, PaneGroup::new(1));
+        group.remove(1);
+        assert_eq!(group.panes(), vec![1]);
+    }
+
+    #[test]
+    fn neighbours_follow_the_layout() {
+        // 0 | 1
+        //   | -
+        //   | 2
+        let mut group = PaneGroup::new(0);
+        group.split(0, 1, Axis::Horizontal);
+        group.split(1, 2, Axis::Vertical);
+        assert_eq!(group.neighbour(0, Direction::Right), Some(1));
+        assert_eq!(group.neighbour(2, Direction::Left), Some(0));
+        assert_eq!(group.neighbour(1, Direction::Down), Some(2));
+        assert_eq!(group.neighbour(2, Direction::Up), Some(1));
+        assert_eq!(group.neighbour(0, Direction::Left), None);
+        assert_eq!(group.neighbour(2, Direction::Down), None);
+    }
+
+    #[test]
+    fn resizing_moves_one_divider_within_limits() {
+        let mut group = PaneGroup::new(0);
+        group.split(0, 1, Axis::Horizontal);
+        group.split(1, 2, Axis::Horizontal);
+        group.resize(&[], 1, 0.9, 0.1);
+        let PaneNode::Split { sizes, .. } = group.root() else {
+            panic!("expected a split");
+        };
+        assert_eq!(sizes[0], 0.5);
+        assert!((sizes[1] - 0.4).abs() < 1e-6);
+        assert!((sizes[2] - 0.1).abs() < 1e-6);
+    }
+}
@@ -1,6 +1,10 @@
 pub const BG_APP: u32 = 0x1e1e1e;
 pub const BG_SIDEBAR: u32 = 0x151515;
 pub const BG_TERMINAL: u32 = 0x0f0f0f;
+pub const BG_ACTIVE_ROW: u32 = 0x2f3640;
+
+pub const BORDER: u32 = 0x2a2a2a;
 
 pub const TEXT_PRIMARY: u32 = 0xffffff;
 pub const TEXT_MUTED: u32 = 0xb0b0b0;
+pub const TEXT_ERROR: u32 = 0xf48771;
@@ -0,0 +1,140 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::StreamExt;
+use futures::channel::mpsc;
+use gpui::{Context, Task};
+
+use crate::editor::TreeWatcher;
+use crate::vault::index::{NoteEntry, NoteIndex};
+use crate::vault::links::is_note;
+
+/// How long changes in the vault settle before the index catches up with them. Saves, renames
+/// and checkouts come as bursts of events.
+const CHANGE_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// The open vault's [`NoteIndex`], read in the background when the vault opens and kept up to
+/// date as its files change, in Zeno or in other programs.
+pub struct VaultIndex {
+    root: Option<PathBuf>,
+    /// Empty until the vault has been read.
+    index: NoteIndex,
+    watch: Option<(TreeWatcher, Task<()>)>,
+}
+
+impl Default for VaultIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VaultIndex {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            index: NoteIndex::default(),
+            watch: None,
+        }
+    }
+
+    pub fn index(&self) -> &NoteIndex {
+        &self.index
+    }
+
+    /// Indexes the vault at `root` on the background executor, then watches it.
+    pub fn open(&mut self, root: PathBuf, cx: &mut Context<Self>) {
+        if self.root.as_ref() == Some(&root) {
+            return;
+        }
+        self.root = Some(root.clone());
+        self.index = NoteIndex::default();
+        self.watch = None;
+        cx.spawn(async move |this, cx| {
+            let build_root = root.clone();
+            let built = cx
+                .background_executor()
+                .spawn(async move { NoteIndex::build(&build_root) })
+                .await;
+            let Ok(index) = built else {
+                return;
+            };
+            this.update(cx, |vault_index, cx| {
+                // Another vault was opened while this one was being read.
+                if vault_index.root.as_ref() != Some(&root) {
+                    return;
+                }
+                vault_index.watch(index.root().to_path_buf(), cx);
+                vault_index.index = index;
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Keeps the index up to date with changes under `root`. Notes that changed are read again;
+    /// anything else, such as a folder being renamed, has the whole vault read again.
+    fn watch(&mut self, root: PathBuf, cx: &mut Context<Self>) {
+        let (changes_tx, mut changes) = mpsc::unbounded();
+        let Ok(watcher) = TreeWatcher::new(&root, changes_tx) else {
+            return;
+        };
+        let task = cx.spawn(async move |this, cx| {
+            while let Some(paths) = changes.next().await {
+                cx.background_executor().timer(CHANGE_DEBOUNCE).await;
+                let mut changed = paths.into_iter().collect::<BTreeSet<_>>();
+                while let Ok(Some(paths)) = changes.try_next() {
+                    changed.extend(paths);
+                }
+                let Ok(changed) = this.update(cx, |vault_index, _| {
+                    changed
+                        .into_iter()
+                        .filter(|path| vault_index.index.covers(path))
+                        .collect::<Vec<_>>()
+                }) else {
+                    break;
+                };
+                if changed.is_empty() {
+                    continue;
+                }
+
+                if changed.iter().all(|path| is_note(path)) {
+                    let entries = cx
+                        .background_executor()
+                        .spawn(async move {
+                            changed
+                                .into_iter()
+                                .map(|path| {
+                                    let entry = NoteEntry::read(&path).ok();
+                                    (path, entry)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .await;
+                    this.update(cx, |vault_index, cx| {
+                        for (path, entry) in entries {
+                            vault_index.index.set(path, entry);
+                        }
+                        cx.notify();
+                    })
+                    .ok();
+                } else {
+                    let build_root = root.clone();
+                    let built = cx
+                        .background_executor()
+                        .spawn(async move { NoteIndex::build(&build_root) })
+                        .await;
+                    if let Ok(index) = built {
+                        this.update(cx, |vault_index, cx| {
+                            vault_index.index = index;
+                            cx.notify();
+                        })
+                        .ok();
+                    }
+                }
+            }
+        });
+        self.watch = Some((watcher, task));
+    }
+}
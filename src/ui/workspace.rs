@@ -1,22 +1,1249 @@
-use gpui::{Context, Entity, IntoElement, Render, Window, div, prelude::*, px, rgb};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gpui::{
+    AnyElement, App, AsyncWindowContext, Axis, ClickEvent, Context, CursorStyle, DragMoveEvent,
+    EmptyView, Entity, Focusable, IntoElement, PathPromptOptions, PromptLevel, Render, Result,
+    SharedString, WeakEntity, Window, actions, div, prelude::*, px, relative, rgb,
+};
 
 use super::EditorView;
+use super::backlinks::{BacklinksView, OpenBacklink};
+use super::document::Document;
+use super::editor::FollowWikiLink;
+use super::file_tree::{FileTreeView, OpenFile, PathMoved, PathTrashed};
+use super::pane_group::{Direction, PaneGroup, PaneId, PaneNode};
 use super::theme;
+use super::vault_index::VaultIndex;
+use crate::editor::{Journal, RecoveryEntry, read_file};
+use crate::vault::links::{Relocation, is_note, resolve_wiki_target, rewrite_files};
+use crate::vault::ops::create_note_at;
+use crate::vault::tree::FileTree;
+
+actions!(
+    zeno_workspace,
+    [
+        Open,
+        OpenFolder,
+        CloseTab,
+        SplitRight,
+        SplitDown,
+        ActivatePaneLeft,
+        ActivatePaneRight,
+        ActivatePaneUp,
+        ActivatePaneDown
+    ]
+);
+
+/// Panes are not resized below this along the split's axis.
+const MIN_PANE_SIZE: f32 = 160.;
+
+/// A file open in a tab.
+pub struct OpenBuffer {
+    /// `None` for a note not saved yet.
+    pub path: Option<PathBuf>,
+    pub editor: Entity<EditorView>,
+    pub is_modified: bool,
+}
+
+impl OpenBuffer {
+    fn name(&self) -> SharedString {
+        self.path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned().into())
+            .unwrap_or_else(|| "Untitled".into())
+    }
+}
+
+/// A region of the editor area with its own tabs.
+struct Pane {
+    id: PaneId,
+    buffers: Vec<OpenBuffer>,
+    /// Index into `buffers` of the tab shown in the pane. Meaningless while there are no buffers.
+    active_index: usize,
+}
+
+impl Pane {
+    fn active_editor(&self) -> Option<&Entity<EditorView>> {
+        self.buffers
+            .get(self.active_index)
+            .map(|buffer| &buffer.editor)
+    }
+}
+
+/// A tab being dragged to a new position in its own or another pane's tab strip.
+#[derive(Clone)]
+struct DraggedTab {
+    pane: PaneId,
+    index: usize,
+    name: SharedString,
+}
+
+impl Render for DraggedTab {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .px_3()
+            .py_1()
+            .rounded_sm()
+            .bg(rgb(theme::BG_ACTIVE_ROW))
+            .text_color(rgb(theme::TEXT_PRIMARY))
+            .text_sm()
+            .child(self.name.clone())
+    }
+}
+
+/// The divider after child `index` of the split at `path` in the pane layout, being dragged.
+#[derive(Clone)]
+struct DraggedDivider {
+    path: Vec<usize>,
+    index: usize,
+}
 
 pub struct Workspace {
-    pub(crate) editor: Entity<EditorView>,
+    /// Every pane in the layout, in the order they were opened.
+    panes: Vec<Pane>,
+    pane_group: PaneGroup,
+    /// The pane last focused, where files open and tab actions apply.
+    active_pane: PaneId,
+    next_pane_id: PaneId,
+    file_tree: Entity<FileTreeView>,
+    backlinks: Entity<BacklinksView>,
+    vault_index: Entity<VaultIndex>,
 }
 
 impl Workspace {
-    pub fn new(cx: &mut Context<Self>) -> Self {
-        let editor = cx.new(EditorView::new);
-        Self { editor }
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
+        let file_tree = cx.new(|cx| FileTreeView::new(window, cx));
+        let vault_index = cx.new(|_| VaultIndex::new());
+        let backlinks = cx.new(|cx| BacklinksView::new(vault_index.clone(), cx));
+        cx.subscribe_in(
+            &file_tree,
+            window,
+            |workspace, _, OpenFile(path), window, cx| {
+                workspace.open_path(path.clone(), window, cx);
+            },
+        )
+        .detach();
+        cx.subscribe_in(
+            &file_tree,
+            window,
+            |workspace, _, PathMoved { from, to }, window, cx| {
+                workspace.path_moved(from, to, window, cx);
+            },
+        )
+        .detach();
+        cx.subscribe_in(
+            &file_tree,
+            window,
+            |workspace, _, PathTrashed(path), window, cx| {
+                workspace.path_trashed(path, window, cx);
+            },
+        )
+        .detach();
+        cx.subscribe_in(
+            &backlinks,
+            window,
+            |workspace, _, OpenBacklink { path, line }, window, cx| {
+                workspace.open_path_at(path.clone(), Some(*line), window, cx);
+            },
+        )
+        .detach();
+        if let Some(journal) = Journal::in_app_data() {
+            cx.spawn_in(window, async move |this, cx| {
+                Self::offer_recovery(this, journal, cx).await
+            })
+            .detach();
+        }
+
+        let mut workspace = Self {
+            panes: vec![Pane {
+                id: 0,
+                buffers: Vec::new(),
+                active_index: 0,
+            }],
+            pane_group: PaneGroup::new(0),
+            active_pane: 0,
+            next_pane_id: 1,
+            file_tree,
+            backlinks,
+            vault_index,
+        };
+        let editor = cx.new(|cx| EditorView::new(window, cx));
+        workspace.add_buffer(0, editor, window, cx);
+        workspace
+    }
+
+    fn pane(&self, id: PaneId) -> Option<&Pane> {
+        self.panes.iter().find(|pane| pane.id == id)
+    }
+
+    fn pane_mut(&mut self, id: PaneId) -> Option<&mut Pane> {
+        self.panes.iter_mut().find(|pane| pane.id == id)
+    }
+
+    /// The pane and tab index showing `editor`.
+    fn position_of(&self, editor: &Entity<EditorView>) -> Option<(PaneId, usize)> {
+        self.panes.iter().find_map(|pane| {
+            pane.buffers
+                .iter()
+                .position(|buffer| &buffer.editor == editor)
+                .map(|index| (pane.id, index))
+        })
+    }
+
+    fn active_buffer(&self) -> Option<&OpenBuffer> {
+        self.pane(self.active_pane)
+            .and_then(|pane| pane.buffers.get(pane.active_index))
+    }
+
+    pub fn active_editor(&self) -> Option<&Entity<EditorView>> {
+        self.pane(self.active_pane).and_then(Pane::active_editor)
+    }
+
+    pub fn focus_active_editor(&self, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(editor) = self.active_editor() {
+            window.focus(&editor.focus_handle(cx));
+        }
+    }
+
+    /// Appends a tab for `editor` to `pane` and makes it active there.
+    fn add_buffer(
+        &mut self,
+        pane: PaneId,
+        editor: Entity<EditorView>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        editor.update(cx, |editor, _| {
+            editor.set_vault_index(self.vault_index.clone())
+        });
+        // Tabs show the editor's file name and dirty state, and change with them.
+        cx.observe(&editor, |workspace, editor, cx| {
+            let document = editor.read(cx).document().read(cx);
+            let path = document.path().map(Path::to_path_buf);
+            let is_modified = document.is_dirty();
+            if let Some((pane, index)) = workspace.position_of(&editor)
+                && let Some(pane) = workspace.pane_mut(pane)
+            {
+                let buffer = &mut pane.buffers[index];
+                buffer.path = path;
+                buffer.is_modified = is_modified;
+            }
+            workspace.sync_active_path(cx);
+            cx.notify();
+        })
+        .detach();
+        cx.subscribe_in(
+            &editor,
+            window,
+            |workspace, editor, FollowWikiLink { target }, window, cx| {
+                let source = editor.read(cx).document().read(cx).path();
+                let source = source.map(Path::to_path_buf);
+                workspace.follow_wiki_link(target.clone(), source, window, cx);
+            },
+        )
+        .detach();
+        // Clicking into a pane's editor makes the pane active.
+        let focused = editor.downgrade();
+        cx.on_focus(&editor.focus_handle(cx), window, move |workspace, _, cx| {
+            let Some(editor) = focused.upgrade() else {
+                return;
+            };
+            if let Some((pane, index)) = workspace.position_of(&editor)
+                && let Some(pane) = workspace.pane_mut(pane)
+            {
+                pane.active_index = index;
+                workspace.active_pane = pane.id;
+                workspace.sync_active_path(cx);
+                cx.notify();
+            }
+        })
+        .detach();
+
+        let document = editor.read(cx).document().read(cx);
+        let buffer = OpenBuffer {
+            path: document.path().map(Path::to_path_buf),
+            is_modified: document.is_dirty(),
+            editor,
+        };
+        let Some(pane) = self.pane_mut(pane) else {
+            return;
+        };
+        pane.buffers.push(buffer);
+        pane.active_index = pane.buffers.len() - 1;
+        self.active_pane = pane.id;
+        self.sync_active_path(cx);
+        cx.notify();
+    }
+
+    /// The active editor if it holds a blank new note, for a file being opened to replace.
+    fn blank_active_editor(&self, cx: &Context<Self>) -> Option<Entity<EditorView>> {
+        self.active_editor()
+            .filter(|editor| editor.read(cx).document().read(cx).is_blank())
+            .cloned()
+    }
+
+    fn sync_active_path(&mut self, cx: &mut Context<Self>) {
+        let path = self.active_buffer().and_then(|buffer| buffer.path.clone());
+        self.backlinks
+            .update(cx, |backlinks, cx| backlinks.set_note(path.clone(), cx));
+        self.file_tree
+            .update(cx, |file_tree, cx| file_tree.set_active_path(path, cx));
+    }
+
+    /// Shows the folder at `root` in the sidebar.
+    pub fn open_vault(&mut self, root: PathBuf, cx: &mut Context<Self>) {
+        self.vault_index
+            .update(cx, |vault_index, cx| vault_index.open(root.clone(), cx));
+        self.file_tree
+            .update(cx, |file_tree, cx| file_tree.open(root, cx));
+    }
+
+    fn open(&mut self, _: &Open, window: &mut Window, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: true,
+            prompt: None,
+        });
+        cx.spawn_in(window, async move |this, cx| {
+            let Ok(Ok(Some(paths))) = paths.await else {
+                return;
+            };
+            for path in paths {
+                this.update_in(cx, |workspace, window, cx| {
+                    workspace.open_path(path, window, cx)
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    fn open_folder(&mut self, _: &OpenFolder, window: &mut Window, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: false,
+            directories: true,
+            multiple: false,
+            prompt: Some("Open Vault".into()),
+        });
+        cx.spawn_in(window, async move |this, cx| {
+            let Ok(Ok(Some(paths))) = paths.await else {
+                return;
+            };
+            if let Some(root) = paths.into_iter().next() {
+                this.update(cx, |workspace, cx| workspace.open_vault(root, cx))
+                    .ok();
+            }
+        })
+        .detach();
+    }
+
+    /// Every open document whose file is `path` or inside the folder `path`, once each.
+    fn documents_under(&self, path: &Path, cx: &App) -> Vec<Entity<Document>> {
+        let mut documents: Vec<Entity<Document>> = Vec::new();
+        for buffer in self.panes.iter().flat_map(|pane| &pane.buffers) {
+            let document = buffer.editor.read(cx).document();
+            if document
+                .read(cx)
+                .path()
+                .is_some_and(|file| file.starts_with(path))
+                && !documents.contains(document)
+            {
+                documents.push(document.clone());
+            }
+        }
+        documents
+    }
+
+    /// Points buffers at their files' new paths after the file, or a folder containing it, was
+    /// renamed or moved in the sidebar, then offers to fix the links the move broke.
+    fn path_moved(&mut self, from: &Path, to: &Path, window: &mut Window, cx: &mut Context<Self>) {
+        for document in self.documents_under(from, cx) {
+            document.update(cx, |document, cx| {
+                let Some(relative) = document
+                    .path()
+                    .and_then(|path| path.strip_prefix(from).ok())
+                else {
+                    return;
+                };
+                let path = if relative.as_os_str().is_empty() {
+                    to.to_path_buf()
+                } else {
+                    to.join(relative)
+                };
+                document.follow_file(Some(path), cx);
+            });
+        }
+        self.offer_link_rewrites(from.to_path_buf(), to.to_path_buf(), window, cx);
+    }
+
+    /// The open document showing the file at `path`, if any.
+    fn document_for_path(&self, path: &Path, cx: &App) -> Option<Entity<Document>> {
+        self.panes
+            .iter()
+            .flat_map(|pane| &pane.buffers)
+            .map(|buffer| buffer.editor.read(cx).document())
+            .find(|document| document.read(cx).path() == Some(path))
+            .cloned()
+    }
+
+    /// Finds the links across the vault that pointed at what moved from `from` to `to`, and after
+    /// showing which notes they are in, rewrites them. Open notes are rewritten in their buffers
+    /// as one undoable edit; the others on disk once every one of them has been read.
+    fn offer_link_rewrites(
+        &mut self,
+        from: PathBuf,
+        to: PathBuf,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(root) = self.file_tree.read(cx).vault_root().map(Path::to_path_buf) else {
+            return;
+        };
+        // Open notes are scanned as they are in their buffers, unsaved edits included.
+        let mut open_texts = HashMap::new();
+        for buffer in self.panes.iter().flat_map(|pane| &pane.buffers) {
+            let document = buffer.editor.read(cx).document().read(cx);
+            if let Some(path) = document.path() {
+                open_texts.insert(path.to_path_buf(), document.state().text().to_string());
+            }
+        }
+        cx.spawn_in(window, async move |this, cx| -> Result<()> {
+            let scan_root = root.clone();
+            let (relocation, affected) = cx
+                .background_executor()
+                .spawn(async move {
+                    let files = FileTree::walk(&scan_root)?.files();
+                    let relocation = Relocation::new(&scan_root, &from, &to, &files);
+                    let mut affected = Vec::new();
+                    for file in files.into_iter().filter(|file| is_note(file)) {
+                        let text = match open_texts.remove(&file) {
+                            Some(text) => text,
+                            None => match read_file(&file) {
+                                Ok((text, _)) => text,
+                                Err(_) => continue,
+                            },
+                        };
+                        let count = relocation.link_edits(&file, &text).len();
+                        if count > 0 {
+                            affected.push((file, count));
+                        }
+                    }
+                    io::Result::Ok((relocation, affected))
+                })
+                .await?;
+            if affected.is_empty() {
+                return Ok(());
+            }
+
+            let links: usize = affected.iter().map(|(_, count)| count).sum();
+            let message = format!(
+                "Update {links} {} in {} {}?",
+                if links == 1 { "link" } else { "links" },
+                affected.len(),
+                if affected.len() == 1 { "note" } else { "notes" },
+            );
+            const LISTED: usize = 10;
+            let mut detail = affected
+                .iter()
+                .take(LISTED)
+                .map(|(file, count)| {
+                    let name = file.strip_prefix(&root).unwrap_or(file).display();
+                    format!("{name} ({count})")
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            if affected.len() > LISTED {
+                detail.push_str(&format!("\nand {} more", affected.len() - LISTED));
+            }
+            let answer = this.update_in(cx, |_, window, cx| {
+                window.prompt(
+                    PromptLevel::Info,
+                    &message,
+                    Some(&detail),
+                    &["Update Links", "Don't Update"],
+                    cx,
+                )
+            })?;
+            if !matches!(answer.await, Ok(0)) {
+                return Ok(());
+            }
+
+            let mut on_disk = Vec::new();
+            this.update_in(cx, |workspace, window, cx| {
+                for (file, _) in affected {
+                    let Some(document) = workspace.document_for_path(&file, cx) else {
+                        on_disk.push(file);
+                        continue;
+                    };
+                    // Edits are worked out again for the buffer as it is now.
+                    document.update(cx, |document, cx| {
+                        let edits = relocation.link_edits(&file, document.state().text());
+                        document.edit_ranges(edits, window, cx);
+                    });
+                }
+            })?;
+            let written = cx
+                .background_executor()
+                .spawn(async move { rewrite_files(&relocation, &on_disk) })
+                .await;
+            if let Err(error) = written {
+                this.update_in(cx, |_, window, cx| {
+                    drop(window.prompt(
+                        PromptLevel::Critical,
+                        "Could not update links",
+                        Some(&error.to_string()),
+                        &["OK"],
+                        cx,
+                    ));
+                })?;
+            }
+            Ok(())
+        })
+        .detach();
+    }
+
+    /// Closes the tabs of files trashed in the sidebar. Buffers with unsaved changes stay open,
+    /// detached from the file, so the changes are not lost with the tab.
+    fn path_trashed(&mut self, path: &Path, window: &mut Window, cx: &mut Context<Self>) {
+        for document in self.documents_under(path, cx) {
+            if document.read(cx).is_dirty() {
+                document.update(cx, |document, cx| document.follow_file(None, cx));
+                continue;
+            }
+            let editors: Vec<_> = self
+                .panes
+                .iter()
+                .flat_map(|pane| &pane.buffers)
+                .map(|buffer| buffer.editor.clone())
+                .filter(|editor| editor.read(cx).document() == &document)
+                .collect();
+            for editor in editors {
+                self.remove_buffer(&editor, window, cx);
+            }
+        }
+    }
+
+    /// Opens the note a wiki link in the note at `source` names, looking for it across the vault,
+    /// or across the note's folder when no vault is open. A note that does not exist yet can be
+    /// created there.
+    fn follow_wiki_link(
+        &mut self,
+        target: String,
+        source: Option<PathBuf>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let root = match self.file_tree.read(cx).vault_root() {
+            Some(root) => root.to_path_buf(),
+            None => match source.as_deref().and_then(Path::parent) {
+                Some(dir) => dir.to_path_buf(),
+                None => return,
+            },
+        };
+        // Once the vault has been indexed its notes are looked up there; until then, or with no
+        // vault open, the folder is walked for them.
+        let index = self.vault_index.read(cx).index();
+        let indexed = (index.root() == root).then(|| index.resolve(&target));
+        cx.spawn_in(window, async move |this, cx| -> Result<()> {
+            let found = match indexed {
+                Some(found) => found,
+                None => {
+                    let (scan_root, scan_target) = (root.clone(), target.clone());
+                    cx.background_executor()
+                        .spawn(async move {
+                            let files = FileTree::walk(&scan_root)?.files();
+                            io::Result::Ok(resolve_wiki_target(&scan_root, &files, &scan_target))
+                        })
+                        .await?
+                }
+            };
+            if let Some(path) = found {
+                return this.update_in(cx, |workspace, window, cx| {
+                    workspace.open_path(path, window, cx);
+                });
+            }
+
+            let message = format!("No file named \"{target}\" found. Create it?");
+            let answer = this.update_in(cx, |_, window, cx| {
+                window.prompt(
+                    PromptLevel::Info,
+                    &message,
+                    None,
+                    &["Create Note", "Cancel"],
+                    cx,
+                )
+            })?;
+            if !matches!(answer.await, Ok(0)) {
+                return Ok(());
+            }
+            let created = cx
+                .background_executor()
+                .spawn(async move { create_note_at(&root, &target) })
+                .await;
+            this.update_in(cx, |workspace, window, cx| match created {
+                Ok(path) => {
+                    workspace
+                        .file_tree
+                        .update(cx, |file_tree, cx| file_tree.refresh(cx));
+                    workspace.open_path(path, window, cx);
+                }
+                Err(error) => {
+                    drop(window.prompt(
+                        PromptLevel::Critical,
+                        "Could not create the note",
+                        Some(&error.to_string()),
+                        &["OK"],
+                        cx,
+                    ));
+                }
+            })
+        })
+        .detach();
+    }
+
+    /// The pane and tab index of a tab showing the file at `path`, preferring the active pane.
+    fn find_path(&self, path: &Path) -> Option<(PaneId, usize)> {
+        let in_pane = |pane: &Pane| {
+            pane.buffers
+                .iter()
+                .position(|buffer| buffer.path.as_deref() == Some(path))
+                .map(|index| (pane.id, index))
+        };
+        self.pane(self.active_pane)
+            .and_then(in_pane)
+            .or_else(|| self.panes.iter().find_map(in_pane))
+    }
+
+    /// Opens the file at `path` in a new tab in the active pane, or switches to a tab already
+    /// showing it.
+    pub fn open_path(&mut self, path: PathBuf, window: &mut Window, cx: &mut Context<Self>) {
+        self.open_path_at(path, None, window, cx);
+    }
+
+    /// Opens the file at `path` like `open_path`, then moves to `line`, zero-based, if given.
+    fn open_path_at(
+        &mut self,
+        path: PathBuf,
+        line: Option<usize>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        cx.spawn_in(window, async move |this, cx| {
+            let (path, read) = cx
+                .background_executor()
+                .spawn(async move {
+                    // Paths from the tree and from file dialogs may differ only in how they are
+                    // spelled; compare them canonically.
+                    let path = fs::canonicalize(&path).unwrap_or(path);
+                    let read = read_file(&path);
+                    (path, read)
+                })
+                .await;
+            this.update_in(cx, |workspace, window, cx| {
+                if let Some((pane, index)) = workspace.find_path(&path) {
+                    workspace.activate(pane, index, window, cx);
+                    if let Some(line) = line
+                        && let Some(editor) = workspace.active_editor()
+                    {
+                        editor.update(cx, |editor, cx| editor.go_to_line(line, cx));
+                    }
+                    return;
+                }
+                let (text, format) = match read {
+                    Ok(read) => read,
+                    Err(error) => {
+                        let message = format!("Could not open {}", path.display());
+                        let detail = error.to_string();
+                        drop(window.prompt(
+                            PromptLevel::Critical,
+                            &message,
+                            Some(&detail),
+                            &["OK"],
+                            cx,
+                        ));
+                        return;
+                    }
+                };
+                let editor = match workspace.blank_active_editor(cx) {
+                    Some(editor) => editor,
+                    None => {
+                        let editor = cx.new(|cx| EditorView::new(window, cx));
+                        workspace.add_buffer(workspace.active_pane, editor.clone(), window, cx);
+                        editor
+                    }
+                };
+                let document = editor.read(cx).document().clone();
+                document.update(cx, |document, cx| document.load(path, text, format, cx));
+                if let Some(line) = line {
+                    editor.update(cx, |editor, cx| editor.go_to_line(line, cx));
+                }
+                workspace.focus_active_editor(window, cx);
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn activate(
+        &mut self,
+        pane: PaneId,
+        index: usize,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(pane) = self.pane_mut(pane) else {
+            return;
+        };
+        if index < pane.buffers.len() {
+            pane.active_index = index;
+            self.active_pane = pane.id;
+            self.sync_active_path(cx);
+            self.focus_active_editor(window, cx);
+            cx.notify();
+        }
+    }
+
+    fn close_tab(&mut self, _: &CloseTab, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(pane) = self.pane(self.active_pane) else {
+            return;
+        };
+        self.close_buffer(pane.id, pane.active_index, window, cx);
+    }
+
+    /// Closes the tab at `index` in `pane`, first asking whether to save unsaved changes unless
+    /// another tab still shows them.
+    fn close_buffer(
+        &mut self,
+        pane: PaneId,
+        index: usize,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(buffer) = self.pane(pane).and_then(|pane| pane.buffers.get(index)) else {
+            return;
+        };
+        let editor = buffer.editor.clone();
+        let document = editor.read(cx).document().clone();
+        let shown_elsewhere = self
+            .panes
+            .iter()
+            .flat_map(|pane| &pane.buffers)
+            .any(|other| other.editor != editor && other.editor.read(cx).document() == &document);
+        if !buffer.is_modified || shown_elsewhere {
+            self.remove_buffer(&editor, window, cx);
+            return;
+        }
+        let answer = window.prompt(
+            PromptLevel::Warning,
+            &format!("Save changes to {}?", buffer.name()),
+            Some("Your changes will be lost if you don't save them."),
+            &["Save", "Don't Save", "Cancel"],
+            cx,
+        );
+        cx.spawn_in(window, async move |this, cx| {
+            match answer.await {
+                Ok(0) => {
+                    let Ok(saved) = document
+                        .update_in(cx, |document, window, cx| document.save_file(window, cx))
+                    else {
+                        return;
+                    };
+                    if !saved.await {
+                        return;
+                    }
+                }
+                Ok(1) => {
+                    document
+                        .update(cx, |document, cx| document.discard_journal(cx))
+                        .ok();
+                }
+                _ => return,
+            }
+            this.update_in(cx, |workspace, window, cx| {
+                workspace.remove_buffer(&editor, window, cx)
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Removes the tab showing `editor`, which may have moved while a close prompt was open.
+    /// Closing the last tab of a pane closes the pane, unless it is the only one.
+    fn remove_buffer(
+        &mut self,
+        editor: &Entity<EditorView>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some((pane_id, index)) = self.position_of(editor) else {
+            return;
+        };
+        let Some(pane) = self.pane_mut(pane_id) else {
+            return;
+        };
+        pane.buffers.remove(index);
+        if index < pane.active_index || pane.active_index >= pane.buffers.len() {
+            pane.active_index = pane.active_index.saturating_sub(1);
+        }
+        if pane.buffers.is_empty() {
+            self.remove_pane(pane_id);
+        }
+        self.sync_active_path(cx);
+        self.focus_active_editor(window, cx);
+        cx.notify();
+    }
+
+    /// Takes an emptied pane out of the layout, handing focus to its neighbour.
+    fn remove_pane(&mut self, id: PaneId) {
+        if self.panes.len() == 1 {
+            return;
+        }
+        let neighbour = [
+            Direction::Left,
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+        ]
+        .into_iter()
+        .find_map(|direction| self.pane_group.neighbour(id, direction));
+        self.pane_group.remove(id);
+        self.panes.retain(|pane| pane.id != id);
+        if self.active_pane == id {
+            self.active_pane = neighbour.unwrap_or(self.panes[0].id);
+        }
+    }
+
+    /// Moves the tab at `from` in `from_pane` to `to` in `to_pane`. Within a pane the same tab
+    /// stays active; a tab moved to another pane becomes active there.
+    fn move_tab(
+        &mut self,
+        (from_pane, from): (PaneId, usize),
+        (to_pane, to): (PaneId, usize),
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if from_pane == to_pane {
+            let Some(pane) = self.pane_mut(from_pane) else {
+                return;
+            };
+            if from == to || from >= pane.buffers.len() || to >= pane.buffers.len() {
+                return;
+            }
+            let active = pane.buffers[pane.active_index].editor.clone();
+            let buffer = pane.buffers.remove(from);
+            pane.buffers.insert(to, buffer);
+            pane.active_index = pane
+                .buffers
+                .iter()
+                .position(|buffer| buffer.editor == active)
+                .unwrap_or(to);
+            cx.notify();
+            return;
+        }
+
+        let Some(source) = self.pane_mut(from_pane) else {
+            return;
+        };
+        if from >= source.buffers.len() {
+            return;
+        }
+        let buffer = source.buffers.remove(from);
+        if from < source.active_index || source.active_index >= source.buffers.len() {
+            source.active_index = source.active_index.saturating_sub(1);
+        }
+        let source_is_empty = source.buffers.is_empty();
+        let Some(target) = self.pane_mut(to_pane) else {
+            return;
+        };
+        let to = to.min(target.buffers.len());
+        target.buffers.insert(to, buffer);
+        target.active_index = to;
+        self.active_pane = to_pane;
+        if source_is_empty {
+            self.remove_pane(from_pane);
+        }
+        self.sync_active_path(cx);
+        self.focus_active_editor(window, cx);
+        cx.notify();
+    }
+
+    fn split_right(&mut self, _: &SplitRight, window: &mut Window, cx: &mut Context<Self>) {
+        self.split(Axis::Horizontal, window, cx);
+    }
+
+    fn split_down(&mut self, _: &SplitDown, window: &mut Window, cx: &mut Context<Self>) {
+        self.split(Axis::Vertical, window, cx);
+    }
+
+    /// Splits the active pane in two along `axis`. The new pane shows the active note in an
+    /// editor of its own, sharing the buffer but keeping separate cursors and scrolling.
+    fn split(&mut self, axis: Axis, window: &mut Window, cx: &mut Context<Self>) {
+        let editor = match self.active_editor().cloned() {
+            Some(source) => source.update(cx, |editor, cx| editor.duplicate(window, cx)),
+            None => cx.new(|cx| EditorView::new(window, cx)),
+        };
+        let id = self.next_pane_id;
+        self.next_pane_id += 1;
+        self.pane_group.split(self.active_pane, id, axis);
+        self.panes.push(Pane {
+            id,
+            buffers: Vec::new(),
+            active_index: 0,
+        });
+        self.add_buffer(id, editor, window, cx);
+        self.focus_active_editor(window, cx);
+    }
+
+    fn activate_pane_in(
+        &mut self,
+        direction: Direction,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(pane) = self.pane_group.neighbour(self.active_pane, direction) {
+            self.active_pane = pane;
+            self.sync_active_path(cx);
+            self.focus_active_editor(window, cx);
+            cx.notify();
+        }
+    }
+
+    fn activate_pane_left(
+        &mut self,
+        _: &ActivatePaneLeft,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.activate_pane_in(Direction::Left, window, cx);
+    }
+
+    fn activate_pane_right(
+        &mut self,
+        _: &ActivatePaneRight,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.activate_pane_in(Direction::Right, window, cx);
+    }
+
+    fn activate_pane_up(
+        &mut self,
+        _: &ActivatePaneUp,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.activate_pane_in(Direction::Up, window, cx);
+    }
+
+    fn activate_pane_down(
+        &mut self,
+        _: &ActivatePaneDown,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.activate_pane_in(Direction::Down, window, cx);
+    }
+
+    /// Offers to restore each buffer a previous session left unsaved, newest first, each into
+    /// its own tab. Entries older than their file were saved after all and are dropped; those
+    /// the user puts off are kept for the next start.
+    async fn offer_recovery(
+        this: WeakEntity<Self>,
+        journal: Journal,
+        cx: &mut AsyncWindowContext,
+    ) -> Result<()> {
+        let entries = cx
+            .background_executor()
+            .spawn(async move { journal.entries() })
+            .await?;
+        for entry in entries {
+            if !entry.is_newer_than_file() {
+                entry.discard().ok();
+                continue;
+            }
+            let name = entry
+                .path
+                .as_deref()
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "an untitled note".to_string());
+            let answer = this.update_in(cx, |_, window, cx| {
+                window.prompt(
+                    PromptLevel::Warning,
+                    &format!("Restore unsaved changes to {name}?"),
+                    Some("Zeno closed before they were saved."),
+                    &["Restore", "Discard", "Not Now"],
+                    cx,
+                )
+            })?;
+            match answer.await {
+                Ok(0) => {
+                    this.update_in(cx, |workspace, window, cx| {
+                        workspace.restore(&entry, window, cx)
+                    })?;
+                    entry.discard().ok();
+                }
+                Ok(1) => {
+                    entry.discard().ok();
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, entry: &RecoveryEntry, window: &mut Window, cx: &mut Context<Self>) {
+        let open = entry
+            .path
+            .as_deref()
+            .and_then(|path| self.find_path(path))
+            .and_then(|(pane, index)| self.pane(pane)?.buffers.get(index))
+            .map(|buffer| buffer.editor.clone());
+        let editor = match open.or_else(|| self.blank_active_editor(cx)) {
+            Some(editor) => editor,
+            None => {
+                let editor = cx.new(|cx| EditorView::new(window, cx));
+                self.add_buffer(self.active_pane, editor.clone(), window, cx);
+                editor
+            }
+        };
+        let document = editor.read(cx).document().clone();
+        document.update(cx, |document, cx| document.restore(entry, cx));
+    }
+
+    fn render_tab_bar(&self, pane: &Pane, cx: &mut Context<Self>) -> impl IntoElement {
+        let pane_id = pane.id;
+        let is_active_pane = pane_id == self.active_pane;
+        let tabs = pane.buffers.iter().enumerate().map(|(index, buffer)| {
+            let is_active = index == pane.active_index;
+            let name = buffer.name();
+            div()
+                .id(("tab", index))
+                .flex()
+                .flex_row()
+                .flex_shrink_0()
+                .items_center()
+                .gap_2()
+                .pl_3()
+                .pr_1()
+                .py_1()
+                .border_r_1()
+                .border_color(rgb(theme::BORDER))
+                .text_sm()
+                .cursor_pointer()
+                .bg(rgb(if is_active {
+                    theme::BG_APP
+                } else {
+                    theme::BG_SIDEBAR
+                }))
+                .when(!is_active || !is_active_pane, |tab| {
+                    tab.text_color(rgb(theme::TEXT_MUTED))
+                })
+                .when(!is_active, |tab| {
+                    tab.hover(|style| style.bg(rgb(theme::BORDER)))
+                })
+                .on_click(cx.listener(move |workspace, _, window, cx| {
+                    workspace.activate(pane_id, index, window, cx)
+                }))
+                .on_drag(
+                    DraggedTab {
+                        pane: pane_id,
+                        index,
+                        name: name.clone(),
+                    },
+                    |tab, _, _, cx| cx.new(|_| tab.clone()),
+                )
+                .drag_over::<DraggedTab>(|style, _, _, _| style.bg(rgb(theme::BG_ACTIVE_ROW)))
+                .on_drop(
+                    cx.listener(move |workspace, dragged: &DraggedTab, window, cx| {
+                        workspace.move_tab(
+                            (dragged.pane, dragged.index),
+                            (pane_id, index),
+                            window,
+                            cx,
+                        )
+                    }),
+                )
+                .child(name)
+                .child(
+                    div()
+                        .id(("close-tab", index))
+                        .w(px(16.))
+                        .flex()
+                        .justify_center()
+                        .rounded_sm()
+                        .text_color(rgb(theme::TEXT_MUTED))
+                        .hover(|style| style.bg(rgb(theme::BORDER)))
+                        .child(if buffer.is_modified { "●" } else { "×" })
+                        .on_click(cx.listener(move |workspace, _: &ClickEvent, window, cx| {
+                            cx.stop_propagation();
+                            workspace.close_buffer(pane_id, index, window, cx);
+                        })),
+                )
+        });
+        div()
+            .id(("tab-bar", pane_id))
+            .flex()
+            .flex_row()
+            .flex_shrink_0()
+            .overflow_x_scroll()
+            .bg(rgb(theme::BG_SIDEBAR))
+            .border_b_1()
+            .border_color(rgb(theme::BORDER))
+            .children(tabs)
+    }
+
+    fn render_pane(&self, pane: &Pane, cx: &mut Context<Self>) -> impl IntoElement {
+        let editor_area = match pane.active_editor() {
+            Some(editor) => div()
+                .flex()
+                .flex_col()
+                .flex_grow()
+                .min_h_0()
+                .p_6()
+                .child(editor.clone()),
+            None => div()
+                .flex()
+                .flex_grow()
+                .items_center()
+                .justify_center()
+                .text_sm()
+                .text_color(rgb(theme::TEXT_MUTED))
+                .child("Open a note from the sidebar or with ⌘O."),
+        };
+        div()
+            .flex()
+            .flex_col()
+            .size_full()
+            .child(self.render_tab_bar(pane, cx))
+            .child(editor_area)
+    }
+
+    /// Renders the part of the pane layout at `path`: a pane, or a split with a draggable
+    /// divider between each pair of children.
+    fn render_pane_node(
+        &self,
+        node: &PaneNode,
+        path: Vec<usize>,
+        cx: &mut Context<Self>,
+    ) -> AnyElement {
+        let (axis, children, sizes) = match node {
+            PaneNode::Pane(id) => {
+                return match self.pane(*id) {
+                    Some(pane) => self.render_pane(pane, cx).into_any_element(),
+                    None => div().into_any_element(),
+                };
+            }
+            PaneNode::Split {
+                axis,
+                children,
+                sizes,
+            } => (*axis, children, sizes),
+        };
+        let mut split = div()
+            .flex()
+            .when(axis == Axis::Vertical, |split| split.flex_col())
+            .size_full()
+            .min_w_0()
+            .min_h_0();
+        for (index, (child, size)) in children.iter().zip(sizes).enumerate() {
+            if index > 0 {
+                let divider = DraggedDivider {
+                    path: path.clone(),
+                    index: index - 1,
+                };
+                split = split.child(
+                    div()
+                        .id(SharedString::from(format!("divider-{path:?}-{index}")))
+                        .flex_shrink_0()
+                        .bg(rgb(theme::BORDER))
+                        .hover(|style| style.bg(rgb(theme::BG_ACTIVE_ROW)))
+                        .map(|divider| match axis {
+                            Axis::Horizontal => divider
+                                .w(px(4.))
+                                .h_full()
+                                .cursor(CursorStyle::ResizeLeftRight),
+                            Axis::Vertical => {
+                                divider.h(px(4.)).w_full().cursor(CursorStyle::ResizeUpDown)
+                            }
+                        })
+                        .on_drag(divider, |_, _, _, cx| cx.new(|_| EmptyView)),
+                );
+            }
+            let mut child_path = path.clone();
+            child_path.push(index);
+            split = split.child(
+                div()
+                    .flex()
+                    .flex_col()
+                    .flex_basis(relative(*size))
+                    .flex_grow()
+                    .flex_shrink()
+                    .min_w_0()
+                    .min_h_0()
+                    .overflow_hidden()
+                    .child(self.render_pane_node(child, child_path, cx)),
+            );
+        }
+        split
+            .on_drag_move(cx.listener(
+                move |workspace, event: &DragMoveEvent<DraggedDivider>, _, cx| {
+                    let divider = event.drag(cx);
+                    if divider.path != path {
+                        return;
+                    }
+                    let index = divider.index;
+                    let bounds = event.bounds;
+                    let position = event.event.position;
+                    let (offset, length) = match axis {
+                        Axis::Horizontal => (position.x - bounds.left(), bounds.size.width),
+                        Axis::Vertical => (position.y - bounds.top(), bounds.size.height),
+                    };
+                    if length <= px(0.) {
+                        return;
+                    }
+                    workspace.pane_group.resize(
+                        &path,
+                        index,
+                        offset / length,
+                        MIN_PANE_SIZE / f32::from(length),
+                    );
+                    cx.notify();
+                },
+            ))
+            .into_any_element()
     }
 }
 
 impl Render for Workspace {
-    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let title = match self.active_buffer() {
+            Some(buffer) => {
+                let dirty = if buffer.is_modified { "• " } else { "" };
+                format!("{dirty}{} — Zeno", buffer.name())
+            }
+            None => "Zeno".to_string(),
+        };
+        window.set_window_title(&title);
+
+        let root = self.pane_group.root().clone();
+        let editor_area = self.render_pane_node(&root, Vec::new(), cx);
+
         div()
+            .on_action(cx.listener(Self::open))
+            .on_action(cx.listener(Self::open_folder))
+            .on_action(cx.listener(Self::close_tab))
+            .on_action(cx.listener(Self::split_right))
+            .on_action(cx.listener(Self::split_down))
+            .on_action(cx.listener(Self::activate_pane_left))
+            .on_action(cx.listener(Self::activate_pane_right))
+            .on_action(cx.listener(Self::activate_pane_up))
+            .on_action(cx.listener(Self::activate_pane_down))
             .flex()
             .flex_col()
             .size_full()
@@ -27,36 +1254,35 @@ impl Render for Workspace {
                     .flex()
                     .flex_row()
                     .flex_grow()
+                    .min_h_0()
                     .child(
                         div()
                             .flex()
                             .flex_col()
+                            .flex_shrink_0()
                             .w(px(280.))
+                            .overflow_hidden()
                             .bg(rgb(theme::BG_SIDEBAR))
-                            .p_4()
-                            .gap_2()
+                            .p_2()
+                            .gap_1()
                             .child(
                                 div()
-                                    .text_sm()
-                                    .font_weight(gpui::FontWeight::BOLD)
-                                    .child("Sidebar"),
+                                    .flex()
+                                    .flex_col()
+                                    .flex_grow()
+                                    .min_h_0()
+                                    .child(self.file_tree.clone()),
                             )
-                            .child(
-                                div()
-                                    .text_xs()
-                                    .text_color(rgb(theme::TEXT_MUTED))
-                                    .child("Phase 0 placeholder"),
-                            ),
+                            .child(self.backlinks.clone()),
                     )
                     .child(
                         div()
                             .flex()
                             .flex_col()
                             .flex_grow()
+                            .min_w_0()
                             .bg(rgb(theme::BG_APP))
-                            .p_6()
-                            .gap_3()
-                            .child(self.editor.clone()),
+                            .child(editor_area),
                     ),
             )
             .child(
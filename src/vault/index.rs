@@ -0,0 +1,546 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::io;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use super::links::{is_note, resolve_markdown_target, resolve_wiki_target};
+use super::tree::{EXCLUDED_DIRS, FileTree};
+use crate::editor::read_file;
+use crate::markdown::links::{LinkKind, find_links};
+use crate::markdown::note::{Heading, aliases, headings};
+
+/// Completions offered at most, best first.
+const MAX_COMPLETIONS: usize = 50;
+
+/// Characters of a line shown as a backlink's snippet at most.
+const MAX_SNIPPET_CHARS: usize = 120;
+
+/// What the index knows of one note.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteEntry {
+    pub aliases: Vec<String>,
+    pub headings: Vec<Heading>,
+    /// The note's wiki and Markdown links, in order.
+    pub links: Vec<NoteLink>,
+    /// The note's text, for the snippets and unlinked mentions of backlinks.
+    pub text: String,
+}
+
+/// A link of an indexed note, as it is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteLink {
+    pub kind: LinkKind,
+    /// The note of a wiki link or the destination path of a Markdown link; see
+    /// [`crate::markdown::links::Link::target`].
+    pub target: String,
+    /// The whole link in the note's text.
+    pub range: Range<usize>,
+    /// The zero-based line the link is on.
+    pub line: usize,
+}
+
+/// A line of another note that links to or mentions a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backlink {
+    pub source: PathBuf,
+    /// Zero-based.
+    pub line: usize,
+    /// The line, trimmed and shortened.
+    pub snippet: String,
+}
+
+/// The notes linking to a note and those naming it without a link, found by
+/// [`NoteIndex::backlinks`]. Each line appears once, by note path and then line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backlinks {
+    pub linked: Vec<Backlink>,
+    /// Lines with the note's name or one of its aliases as plain text, outside links.
+    pub unlinked: Vec<Backlink>,
+}
+
+impl NoteEntry {
+    pub fn parse(text: &str) -> Self {
+        let mut line = 0;
+        let mut counted = 0;
+        let links = find_links(text)
+            .into_iter()
+            .map(|link| {
+                line += text[counted..link.range.start].matches('\n').count();
+                counted = link.range.start;
+                NoteLink {
+                    kind: link.kind,
+                    target: text[link.target].to_string(),
+                    range: link.range,
+                    line,
+                }
+            })
+            .collect();
+        Self {
+            aliases: aliases(text),
+            headings: headings(text),
+            links,
+            text: text.to_string(),
+        }
+    }
+
+    /// Reads the note at `path`. Blocking.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let (text, _) = read_file(path)?;
+        Ok(Self::parse(&text))
+    }
+}
+
+/// The notes of a vault by path, with the names, aliases and headings links use for them and
+/// the links they hold. Cheap to clone, for reading on a background executor.
+#[derive(Debug, Clone, Default)]
+pub struct NoteIndex {
+    root: PathBuf,
+    notes: BTreeMap<PathBuf, Arc<NoteEntry>>,
+}
+
+/// One suggestion for the wiki link being typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// What the list shows: a note's name, one of its aliases or a heading.
+    pub label: String,
+    /// Shown dimmed after the label, e.g. the note's folder or which note an alias belongs to.
+    pub detail: Option<String>,
+    /// The text to put between `[[` and `]]`.
+    pub text: String,
+}
+
+impl NoteIndex {
+    /// Reads every note in the vault at `root`, leaving out those that cannot be read. Blocking,
+    /// so run it on a background executor.
+    pub fn build(root: &Path) -> io::Result<Self> {
+        let tree = FileTree::walk(root)?;
+        let notes = tree
+            .files()
+            .into_iter()
+            .filter(|file| is_note(file))
+            .filter_map(|file| {
+                let entry = NoteEntry::read(&file).ok()?;
+                Some((file, Arc::new(entry)))
+            })
+            .collect();
+        Ok(Self {
+            root: tree.root,
+            notes,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether a change to `path` can affect the index: it is inside the vault and not in a
+    /// hidden or excluded folder. Ignore files are not consulted.
+    pub fn covers(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        relative.components().all(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                !name.starts_with('.') && !EXCLUDED_DIRS.contains(&name.as_ref())
+            }
+            _ => false,
+        })
+    }
+
+    /// Records what the note at `path` now holds, or that it is gone.
+    pub fn set(&mut self, path: PathBuf, entry: Option<NoteEntry>) {
+        match entry {
+            Some(entry) => {
+                self.notes.insert(path, Arc::new(entry));
+            }
+            None => {
+                self.notes.remove(&path);
+            }
+        }
+    }
+
+    /// The note a wiki link target names, as `resolve_wiki_target` finds it among the notes.
+    pub fn resolve(&self, target: &str) -> Option<PathBuf> {
+        let files = self.notes.keys().cloned().collect::<Vec<_>>();
+        resolve_wiki_target(&self.root, &files, target)
+    }
+
+    /// Completions for `query`, the text typed after `[[` in the note at `source`. Before a `#`
+    /// the query matches note names and aliases; after it, the headings of the note named
+    /// before it, or of `source` when nothing is.
+    pub fn complete(&self, query: &str, source: Option<&Path>) -> Vec<Completion> {
+        let mut scored = match query.split_once('#') {
+            Some((note, heading)) => self.complete_heading(note.trim(), heading.trim(), source),
+            None => self.complete_note(query.trim()),
+        };
+        // Stable, so equally good headings stay in document order.
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.truncate(MAX_COMPLETIONS);
+        scored
+            .into_iter()
+            .map(|(_, completion)| completion)
+            .collect()
+    }
+
+    fn complete_note(&self, query: &str) -> Vec<(i64, Completion)> {
+        let mut stem_counts: HashMap<String, usize> = HashMap::new();
+        for path in self.notes.keys() {
+            *stem_counts.entry(stem(path).to_lowercase()).or_default() += 1;
+        }
+        let mut scored = Vec::new();
+        for (path, entry) in &self.notes {
+            let name = stem(path);
+            let folder = path
+                .parent()
+                .and_then(|parent| parent.strip_prefix(&self.root).ok())
+                .filter(|folder| !folder.as_os_str().is_empty())
+                .map(|folder| folder.to_string_lossy().into_owned());
+            // A name other notes share would be ambiguous; such notes are linked by path.
+            let link = if stem_counts.get(&name.to_lowercase()) == Some(&1) {
+                name.to_string()
+            } else {
+                let relative = path.strip_prefix(&self.root).unwrap_or(path);
+                let relative = relative.with_extension("");
+                relative.to_string_lossy().replace('\\', "/")
+            };
+            if let Some(score) = fuzzy_score(query, name) {
+                scored.push((
+                    score,
+                    Completion {
+                        label: name.to_string(),
+                        detail: folder,
+                        text: link.clone(),
+                    },
+                ));
+            }
+            for alias in &entry.aliases {
+                if let Some(score) = fuzzy_score(query, alias) {
+                    scored.push((
+                        score,
+                        Completion {
+                            label: alias.clone(),
+                            detail: Some(format!("alias of {name}")),
+                            text: format!("{link}|{alias}"),
+                        },
+                    ));
+                }
+            }
+        }
+        // Alphabetical among equals; `complete` keeps this order for ties.
+        scored.sort_by_cached_key(|(_, completion)| completion.label.to_lowercase());
+        scored
+    }
+
+    fn complete_heading(
+        &self,
+        note: &str,
+        query: &str,
+        source: Option<&Path>,
+    ) -> Vec<(i64, Completion)> {
+        let path = if note.is_empty() {
+            source.map(Path::to_path_buf)
+        } else {
+            self.resolve(note)
+        };
+        let Some(entry) = path.as_deref().and_then(|path| self.notes.get(path)) else {
+            return Vec::new();
+        };
+        entry
+            .headings
+            .iter()
+            .filter_map(|heading| {
+                let score = fuzzy_score(query, &heading.text)?;
+                Some((
+                    score,
+                    Completion {
+                        label: heading.text.clone(),
+                        detail: Some("#".repeat(heading.level as usize)),
+                        text: format!("{note}#{}", heading.text),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// The lines of other notes that link to the note at `note`, through wiki links or
+    /// Markdown links, or that mention it by name.
+    pub fn backlinks(&self, note: &Path) -> Backlinks {
+        let files = self.notes.keys().cloned().collect::<Vec<_>>();
+        let name = stem(note).to_lowercase();
+        let file_name = note
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let mut mentioned = vec![name.clone()];
+        if let Some(entry) = self.notes.get(note) {
+            mentioned.extend(entry.aliases.iter().map(|alias| alias.to_lowercase()));
+        }
+
+        let mut backlinks = Backlinks::default();
+        for (source, entry) in &self.notes {
+            if source == note {
+                continue;
+            }
+            let lines = entry.text.lines().collect::<Vec<_>>();
+            let backlink = |line: usize| Backlink {
+                source: source.clone(),
+                line,
+                snippet: snippet(lines.get(line).copied().unwrap_or_default()),
+            };
+            for link in &entry.links {
+                let target = match link.kind {
+                    LinkKind::Wiki => {
+                        // Resolving is slow; only links by this note's name can resolve to it.
+                        let key = link.target.trim().replace('\\', "/").to_lowercase();
+                        let last = key.rsplit('/').next().unwrap_or_default();
+                        if last != name && last != file_name {
+                            continue;
+                        }
+                        resolve_wiki_target(&self.root, &files, &link.target)
+                    }
+                    LinkKind::Markdown { .. } => {
+                        resolve_markdown_target(&self.root, source, &link.target)
+                    }
+                };
+                let is_new_line = backlinks
+                    .linked
+                    .last()
+                    .is_none_or(|last| &last.source != source || last.line != link.line);
+                if target.as_deref() == Some(note) && is_new_line {
+                    backlinks.linked.push(backlink(link.line));
+                }
+            }
+            for line in mention_lines(entry, &mentioned) {
+                backlinks.unlinked.push(backlink(line));
+            }
+        }
+        backlinks
+    }
+}
+
+/// The lines of `entry` where one of `names`, in lowercase, appears as a whole word outside
+/// links, ignoring case.
+fn mention_lines(entry: &NoteEntry, names: &[String]) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for (number, line) in entry.text.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        // Lowercasing can change a char's length, so note where in `line` each byte came from.
+        let mut lower = String::with_capacity(line.len());
+        let mut origins = Vec::with_capacity(line.len() + 1);
+        for (at, ch) in line.char_indices() {
+            lower.extend(ch.to_lowercase());
+            origins.resize(lower.len(), at);
+        }
+        origins.push(line.len());
+        let mentions = names.iter().filter(|name| !name.is_empty()).any(|name| {
+            lower.match_indices(name.as_str()).any(|(at, _)| {
+                let end = at + name.len();
+                let before = lower[..at].chars().next_back();
+                let after = lower[end..].chars().next();
+                let (at, end) = (origins[at], origins[end]);
+                let in_link = entry
+                    .links
+                    .iter()
+                    .any(|link| link.range.start < start + end && start + at < link.range.end);
+                !before.is_some_and(char::is_alphanumeric)
+                    && !after.is_some_and(char::is_alphanumeric)
+                    && !in_link
+            })
+        });
+        if mentions {
+            lines.push(number);
+        }
+    }
+    lines
+}
+
+/// `line` trimmed and cut to [`MAX_SNIPPET_CHARS`].
+fn snippet(line: &str) -> String {
+    let line = line.trim();
+    match line.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}…", line[..end].trim_end()),
+        None => line.to_string(),
+    }
+}
+
+fn stem(path: &Path) -> &str {
+    path.file_stem().and_then(OsStr::to_str).unwrap_or_default()
+}
+
+/// How well `candidate` matches `query` typed as a subsequence of it, ignoring case, or `None`
+/// if it does not. Characters matched in a row and at the start of words count for more.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query = query.to_lowercase().chars().collect::<Vec<_>>();
+    let Some(&first) = query.first() else {
+        return Some(0);
+    };
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    let lower = candidate
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect::<Vec<_>>();
+    let word_start = |i: usize| i == 0 || !candidate[i - 1].is_alphanumeric();
+
+    // Greedy matching from each place the first character occurs, keeping the best.
+    let mut best = None;
+    for start in (0..lower.len()).filter(|&i| lower[i] == first) {
+        let mut score = 0;
+        let mut previous: Option<usize> = None;
+        let mut next = start;
+        let mut matched = 0;
+        for &c in &query {
+            let Some(i) = (next..lower.len()).find(|&i| lower[i] == c) else {
+                break;
+            };
+            score += 1;
+            if previous == Some(i.wrapping_sub(1)) {
+                score += 5;
+            }
+            if word_start(i) {
+                score += 8;
+            }
+            previous = Some(i);
+            next = i + 1;
+            matched += 1;
+        }
+        if matched == query.len() {
+            best = best.max(Some(score));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use super::{NoteEntry, NoteIndex, fuzzy_score};
+
+    fn index(notes: &[(&str, &str)]) -> NoteIndex {
+        NoteIndex {
+            root: PathBuf::from("/v"),
+            notes: notes
+                .iter()
+                .map(|(path, text)| (Path::new("/v").join(path), Arc::new(NoteEntry::parse(text))))
+                .collect(),
+        }
+    }
+
+    fn texts(index: &NoteIndex, query: &str, source: Option<&str>) -> Vec<String> {
+        let source = source.map(|source| Path::new("/v").join(source));
+        index
+            .complete(query, source.as_deref())
+            .into_iter()
+            .map(|completion| completion.text)
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_matches_prefer_runs_and_word_starts() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("xyz", "Plan"), None);
+        assert!(fuzzy_score("pl", "Plan") > fuzzy_score("pl", "Apple"));
+        assert!(fuzzy_score("mn", "Meeting notes") > fuzzy_score("mn", "Summon"));
+        assert!(fuzzy_score("plan", "Apple plan").is_some());
+    }
+
+    #[test]
+    fn completes_names_aliases_and_headings() {
+        let index = index(&[
+            (
+                "Plan.md",
+                "---\naliases: [Roadmap]\n---\n# Goals\n## Budget\n",
+            ),
+            ("Apple.md", ""),
+            ("a/Log.md", ""),
+            ("b/Log.md", "# Monday\n"),
+        ]);
+        assert_eq!(texts(&index, "pl", None), ["Plan", "Apple"]);
+        assert_eq!(texts(&index, "road", None), ["Plan|Roadmap"]);
+        assert_eq!(texts(&index, "log", None), ["a/Log", "b/Log"]);
+        assert_eq!(texts(&index, "", None).len(), 5);
+
+        assert_eq!(texts(&index, "Plan#", None), ["Plan#Goals", "Plan#Budget"]);
+        assert_eq!(texts(&index, "Plan#bud", None), ["Plan#Budget"]);
+        assert_eq!(texts(&index, "b/Log#", None), ["b/Log#Monday"]);
+        assert_eq!(texts(&index, "#go", Some("Plan.md")), ["#Goals"]);
+        assert!(texts(&index, "Missing#", None).is_empty());
+    }
+
+    #[test]
+    fn finds_links_and_mentions_of_a_note() {
+        let index = index(&[
+            (
+                "Plan.md",
+                "---\naliases: [Roadmap]\n---\nSee [[Plan]] here.\n",
+            ),
+            (
+                "a/Log.md",
+                "# Log\n\nWorked on the [[plan#Goals|plan]] and [[Other]].\nThe plan, again.\n",
+            ),
+            (
+                "a/Links.md",
+                "[one](../Plan.md) and [two](</Plan.md>)\n[[Plan]]\n",
+            ),
+            ("b/Notes.md", "planning the Roadmap\nexplain\n"),
+        ]);
+        let lines = |backlinks: &[super::Backlink]| {
+            backlinks
+                .iter()
+                .map(|backlink| {
+                    let source = backlink.source.strip_prefix("/v").unwrap();
+                    (source.to_string_lossy().into_owned(), backlink.line)
+                })
+                .collect::<Vec<_>>()
+        };
+        let backlinks = index.backlinks(Path::new("/v/Plan.md"));
+        assert_eq!(
+            lines(&backlinks.linked),
+            [
+                ("a/Links.md".to_string(), 0),
+                ("a/Links.md".to_string(), 1),
+                ("a/Log.md".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            backlinks.linked[2].snippet,
+            "Worked on the [[plan#Goals|plan]] and [[Other]]."
+        );
+        assert_eq!(
+            lines(&backlinks.unlinked),
+            [("a/Log.md".to_string(), 3), ("b/Notes.md".to_string(), 0)]
+        );
+        assert_eq!(
+            index.backlinks(Path::new("/v/a/Log.md")),
+            super::Backlinks::default()
+        );
+
+        // Names are compared in lowercase beyond ASCII, and chars whose lowercase is longer do not
+        // shift where links are.
+        let index = self::index(&[
+            ("Übersicht.md", ""),
+            ("c.md", "İ [[Übersicht]] übersicht\nÜBERSICHT!\n"),
+        ]);
+        let backlinks = index.backlinks(Path::new("/v/Übersicht.md"));
+        assert_eq!(lines(&backlinks.linked), [("c.md".to_string(), 0)]);
+        assert_eq!(
+            lines(&backlinks.unlinked),
+            [("c.md".to_string(), 0), ("c.md".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn covers_only_visible_vault_paths() {
+        let index = index(&[]);
+        assert!(index.covers(Path::new("/v/notes/a.md")));
+        assert!(!index.covers(Path::new("/v/.zeno-trash/a.md")));
+        assert!(!index.covers(Path::new("/v/node_modules/x/a.md")));
+        assert!(!index.covers(Path::new("/elsewhere/a.md")));
+    }
+}
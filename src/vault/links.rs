@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use crate::editor::{read_file, stage_file};
+use crate::markdown::links::{LinkKind, find_links};
+
+/// A file or folder of the vault renamed or moved from `from` to `to`, and how links across the
+/// vault have to change to keep pointing at the same files.
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    root: PathBuf,
+    from: PathBuf,
+    to: PathBuf,
+    /// New paths of the moved files by the lowercased vault-relative path, with and without a
+    /// `.md` extension, they had before: what path-style wiki links such as `[[notes/Idea]]`
+    /// name.
+    moved_by_path: HashMap<String, PathBuf>,
+    /// New paths of the moved files by the lowercased name bare wiki links such as `[[Idea]]`
+    /// used for them. Names another file also answers to are left out, since links using them
+    /// may mean that file.
+    moved_by_name: HashMap<String, PathBuf>,
+}
+
+impl Relocation {
+    /// `files` lists every file in the vault at `root` as it is after the move.
+    pub fn new(root: &Path, from: &Path, to: &Path, files: &[PathBuf]) -> Self {
+        let mut relocation = Self {
+            root: root.to_path_buf(),
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            moved_by_path: HashMap::new(),
+            moved_by_name: HashMap::new(),
+        };
+        let mut name_counts: HashMap<String, usize> = HashMap::new();
+        for file in files {
+            let old = relocation.old_path(file);
+            for name in link_names(&old) {
+                *name_counts.entry(name).or_default() += 1;
+            }
+            if !file.starts_with(to) {
+                continue;
+            }
+            let Ok(relative) = old.strip_prefix(root) else {
+                continue;
+            };
+            let relative = slash_path(relative).to_lowercase();
+            if let Some(stem) = relative.strip_suffix(".md") {
+                relocation
+                    .moved_by_path
+                    .insert(stem.to_string(), file.clone());
+            }
+            relocation.moved_by_path.insert(relative, file.clone());
+            for name in link_names(&old) {
+                relocation.moved_by_name.insert(name, file.clone());
+            }
+        }
+        relocation
+            .moved_by_name
+            .retain(|name, _| name_counts.get(name) == Some(&1));
+        relocation
+    }
+
+    /// Where the file now at `path` was before the move.
+    fn old_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.to) {
+            Ok(rest) => join(&self.from, rest),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Where the file that was at `path` is after the move.
+    fn new_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.from) {
+            Ok(rest) => join(&self.to, rest),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Edits to `text`, the note now at `path`, that point its links at the moved files' new
+    /// paths. Relative links of a note that moved itself are adjusted too. Each edit replaces
+    /// a link's target only, so aliases, headings and titles stay.
+    pub fn link_edits(&self, path: &Path, text: &str) -> Vec<(Range<usize>, String)> {
+        let old_source = self.old_path(path);
+        let mut edits = Vec::new();
+        for link in find_links(text) {
+            let target = &text[link.target.clone()];
+            let replacement = match link.kind {
+                LinkKind::Wiki => self.wiki_target(target),
+                LinkKind::Markdown { angle } => {
+                    self.markdown_target(target, angle, &old_source, path)
+                }
+            };
+            if let Some(replacement) = replacement.filter(|replacement| replacement != target) {
+                edits.push((link.target, replacement));
+            }
+        }
+        edits
+    }
+
+    fn wiki_target(&self, target: &str) -> Option<String> {
+        let key = target.replace('\\', "/").to_lowercase();
+        let keeps_extension = key.ends_with(".md");
+        if key.contains('/') {
+            let new = self.moved_by_path.get(&key)?;
+            let relative = slash_path(new.strip_prefix(&self.root).ok()?);
+            Some(match relative.strip_suffix(".md") {
+                Some(stem) if !keeps_extension => stem.to_string(),
+                _ => relative,
+            })
+        } else {
+            let new = self.moved_by_name.get(&key)?;
+            let name = new.file_name()?.to_string_lossy().into_owned();
+            // A link that already resolves by name keeps its spelling.
+            if link_names(&self.old_path(new)).contains(&key) && link_names(new).contains(&key) {
+                return None;
+            }
+            Some(match name.strip_suffix(".md") {
+                Some(stem) if !keeps_extension => stem.to_string(),
+                _ => name,
+            })
+        }
+    }
+
+    /// The new destination path for a Markdown link written `target` in the note that was at
+    /// `old_source` and is now at `new_source`. External URLs and links within the note are
+    /// left alone.
+    fn markdown_target(
+        &self,
+        target: &str,
+        angle: bool,
+        old_source: &Path,
+        new_source: &Path,
+    ) -> Option<String> {
+        if target.is_empty() || has_scheme(target) {
+            return None;
+        }
+        let decoded = percent_decode(target);
+        let from_root = decoded.starts_with('/');
+        let old_target = if from_root {
+            join(&self.root, Path::new(decoded.trim_start_matches('/')))
+        } else {
+            join(old_source.parent()?, Path::new(&decoded))
+        };
+        let new_target = self.new_path(&old_target);
+        if new_target == old_target && new_source == old_source {
+            return None;
+        }
+        let path = if from_root {
+            format!("/{}", slash_path(new_target.strip_prefix(&self.root).ok()?))
+        } else {
+            relative_path(new_source.parent()?, &new_target)
+        };
+        if path == decoded {
+            return None;
+        }
+        Some(if angle {
+            path
+        } else {
+            path.replace(' ', "%20")
+        })
+    }
+}
+
+/// Whether the file at `path` is a Markdown note, whose links are kept up to date.
+pub fn is_note(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown")
+        })
+}
+
+/// The file among `files`, the vault at `root`, that the wiki link target `target` names: a file
+/// with that name, or with that stem preferring notes, compared without case. A target with a
+/// `/` names a vault-relative path the same way. Ties go to the file nearest the root.
+pub fn resolve_wiki_target(root: &Path, files: &[PathBuf], target: &str) -> Option<PathBuf> {
+    let key = target.trim().replace('\\', "/");
+    let key = key.trim_start_matches('/').to_lowercase();
+    if key.is_empty() {
+        return None;
+    }
+    files
+        .iter()
+        .filter_map(|file| {
+            let relative = slash_path(file.strip_prefix(root).ok()?).to_lowercase();
+            let name = if key.contains('/') {
+                relative.as_str()
+            } else {
+                relative.rsplit('/').next()?
+            };
+            let stem = match name.rfind('.') {
+                Some(dot) if !name[dot..].contains('/') => &name[..dot],
+                _ => name,
+            };
+            let rank = if name == key {
+                0
+            } else if stem == key && is_note(file) {
+                1
+            } else if stem == key {
+                2
+            } else {
+                return None;
+            };
+            Some(((rank, file.components().count()), file))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, file)| file.clone())
+}
+
+/// The file a Markdown link destination `target` in the note at `source` points to, `/` starting
+/// from the vault at `root`. External URLs have none.
+pub fn resolve_markdown_target(root: &Path, source: &Path, target: &str) -> Option<PathBuf> {
+    if target.is_empty() || has_scheme(target) {
+        return None;
+    }
+    let decoded = percent_decode(target);
+    Some(match decoded.strip_prefix('/') {
+        Some(from_root) => join(root, Path::new(from_root.trim_start_matches('/'))),
+        None => join(source.parent()?, Path::new(&decoded)),
+    })
+}
+
+/// Why `rewrite_files` stopped, and the notes it had already rewritten by then.
+#[derive(Debug)]
+pub struct RewriteError {
+    pub error: io::Error,
+    pub rewritten: Vec<PathBuf>,
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if !self.rewritten.is_empty() {
+            write!(f, "\n\nThese notes were already updated:")?;
+            for path in &self.rewritten {
+                write!(f, "\n{}", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites the links in the notes at `files` on disk for `relocation`, returning the notes that
+/// changed. Every note is read, rewritten and written to a temporary file beside it before the
+/// first is replaced, so a note that cannot be read or written leaves them all untouched. Only
+/// a failure among the final renames leaves some rewritten, and the error lists them. Blocking,
+/// so run it on a background executor.
+pub fn rewrite_files(
+    relocation: &Relocation,
+    files: &[PathBuf],
+) -> Result<Vec<PathBuf>, RewriteError> {
+    let untouched = |error| RewriteError {
+        error,
+        rewritten: Vec::new(),
+    };
+    let mut staged = Vec::new();
+    for file in files {
+        let (mut text, format) = read_file(file).map_err(untouched)?;
+        let edits = relocation.link_edits(file, &text);
+        if edits.is_empty() {
+            continue;
+        }
+        for (range, replacement) in edits.into_iter().rev() {
+            text.replace_range(range, &replacement);
+        }
+        let contents = format.encode(&text);
+        staged.push((
+            file,
+            stage_file(file, contents.as_bytes()).map_err(untouched)?,
+        ));
+    }
+    let mut rewritten = Vec::new();
+    for (file, staged) in staged {
+        if let Err(error) = staged.commit() {
+            return Err(RewriteError { error, rewritten });
+        }
+        rewritten.push(file.clone());
+    }
+    Ok(rewritten)
+}
+
+/// The lowercased names bare wiki links use for the file at `path`: its file name, and its stem
+/// for a note.
+fn link_names(path: &Path) -> Vec<String> {
+    let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+        return Vec::new();
+    };
+    let name = name.to_lowercase();
+    match name.strip_suffix(".md") {
+        Some(stem) => vec![stem.to_string(), name.clone()],
+        None => vec![name],
+    }
+}
+
+/// `base` joined with `relative`, with `.` and `..` resolved without touching the file system.
+fn join(base: &Path, relative: &Path) -> PathBuf {
+    let mut path = base.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+    path
+}
+
+/// `path` relative to the folder `dir`, both absolute, with `/` separators as links use.
+fn relative_path(dir: &Path, path: &Path) -> String {
+    let dir: Vec<_> = dir.components().collect();
+    let path: Vec<_> = path.components().collect();
+    let common = dir.iter().zip(&path).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); dir.len() - common];
+    parts.extend(
+        path[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn has_scheme(target: &str) -> bool {
+    target.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+            // A Windows drive letter, not a scheme.
+            && scheme.len() > 1
+    })
+}
+
+/// Decodes `%XX` escapes, as written for spaces and other characters in link destinations.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Relocation, resolve_wiki_target};
+
+    fn apply(text: &str, edits: &[(std::ops::Range<usize>, String)]) -> String {
+        let mut text = text.to_string();
+        for (range, replacement) in edits.iter().rev() {
+            text.replace_range(range.clone(), replacement);
+        }
+        text
+    }
+
+    fn vault(files: &[&str]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .map(|file| Path::new("/v").join(file))
+            .collect()
+    }
+
+    #[test]
+    fn renaming_a_note_rewrites_links_to_it_and_keeps_aliases() {
+        let files = vault(&["Plan.md", "index.md", "notes/Other.md"]);
+        let relocation = Relocation::new(
+            Path::new("/v"),
+            Path::new("/v/Idea.md"),
+            Path::new("/v/Plan.md"),
+            &files,
+        );
+        let text = "[[Idea]] [[idea|My idea]] [[Idea#Goals]] [[Idea.md]] [[Other]] \
+                    [x](Idea.md#top) [y](notes/Other.md) `[[Idea]]`";
+        let edits = relocation.link_edits(Path::new("/v/index.md"), text);
+        assert_eq!(
+            apply(text, &edits),
+            "[[Plan]] [[Plan|My idea]] [[Plan#Goals]] [[Plan.md]] [[Other]] \
+             [x](Plan.md#top) [y](notes/Other.md) `[[Idea]]`"
+        );
+
+        let text = "[up](../Idea.md) [[notes/../Idea]]";
+        let edits = relocation.link_edits(Path::new("/v/notes/Other.md"), text);
+        assert_eq!(apply(text, &edits), "[up](../Plan.md) [[notes/../Idea]]");
+    }
+
+    #[test]
+    fn moving_a_folder_rewrites_paths_both_ways() {
+        let files = vault(&[
+            "archive/2024/Log.md",
+            "archive/2024/my pic.png",
+            "index.md",
+            "Todo.md",
+        ]);
+        let relocation = Relocation::new(
+            Path::new("/v"),
+            Path::new("/v/2024"),
+            Path::new("/v/archive/2024"),
+            &files,
+        );
+        let text = "[[2024/Log]] [[Log]] [log](2024/Log.md) ![](<2024/my pic.png>) \
+                    [abs](/2024/Log.md) [web](https://x.y/2024/Log.md)";
+        let edits = relocation.link_edits(Path::new("/v/index.md"), text);
+        assert_eq!(
+            apply(text, &edits),
+            "[[archive/2024/Log]] [[Log]] [log](archive/2024/Log.md) \
+             ![](<archive/2024/my pic.png>) [abs](/archive/2024/Log.md) \
+             [web](https://x.y/2024/Log.md)"
+        );
+
+        // Links from a moved note to notes that stayed put follow it; links within the moved
+        // folder still work as they are.
+        let text = "[todo](../Todo.md) ![pic](my%20pic.png) [[Todo]]";
+        let edits = relocation.link_edits(Path::new("/v/archive/2024/Log.md"), text);
+        assert_eq!(
+            apply(text, &edits),
+            "[todo](../../Todo.md) ![pic](my%20pic.png) [[Todo]]"
+        );
+    }
+
+    #[test]
+    fn ambiguous_names_are_left_alone() {
+        let files = vault(&["a/Note.md", "b/Note.md", "Home.md"]);
+        let relocation = Relocation::new(
+            Path::new("/v"),
+            Path::new("/v/Note.md"),
+            Path::new("/v/a/Note.md"),
+            &files,
+        );
+        let text = "[[Note]] [[Note|n]]";
+        assert!(
+            relocation
+                .link_edits(Path::new("/v/Home.md"), text)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn wiki_targets_resolve_by_name_preferring_notes() {
+        let files = vault(&[
+            "Plan.pdf",
+            "archive/Plan.md",
+            "projects/Plan.md",
+            "Todo.txt",
+            "pics/Chart.png",
+        ]);
+        let resolve = |target| resolve_wiki_target(Path::new("/v"), &files, target);
+        assert_eq!(resolve("plan"), Some(files[1].clone()));
+        assert_eq!(resolve("Plan.pdf"), Some(files[0].clone()));
+        assert_eq!(resolve("projects/Plan"), Some(files[2].clone()));
+        assert_eq!(resolve(" Todo "), Some(files[3].clone()));
+        assert_eq!(resolve("Chart.png"), Some(files[4].clone()));
+        assert_eq!(resolve("pics"), None);
+        assert_eq!(resolve("Missing"), None);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod index;
+pub mod links;
+pub mod ops;
+pub mod tree;
@@ -0,0 +1,404 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Folder inside the vault that trashed entries are moved to. Hidden, so the tree leaves it out.
+pub const TRASH_DIR: &str = ".zeno-trash";
+
+/// Extension given to a new note when its name has none.
+const NOTE_EXTENSION: &str = "md";
+
+/// Creates an empty note called `name` in `dir`, adding the `.md` extension when the name has
+/// none. Never replaces an existing file.
+pub fn create_note(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut path = dir.join(validate_name(name)?);
+    if path.extension().is_none() {
+        path.set_extension(NOTE_EXTENSION);
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    Ok(path)
+}
+
+/// Creates an empty note at `relative`, a `/`-separated path inside `root` such as a wiki link
+/// names, along with any folders on the way to it. Adds the `.md` extension like `create_note`.
+pub fn create_note_at(root: &Path, relative: &str) -> io::Result<PathBuf> {
+    let (folders, name) = relative.rsplit_once('/').unwrap_or(("", relative));
+    let mut dir = root.to_path_buf();
+    for folder in folders
+        .split('/')
+        .filter(|folder| !folder.trim().is_empty())
+    {
+        dir.push(validate_name(folder)?);
+    }
+    fs::create_dir_all(&dir)?;
+    create_note(&dir, name)
+}
+
+/// Creates an empty folder called `name` in `dir`.
+pub fn create_folder(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let path = dir.join(validate_name(name)?);
+    fs::create_dir(&path)?;
+    Ok(path)
+}
+
+/// A name for a new entry in `dir` that is not taken yet: `stem`, then `stem 1`, `stem 2` and so
+/// on, each followed by `.extension` if given.
+pub fn unused_name(dir: &Path, stem: &str, extension: Option<&str>) -> String {
+    (0..)
+        .map(|n| {
+            let stem = match n {
+                0 => stem.to_string(),
+                n => format!("{stem} {n}"),
+            };
+            match extension {
+                Some(extension) => format!("{stem}.{extension}"),
+                None => stem,
+            }
+        })
+        .find(|name| fs::symlink_metadata(dir.join(name)).is_err())
+        .unwrap_or_default()
+}
+
+/// Renames the file or folder at `path` within its folder. A file keeps its extension when
+/// `new_name` has none. Resolves to the new path.
+pub fn rename(path: &Path, new_name: &str) -> io::Result<PathBuf> {
+    let new_name = validate_name(new_name)?;
+    let mut target = path.with_file_name(new_name);
+    if path.is_file()
+        && target.extension().is_none()
+        && let Some(extension) = path.extension()
+    {
+        target.set_extension(extension);
+    }
+    if target == path {
+        return Ok(target);
+    }
+    move_to(path, &target)?;
+    Ok(target)
+}
+
+/// Moves the file or folder at `path` into the folder `dir`, keeping its name. Resolves to the
+/// new path.
+pub fn move_into(path: &Path, dir: &Path) -> io::Result<PathBuf> {
+    if dir.starts_with(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a folder cannot be moved into itself",
+        ));
+    }
+    let Some(name) = path.file_name() else {
+        return Err(io::ErrorKind::InvalidInput.into());
+    };
+    let target = dir.join(name);
+    if target == path {
+        return Ok(target);
+    }
+    move_to(path, &target)?;
+    Ok(target)
+}
+
+/// Renames `path` to `target`, refusing to replace whatever is there already. A target that is
+/// `path` under another spelling, as after changing only the case of a name on a
+/// case-insensitive file system, is not in the way.
+fn move_to(path: &Path, target: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(target).is_ok() && !same_file(path, target) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", display_name(target)),
+        ));
+    }
+    fs::rename(path, target)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Checks that `name` names a single entry in a folder, and trims surrounding whitespace.
+fn validate_name(name: &str) -> io::Result<&str> {
+    let name = name.trim();
+    let mut components = Path::new(name).components();
+    let is_plain = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) && !name.contains(['/', '\\']);
+    if is_plain {
+        Ok(name)
+    } else if name.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the name cannot be empty",
+        ))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("\"{name}\" is not a valid name"),
+        ))
+    }
+}
+
+/// The vault's trash: a hidden folder holding deleted entries until they are restored.
+///
+/// Each entry is moved to `<trash>/<id>/<name>`, with its path relative to the vault written to
+/// `<trash>/<id>.origin` so it can be put back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trash {
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    id: String,
+    /// Where the entry was when it was trashed.
+    pub original: PathBuf,
+    /// Where it is now, inside the trash.
+    pub trashed: PathBuf,
+}
+
+impl TrashEntry {
+    pub fn name(&self) -> String {
+        display_name(&self.original)
+    }
+}
+
+impl Trash {
+    /// The trash of the vault at `root`.
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.root.join(TRASH_DIR)
+    }
+
+    /// Moves the file or folder at `path`, which must be inside the vault, to the trash.
+    pub fn trash(&self, path: &Path) -> io::Result<TrashEntry> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .ok()
+            .filter(|relative| !relative.as_os_str().is_empty() && !relative.starts_with(TRASH_DIR))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not in the vault", path.display()),
+                )
+            })?;
+        let Some(name) = path.file_name() else {
+            return Err(io::ErrorKind::InvalidInput.into());
+        };
+
+        let dir = self.dir();
+        fs::create_dir_all(&dir)?;
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        // Entries trashed within the same millisecond get the next free suffix.
+        let mut n = 0;
+        let id = loop {
+            let id = format!("{millis}-{n}");
+            match fs::create_dir(dir.join(&id)) {
+                Ok(()) => break id,
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(error) => return Err(error),
+            }
+        };
+
+        // Where the entry came from is recorded first, so that nothing is ever in the trash
+        // without a place to be restored to.
+        let origin = dir.join(format!("{id}.origin"));
+        let trashed = dir.join(&id).join(name);
+        let moved = fs::write(&origin, relative.to_string_lossy().as_bytes())
+            .and_then(|()| fs::rename(path, &trashed));
+        if let Err(error) = moved {
+            fs::remove_file(&origin).ok();
+            fs::remove_dir(dir.join(&id)).ok();
+            return Err(error);
+        }
+        Ok(TrashEntry {
+            id,
+            original: path.to_path_buf(),
+            trashed,
+        })
+    }
+
+    /// Everything in the trash, most recently trashed first. Blocking, so run it on a background
+    /// executor.
+    pub fn entries(&self) -> io::Result<Vec<TrashEntry>> {
+        let read = match fs::read_dir(self.dir()) {
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut entries = Vec::new();
+        for file in read {
+            let path = file?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("origin") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let original = self.root.join(fs::read_to_string(&path)?.trim_end());
+            let Some(name) = original.file_name() else {
+                continue;
+            };
+            let trashed = self.dir().join(id).join(name);
+            if fs::symlink_metadata(&trashed).is_err() {
+                continue;
+            }
+            entries.push(TrashEntry {
+                id: id.to_string(),
+                original,
+                trashed,
+            });
+        }
+        // Ids start with the time the entry was trashed.
+        entries.sort_by_cached_key(|entry| {
+            let (millis, n) = entry.id.split_once('-').unwrap_or((&entry.id, ""));
+            (
+                std::cmp::Reverse(millis.parse::<u128>().unwrap_or_default()),
+                std::cmp::Reverse(n.parse::<usize>().unwrap_or_default()),
+            )
+        });
+        Ok(entries)
+    }
+
+    /// Puts `entry` back where it was trashed from, recreating missing folders on the way.
+    /// Refuses if something else has taken its place since.
+    pub fn restore(&self, entry: &TrashEntry) -> io::Result<PathBuf> {
+        if let Some(parent) = entry.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_to(&entry.trashed, &entry.original)?;
+        let dir = self.dir();
+        fs::remove_dir(dir.join(&entry.id)).ok();
+        fs::remove_file(dir.join(format!("{}.origin", entry.id))).ok();
+        Ok(entry.original.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    use super::{
+        Trash, create_folder, create_note, create_note_at, move_into, rename, unused_name,
+    };
+
+    fn vault(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("zeno-ops-test-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&root).ok();
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn creates_notes_and_folders_without_replacing_anything() {
+        let root = vault("create");
+        let note = create_note(&root, " Ideas ").unwrap();
+        assert_eq!(note, root.join("Ideas.md"));
+        assert_eq!(
+            create_note(&root, "Ideas.md").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            create_note(&root, "todo.txt").unwrap(),
+            root.join("todo.txt")
+        );
+
+        let folder = create_folder(&root, "Projects").unwrap();
+        assert!(folder.is_dir());
+        assert_eq!(unused_name(&root, "Ideas", Some("md")), "Ideas 1.md");
+        assert_eq!(unused_name(&root, "Untitled", None), "Untitled");
+
+        for name in ["", "  ", "a/b", "..", "."] {
+            assert_eq!(
+                create_note(&root, name).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{name:?}"
+            );
+        }
+
+        assert_eq!(
+            create_note_at(&root, "Projects/2025/Plan").unwrap(),
+            root.join("Projects/2025/Plan.md")
+        );
+        assert_eq!(
+            create_note_at(&root, "../Escape").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn renames_and_moves_keep_extensions_and_refuse_to_overwrite() {
+        let root = vault("move");
+        let note = create_note(&root, "draft").unwrap();
+        let other = create_note(&root, "final").unwrap();
+        let folder = create_folder(&root, "archive").unwrap();
+
+        let renamed = rename(&note, "published").unwrap();
+        assert_eq!(renamed, root.join("published.md"));
+        assert!(!note.exists() && renamed.exists());
+        assert_eq!(
+            rename(&renamed, "final").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert!(other.exists());
+
+        let moved = move_into(&renamed, &folder).unwrap();
+        assert_eq!(moved, folder.join("published.md"));
+        let nested = create_folder(&folder, "2024").unwrap();
+        assert_eq!(
+            move_into(&folder, &nested).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(move_into(&moved, &folder).unwrap(), moved);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn trashed_entries_can_be_restored() {
+        let root = vault("trash");
+        let folder = create_folder(&root, "notes").unwrap();
+        let note = folder.join("a.md");
+        fs::write(&note, "hello").unwrap();
+        let trash = Trash::new(&root);
+
+        let first = trash.trash(&note).unwrap();
+        assert!(!note.exists());
+        let second = trash.trash(&folder).unwrap();
+        assert!(!folder.exists());
+        assert!(trash.trash(&root).is_err());
+
+        let entries = trash.entries().unwrap();
+        assert_eq!(entries, vec![second.clone(), first.clone()]);
+        assert_eq!(entries[1].name(), "a.md");
+
+        // Restoring recreates the folder the note was in.
+        assert_eq!(trash.restore(&first).unwrap(), note);
+        assert_eq!(fs::read_to_string(&note).unwrap(), "hello");
+        assert_eq!(
+            trash.restore(&second).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(trash.entries().unwrap(), vec![second]);
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+/// Directories left out of every vault on top of hidden entries and anything `.gitignore`d.
+pub(super) const EXCLUDED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// The files and folders of a vault, as shown in the sidebar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTree {
+    pub root: PathBuf,
+    /// Top-level entries: folders first, then files, each sorted by name.
+    pub nodes: Vec<FileNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileNode {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Meaningful for directories only.
+    pub is_expanded: bool,
+    pub children: Vec<FileNode>,
+}
+
+/// A node in the flattened list of visible rows.
+#[derive(Debug, Clone, Copy)]
+pub struct FileRow<'a> {
+    pub node: &'a FileNode,
+    pub depth: usize,
+}
+
+impl FileNode {
+    pub fn name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+    }
+}
+
+impl FileTree {
+    /// Walks `root` recursively, skipping hidden entries, the default exclusions and anything
+    /// ignored by `.gitignore` files, whether or not the vault is a Git repository. Blocking, so
+    /// run it on a background executor.
+    pub fn walk(root: &Path) -> io::Result<Self> {
+        let root = root.canonicalize()?;
+        let walker = WalkBuilder::new(&root)
+            .hidden(true)
+            .git_ignore(true)
+            .require_git(false)
+            .filter_entry(|entry| {
+                let is_dir = entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_dir());
+                !(is_dir
+                    && entry.depth() > 0
+                    && EXCLUDED_DIRS
+                        .iter()
+                        .any(|excluded| entry.file_name() == OsStr::new(excluded)))
+            })
+            .build();
+
+        let mut children_by_parent: HashMap<PathBuf, Vec<FileNode>> = HashMap::new();
+        for entry in walker {
+            // Entries that vanish or cannot be read mid-walk are left out rather than failing it.
+            let Ok(entry) = entry else {
+                continue;
+            };
+            if entry.depth() == 0 {
+                continue;
+            }
+            let Some(parent) = entry.path().parent() else {
+                continue;
+            };
+            children_by_parent
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(FileNode {
+                    path: entry.path().to_path_buf(),
+                    is_dir: entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_dir()),
+                    is_expanded: false,
+                    children: Vec::new(),
+                });
+        }
+
+        let nodes = assemble(&root, &mut children_by_parent);
+        Ok(Self { root, nodes })
+    }
+
+    /// Visible nodes in display order, descending only into expanded directories.
+    pub fn visible_rows(&self) -> Vec<FileRow<'_>> {
+        fn push_rows<'a>(nodes: &'a [FileNode], depth: usize, rows: &mut Vec<FileRow<'a>>) {
+            for node in nodes {
+                rows.push(FileRow { node, depth });
+                if node.is_expanded {
+                    push_rows(&node.children, depth + 1, rows);
+                }
+            }
+        }
+        let mut rows = Vec::new();
+        push_rows(&self.nodes, 0, &mut rows);
+        rows
+    }
+
+    /// Every file in the tree, expanded or not, in display order.
+    pub fn files(&self) -> Vec<PathBuf> {
+        fn push_files(nodes: &[FileNode], files: &mut Vec<PathBuf>) {
+            for node in nodes {
+                if node.is_dir {
+                    push_files(&node.children, files);
+                } else {
+                    files.push(node.path.clone());
+                }
+            }
+        }
+        let mut files = Vec::new();
+        push_files(&self.nodes, &mut files);
+        files
+    }
+
+    pub fn node_mut(&mut self, path: &Path) -> Option<&mut FileNode> {
+        fn find<'a>(nodes: &'a mut [FileNode], path: &Path) -> Option<&'a mut FileNode> {
+            let node = nodes.iter_mut().find(|node| path.starts_with(&node.path))?;
+            if node.path == path {
+                Some(node)
+            } else {
+                find(&mut node.children, path)
+            }
+        }
+        find(&mut self.nodes, path)
+    }
+
+    pub fn toggle_expanded(&mut self, path: &Path) {
+        if let Some(node) = self.node_mut(path).filter(|node| node.is_dir) {
+            node.is_expanded = !node.is_expanded;
+        }
+    }
+
+    /// Expands the directories that were expanded in `previous`, so refreshing the tree keeps
+    /// the user's view of it.
+    pub fn keep_expanded(&mut self, previous: &FileTree) {
+        let mut expanded = Vec::new();
+        for row in previous.visible_rows() {
+            if row.node.is_expanded {
+                expanded.push(row.node.path.clone());
+            }
+        }
+        for path in expanded {
+            if let Some(node) = self.node_mut(&path) {
+                node.is_expanded = true;
+            }
+        }
+    }
+}
+
+fn assemble(dir: &Path, children_by_parent: &mut HashMap<PathBuf, Vec<FileNode>>) -> Vec<FileNode> {
+    let mut nodes = children_by_parent.remove(dir).unwrap_or_default();
+    for node in &mut nodes {
+        if node.is_dir {
+            node.children = assemble(&node.path, children_by_parent);
+        }
+    }
+    nodes.sort_by_cached_key(|node| (!node.is_dir, node.name().to_lowercase()));
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::FileTree;
+
+    fn names(tree: &FileTree) -> Vec<String> {
+        tree.visible_rows()
+            .iter()
+            .map(|row| format!("{}{}", "  ".repeat(row.depth), row.node.name()))
+            .collect()
+    }
+
+    #[test]
+    fn walks_vault_honouring_ignores_and_exclusions() {
+        let root = std::env::temp_dir().join(format!("zeno-tree-test-{}", std::process::id()));
+        for dir in [
+            "notes/daily",
+            ".obsidian",
+            "target/debug",
+            "node_modules/x",
+            "drafts",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "Index.md",
+            "apple.md",
+            "notes/b.md",
+            "notes/daily/2024-01-01.md",
+            ".obsidian/app.json",
+            "target/debug/out",
+            "drafts/secret.md",
+            "scratch.tmp",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "*.tmp\ndrafts/\n").unwrap();
+
+        let mut tree = FileTree::walk(&root).unwrap();
+        assert_eq!(names(&tree), ["notes", "apple.md", "Index.md"]);
+
+        let notes = tree.root.join("notes");
+        assert_eq!(
+            tree.files(),
+            [
+                notes.join("daily/2024-01-01.md"),
+                notes.join("b.md"),
+                tree.root.join("apple.md"),
+                tree.root.join("Index.md"),
+            ]
+        );
+        tree.toggle_expanded(&notes);
+        tree.toggle_expanded(&notes.join("daily"));
+        assert_eq!(
+            names(&tree),
+            [
+                "notes",
+                "  daily",
+                "    2024-01-01.md",
+                "  b.md",
+                "apple.md",
+                "Index.md"
+            ]
+        );
+
+        let mut refreshed = FileTree::walk(&root).unwrap();
+        refreshed.keep_expanded(&tree);
+        assert_eq!(refreshed, tree);
+
+        tree.toggle_expanded(Path::new(&notes));
+        assert_eq!(names(&tree), ["notes", "apple.md", "Index.md"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}